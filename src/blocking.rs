@@ -0,0 +1,102 @@
+//! This module implements a blocking API to send and receive messages, for synchronous programs which do not
+//! run a tokio runtime.
+//!
+//! The backends are connected with a URI as in [`crate::connect()`] and driven by a current-thread runtime,
+//! which is shared between the returned [`Sender`] and [`Receiver`]. Both may be moved to different threads.
+//!
+//! ```no_run
+//! use async_can::{blocking, Message};
+//!
+//! let (mut tx, mut rx) = blocking::connect("socketcan://can0").unwrap();
+//! tx.send(Message::new_data(0x123, false, &[1, 2, 3]).unwrap()).unwrap();
+//! println!("{}", rx.recv().unwrap());
+//! ```
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::runtime::{self, Runtime};
+
+use crate::ext::ReceiverExt;
+use crate::uri::{BoxReceiver, BoxSender};
+use crate::{Message, Result};
+
+/// Sends messages, blocking the current thread until the message was sent.
+pub struct Sender {
+    inner: BoxSender,
+    runtime: Arc<Runtime>,
+}
+
+/// Receives messages, blocking the current thread until a message was received.
+pub struct Receiver {
+    inner: BoxReceiver,
+    runtime: Arc<Runtime>,
+}
+
+/// Connect to the backend given by `uri`, refer to the [`crate::uri`] module for the supported schemes.
+///
+/// Must not be called from within an asynchronous context, as it blocks the current thread.
+pub fn connect(uri: &str) -> Result<(Sender, Receiver)> {
+    let runtime = Arc::new(
+        runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?,
+    );
+    let (sender, receiver) = runtime.block_on(crate::connect(uri))?;
+    let sender = Sender {
+        inner: sender,
+        runtime: runtime.clone(),
+    };
+    let receiver = Receiver {
+        inner: receiver,
+        runtime,
+    };
+    Ok((sender, receiver))
+}
+
+impl Sender {
+    pub fn send(&mut self, msg: Message) -> Result<()> {
+        self.runtime.block_on(self.inner.send(msg))
+    }
+
+    /// Refer to [`crate::Sender::shutdown()`].
+    pub fn shutdown(&mut self) -> Result<()> {
+        self.runtime.block_on(self.inner.shutdown())
+    }
+}
+
+impl Receiver {
+    pub fn recv(&mut self) -> Result<Message> {
+        self.runtime.block_on(self.inner.recv())
+    }
+
+    /// Receive the next message, waiting for at most `timeout`. Returns `Ok(None)` if no message was received
+    /// in time.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Message>> {
+        self.runtime.block_on(self.inner.recv_timeout(timeout))
+    }
+
+    /// Refer to [`crate::Receiver::shutdown()`].
+    pub fn shutdown(&mut self) -> Result<()> {
+        self.runtime.block_on(self.inner.shutdown())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn loopback() {
+        let (mut tx, mut rx) = connect("loopback://").unwrap();
+        let msg = Message::new_data(0x123, false, &[1, 2]).unwrap();
+        tx.send(msg.clone()).unwrap();
+        assert_eq!(rx.recv().unwrap(), msg);
+        assert_eq!(rx.recv_timeout(Duration::from_millis(10)).unwrap(), None);
+
+        let received = thread::spawn(move || rx.recv());
+        tx.send(msg.clone()).unwrap();
+        assert_eq!(received.join().unwrap().unwrap(), msg);
+    }
+}
@@ -0,0 +1,96 @@
+//! This module implements forwarding messages from one bus to another, for example to act as a gateway between
+//! a test bench and a vehicle.
+
+use crate::filter::{Filter, FilteredReceiver};
+use crate::{Error, Receiver, Sender};
+
+/// Forward all messages received from `rx` to `tx`, optionally only those passing `filter`.
+///
+/// Runs until either side fails and returns the error.
+pub async fn bridge<R, S>(rx: R, mut tx: S, filter: Option<Filter>) -> Error
+where
+    R: Receiver + Send,
+    S: Sender + Send,
+{
+    let filters = filter.unwrap_or_default().compile();
+    let mut rx = FilteredReceiver::new(rx, filters);
+    loop {
+        let result = match rx.recv().await {
+            Ok(msg) => tx.send(msg).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            return err;
+        }
+    }
+}
+
+/// Forward messages in both directions between the buses `a` and `b`, each given as a sender and receiver.
+///
+/// Both directions are forwarded on a separate task. Once either direction fails, the other one is stopped
+/// and the error is returned.
+pub async fn bridge_duplex<S1, R1, S2, R2>(a: (S1, R1), b: (S2, R2)) -> Error
+where
+    S1: Sender + Send + 'static,
+    R1: Receiver + Send + 'static,
+    S2: Sender + Send + 'static,
+    R2: Receiver + Send + 'static,
+{
+    let (tx_a, rx_a) = a;
+    let (tx_b, rx_b) = b;
+    let mut a_to_b = tokio::spawn(bridge(rx_a, tx_b, None));
+    let mut b_to_a = tokio::spawn(bridge(rx_b, tx_a, None));
+    let result = tokio::select! {
+        x = &mut a_to_b => x,
+        x = &mut b_to_a => x,
+    };
+    a_to_b.abort();
+    b_to_a.abort();
+    result.unwrap_or_else(|err| Error::Other(format!("Bridge task failed: {}", err)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{loopback, Message};
+
+    #[tokio::test]
+    async fn filtered() {
+        let (mut tx_in, rx_in) = loopback::connect();
+        let (tx_out, mut rx_out) = loopback::connect();
+        let task = tokio::spawn(bridge(
+            rx_in,
+            tx_out,
+            Some(Filter::new().id_range(0x100..=0x1FF)),
+        ));
+        for id in [0x0FF, 0x100, 0x200, 0x1FF] {
+            tx_in
+                .send(Message::new_data(id, false, &[]).unwrap())
+                .await
+                .unwrap();
+        }
+        assert_eq!(rx_out.recv().await.unwrap().id(), 0x100);
+        assert_eq!(rx_out.recv().await.unwrap().id(), 0x1FF);
+        drop(tx_in);
+        assert!(task.await.unwrap().is_disconnected());
+    }
+
+    #[tokio::test]
+    async fn duplex() {
+        let (mut tx_a, rx_a) = loopback::connect();
+        let (tx_a_out, mut rx_a_out) = loopback::connect();
+        let (mut tx_b, rx_b) = loopback::connect();
+        let (tx_b_out, mut rx_b_out) = loopback::connect();
+        let task = tokio::spawn(bridge_duplex((tx_a_out, rx_a), (tx_b_out, rx_b)));
+
+        let msg = Message::new_data(0x123, false, &[1]).unwrap();
+        tx_a.send(msg.clone()).await.unwrap();
+        assert_eq!(rx_b_out.recv().await.unwrap(), msg);
+        let msg = Message::new_data(0x456, false, &[2]).unwrap();
+        tx_b.send(msg.clone()).await.unwrap();
+        assert_eq!(rx_a_out.recv().await.unwrap(), msg);
+
+        drop(tx_a);
+        assert!(task.await.unwrap().is_disconnected());
+    }
+}
@@ -0,0 +1,218 @@
+//! This module implements estimating the utilization of a CAN bus from the messages observed on it.
+//!
+//! The number of bits of each frame is computed from the length of the ID and the data field, including
+//! the worst-case number of stuff bits and the interframe space. CAN-FD frames are counted as if the whole
+//! frame was transmitted with the nominal bitrate, which overestimates the load if bitrate switching is used.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+
+use crate::{Message, Receiver, Result, Timestamp};
+
+/// Number of bits of a frame which are subject to bit stuffing, excluding the data field.
+/// That is SOF, arbitration field, control field and CRC.
+fn stuffed_bits(msg: &Message) -> usize {
+    match msg {
+        Message::Fd(x) => {
+            // CAN-FD uses CRC-17 for up to 16 data bytes and CRC-21 for longer payloads
+            let crc = if x.data().len() > 16 { 21 } else { 17 };
+            // the control field consists of FDF, res, BRS, ESI and DLC, preceded by IDE for standard IDs
+            if msg.ext_id() {
+                1 + 32 + 8 + crc
+            } else {
+                1 + 12 + 9 + crc
+            }
+        }
+        _ if msg.ext_id() => 1 + 32 + 6 + 15,
+        _ => 1 + 12 + 6 + 15,
+    }
+}
+
+/// Returns the number of bits required to transmit `msg`, including worst-case bit stuffing, the
+/// CRC delimiter, the ACK slot, the end of frame and the interframe space.
+pub fn frame_bits(msg: &Message) -> usize {
+    let data_bits = match msg {
+        Message::Data(x) => 8 * x.data().len(),
+        Message::Remote(_) => 0,
+        Message::Fd(x) => 8 * x.data().len(),
+    };
+    let stuffed = stuffed_bits(msg) + data_bits;
+    // CRC delimiter, ACK slot, ACK delimiter, EOF and interframe space
+    let trailer = 1 + 2 + 7 + 3;
+    stuffed + (stuffed - 1) / 4 + trailer
+}
+
+/// Estimates the bus load over a sliding window of the messages fed to it.
+///
+/// The load and frame rate are computed relative to the timestamp of the most recent message, or the time
+/// passed to [`BusLoad::advance()`] if it is later.
+#[derive(Debug, Clone)]
+pub struct BusLoad {
+    bitrate: u32,
+    window: Duration,
+    frames: VecDeque<(Timestamp, usize)>,
+    bits: usize,
+}
+
+impl BusLoad {
+    /// Create a new estimator for a bus with the given nominal `bitrate` averaging over `window`.
+    ///
+    /// Panics if `bitrate` or `window` is zero.
+    pub fn new(bitrate: u32, window: Duration) -> Self {
+        assert!(bitrate > 0, "The bitrate must not be zero");
+        assert!(!window.is_zero(), "The window must not be zero");
+        Self {
+            bitrate,
+            window,
+            frames: VecDeque::new(),
+            bits: 0,
+        }
+    }
+
+    /// Account for a message observed on the bus at the given time.
+    pub fn feed(&mut self, msg: &Message, timestamp: Timestamp) {
+        let bits = frame_bits(msg);
+        self.bits += bits;
+        self.frames.push_back((timestamp.clone(), bits));
+        self.advance(timestamp);
+    }
+
+    /// Discard the messages which are no longer within the window at the given time, such that the load
+    /// decays if no further messages are observed.
+    pub fn advance(&mut self, now: Timestamp) {
+        let window = self.window.as_micros() as u64;
+        while let Some((front, bits)) = self.frames.front() {
+            if front.micros + window > now.micros {
+                break;
+            }
+            self.bits -= bits;
+            self.frames.pop_front();
+        }
+    }
+
+    /// Returns the estimated utilization of the bus in percent.
+    pub fn load_percent(&self) -> f64 {
+        let capacity = self.bitrate as f64 * self.window.as_secs_f64();
+        100.0 * self.bits as f64 / capacity
+    }
+
+    /// Returns the number of frames per second observed within the window.
+    pub fn frames_per_second(&self) -> f64 {
+        self.frames.len() as f64 / self.window.as_secs_f64()
+    }
+}
+
+/// Wraps a [`crate::Receiver`] and feeds all received messages into a [`BusLoad`].
+///
+/// The messages are timestamped when they are received.
+pub struct BusLoadReceiver<R> {
+    inner: R,
+    load: BusLoad,
+    start: Instant,
+}
+
+impl<R> BusLoadReceiver<R> {
+    pub fn new(inner: R, load: BusLoad) -> Self {
+        Self {
+            inner,
+            load,
+            start: Instant::now(),
+        }
+    }
+
+    /// Returns the bus load estimated from the messages received within the window up to now.
+    pub fn bus_load(&mut self) -> &BusLoad {
+        self.load
+            .advance(Timestamp::from_duration(self.start.elapsed()));
+        &self.load
+    }
+
+    /// Return the wrapped receiver.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<R: Receiver + Send> Receiver for BusLoadReceiver<R> {
+    async fn recv(&mut self) -> Result<Message> {
+        let msg = self.inner.recv().await?;
+        let timestamp = Timestamp::from_duration(self.start.elapsed());
+        self.load.feed(&msg, timestamp);
+        Ok(msg)
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        self.inner.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bits_per_frame() {
+        // 47 bits without stuffing for a standard frame without data
+        let msg = Message::new_data(0x123, false, &[]).unwrap();
+        assert_eq!(frame_bits(&msg), 34 + 8 + 13);
+        let msg = Message::new_data(0x123, false, &[0; 8]).unwrap();
+        assert_eq!(frame_bits(&msg), 98 + 24 + 13);
+        let msg = Message::new_remote(0x123, true, 8).unwrap();
+        assert_eq!(frame_bits(&msg), 54 + 13 + 13);
+    }
+
+    #[test]
+    fn bits_per_fd_frame() {
+        // CRC-17 up to 16 data bytes
+        let msg = Message::new_fd(0x123, true, false, &[0; 16]).unwrap();
+        assert_eq!(frame_bits(&msg), 186 + 46 + 13);
+        let msg = Message::new_fd(0x123, false, false, &[0; 16]).unwrap();
+        assert_eq!(frame_bits(&msg), 167 + 41 + 13);
+        // CRC-21 for longer payloads, regardless of the ID type
+        let msg = Message::new_fd(0x123, false, true, &[0; 64]).unwrap();
+        assert_eq!(frame_bits(&msg), 555 + 138 + 13);
+        let msg = Message::new_fd(0x123, true, true, &[0; 64]).unwrap();
+        assert_eq!(frame_bits(&msg), 574 + 143 + 13);
+    }
+
+    #[test]
+    fn sliding_window() {
+        let mut load = BusLoad::new(500_000, Duration::from_secs(1));
+        let msg = Message::new_data(0x123, false, &[0; 8]).unwrap();
+        for k in 0..1000 {
+            load.feed(&msg, Timestamp::from_micros(k * 1000));
+        }
+        assert_eq!(load.frames_per_second(), 1000.0);
+        assert!((load.load_percent() - 27.0).abs() < 1e-9);
+
+        // frames older than the window are discarded
+        load.feed(&msg, Timestamp::from_micros(1_999_000));
+        assert_eq!(load.frames_per_second(), 1.0);
+
+        // the load decays once the bus is quiet
+        load.advance(Timestamp::from_micros(2_999_000));
+        assert_eq!(load.frames_per_second(), 0.0);
+        assert_eq!(load.load_percent(), 0.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_window() {
+        BusLoad::new(500_000, Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn wrap_receiver() {
+        use crate::{loopback, Sender};
+
+        let (mut tx, rx) = loopback::connect();
+        let mut rx = BusLoadReceiver::new(rx, BusLoad::new(125_000, Duration::from_secs(10)));
+        let msg = Message::new_data(0x123, false, &[]).unwrap();
+        tx.send(msg.clone()).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap(), msg);
+        assert_eq!(rx.bus_load().frames_per_second(), 0.1);
+    }
+}
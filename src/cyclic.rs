@@ -0,0 +1,117 @@
+//! This module implements transmitting a message periodically, as expected by many ECUs for keep-alive or
+//! status frames.
+
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+use crate::{Error, Message, Result, Sender};
+
+/// Handle to a task spawned with [`spawn_cyclic()`].
+///
+/// Dropping the handle stops the task as well.
+pub struct CyclicHandle<S> {
+    msg: watch::Sender<Message>,
+    task: JoinHandle<Result<S>>,
+}
+
+/// Spawn a task which sends `msg` with `sender` every `period`, starting immediately.
+///
+/// If a tick is missed because sending took too long, the following ticks are delayed rather than sent in
+/// a burst. Errors are logged and the task continues, except if the sender is disconnected, which stops
+/// the task.
+pub fn spawn_cyclic<S>(mut sender: S, msg: Message, period: Duration) -> CyclicHandle<S>
+where
+    S: Sender + Send + 'static,
+{
+    let (msg_tx, mut msg_rx) = watch::channel(msg);
+    let task = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                biased;
+                _ = interval.tick() => {
+                    let msg = msg_rx.borrow().clone();
+                    match sender.send(msg).await {
+                        Ok(()) => {}
+                        Err(err) if err.is_disconnected() => return Err(err),
+                        Err(err) => log::warn!("Sending cyclic message failed: {}", err),
+                    }
+                }
+                changed = msg_rx.changed() => {
+                    // the handle was dropped or stopped
+                    if changed.is_err() {
+                        return Ok(sender);
+                    }
+                }
+            }
+        }
+    });
+    CyclicHandle { msg: msg_tx, task }
+}
+
+impl<S> CyclicHandle<S> {
+    /// Replace the message, which is sent from the next tick onwards.
+    pub fn update(&self, msg: Message) {
+        // fails only if the task has stopped
+        let _ = self.msg.send(msg);
+    }
+
+    /// Returns false if the task stopped because the sender was disconnected.
+    pub fn is_running(&self) -> bool {
+        !self.task.is_finished()
+    }
+
+    /// Stop sending and return the sender, or the error which stopped the task.
+    pub async fn stop(self) -> Result<S> {
+        drop(self.msg);
+        self.task
+            .await
+            .map_err(|err| Error::Other(format!("Cyclic task failed: {}", err)))?
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ext::ReceiverExt;
+    use crate::{loopback, Receiver};
+
+    #[tokio::test(start_paused = true)]
+    async fn send_periodically() {
+        let (tx, mut rx) = loopback::connect();
+        let msg = Message::new_data(0x100, false, &[1]).unwrap();
+        let handle = spawn_cyclic(tx, msg.clone(), Duration::from_millis(100));
+
+        let start = tokio::time::Instant::now();
+        assert_eq!(rx.recv().await.unwrap(), msg);
+        assert_eq!(rx.recv().await.unwrap(), msg);
+        assert_eq!(start.elapsed(), Duration::from_millis(100));
+
+        let updated = Message::new_data(0x100, false, &[2]).unwrap();
+        handle.update(updated.clone());
+        assert_eq!(rx.recv().await.unwrap(), updated);
+        assert_eq!(start.elapsed(), Duration::from_millis(200));
+
+        assert!(handle.is_running());
+        let _tx = handle.stop().await.unwrap();
+        let timeout = Duration::from_millis(500);
+        assert!(rx.recv_timeout(timeout).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn stop_on_disconnect() {
+        let (tx, rx) = loopback::connect();
+        drop(rx);
+        let msg = Message::new_data(0x100, false, &[]).unwrap();
+        let handle = spawn_cyclic(tx, msg, Duration::from_millis(1));
+        while handle.is_running() {
+            tokio::task::yield_now().await;
+        }
+        let err = handle.stop().await.err().unwrap();
+        assert!(err.is_disconnected());
+    }
+}
@@ -0,0 +1,355 @@
+//! This module implements loading a DBC file to decode the signals of received messages into physical values.
+//!
+//! Only message definitions (`BO_`) and their signals (`SG_`) are evaluated, all other sections of the file,
+//! such as value tables, attributes or comments, are ignored. Simple multiplexing, where a single multiplexor
+//! signal selects the multiplexed signals, is supported. Extended multiplexing is not.
+//!
+//! ```no_run
+//! # tokio_test::block_on(async {
+//! use async_can::dbc::Dbc;
+//! use async_can::Message;
+//!
+//! let dbc = Dbc::load("vehicle.dbc").await.unwrap();
+//! let msg = Message::new_data(0x123, false, &[0x10, 0x27]).unwrap();
+//! if let Some(values) = dbc.decode(&msg) {
+//!     println!("{:?}", values);
+//! }
+//! # })
+//! ```
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::signal::{extract_signed, extract_unsigned, fits};
+use crate::{CanId, Error, Message, Result, CANFD_MAX_DLC};
+
+/// Flag marking an extended ID in the message ID of a DBC file.
+const DBC_EXT_ID_FLAG: u32 = 0x8000_0000;
+
+/// Whether a signal is part of a multiplexed message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Multiplexing {
+    /// The signal is always present.
+    None,
+    /// The signal selects which multiplexed signals are present, `M` in the DBC file.
+    Multiplexor,
+    /// The signal is present if the multiplexor has the given value, e.g. `m3` in the DBC file.
+    Multiplexed(u64),
+}
+
+/// The definition of a signal within a [`MessageDef`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignalDef {
+    pub name: String,
+    /// Position of the least significant bit for little endian signals, or the most significant bit for big
+    /// endian signals, refer to [`crate::signal`].
+    pub start_bit: usize,
+    pub len: usize,
+    pub little_endian: bool,
+    pub signed: bool,
+    pub factor: f64,
+    pub offset: f64,
+    pub unit: String,
+    pub multiplexing: Multiplexing,
+}
+
+impl SignalDef {
+    /// Extract the raw value of the signal. Returns `None` if the signal does not fit into `data`.
+    pub fn raw(&self, data: &[u8]) -> Option<u64> {
+        if !fits(data.len(), self.start_bit, self.len, self.little_endian) {
+            return None;
+        }
+        Some(extract_unsigned(
+            data,
+            self.start_bit,
+            self.len,
+            self.little_endian,
+        ))
+    }
+
+    /// Extract the physical value of the signal, i.e. `raw * factor + offset`. Returns `None` if the signal does
+    /// not fit into `data`.
+    pub fn decode(&self, data: &[u8]) -> Option<f64> {
+        if !fits(data.len(), self.start_bit, self.len, self.little_endian) {
+            return None;
+        }
+        let raw = if self.signed {
+            extract_signed(data, self.start_bit, self.len, self.little_endian) as f64
+        } else {
+            extract_unsigned(data, self.start_bit, self.len, self.little_endian) as f64
+        };
+        Some(raw * self.factor + self.offset)
+    }
+}
+
+/// The definition of a message in a [`Dbc`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageDef {
+    pub id: CanId,
+    pub name: String,
+    pub dlc: u8,
+    pub signals: Vec<SignalDef>,
+}
+
+impl MessageDef {
+    /// Decode all signals present in `data` into physical values by signal name.
+    ///
+    /// Signals which do not fit into `data`, or multiplexed signals not selected by the multiplexor, are omitted.
+    pub fn decode(&self, data: &[u8]) -> HashMap<String, f64> {
+        let mux = self
+            .signals
+            .iter()
+            .find(|x| x.multiplexing == Multiplexing::Multiplexor)
+            .and_then(|x| x.raw(data));
+        self.signals
+            .iter()
+            .filter(|x| match x.multiplexing {
+                Multiplexing::Multiplexed(value) => mux == Some(value),
+                _ => true,
+            })
+            .filter_map(|x| Some((x.name.clone(), x.decode(data)?)))
+            .collect()
+    }
+}
+
+/// The message definitions of a DBC file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Dbc {
+    messages: Vec<MessageDef>,
+}
+
+impl Dbc {
+    /// Load and parse the DBC file at `path`.
+    pub async fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        tokio::fs::read_to_string(path).await?.parse()
+    }
+
+    pub fn messages(&self) -> &[MessageDef] {
+        &self.messages
+    }
+
+    /// Returns the definition of the message with the given ID.
+    pub fn message(&self, id: CanId) -> Option<&MessageDef> {
+        self.messages.iter().find(|x| x.id == id)
+    }
+
+    /// Returns the definition of the message with the given name.
+    pub fn message_by_name(&self, name: &str) -> Option<&MessageDef> {
+        self.messages.iter().find(|x| x.name == name)
+    }
+
+    /// Decode the signals of `msg` into physical values by signal name, refer to [`MessageDef::decode()`].
+    ///
+    /// Returns `None` for remote frames and messages which are not defined.
+    pub fn decode(&self, msg: &Message) -> Option<HashMap<String, f64>> {
+        let data = match msg {
+            Message::Data(frame) => frame.data(),
+            Message::Remote(_) => return None,
+            Message::Fd(frame) => frame.data(),
+        };
+        Some(self.message(msg.can_id())?.decode(data))
+    }
+}
+
+/// Parse `BO_ <id> <name>: <dlc> <transmitter>`.
+///
+/// Returns `Ok(None)` for messages with an invalid ID, such as the pseudo-message `VECTOR__INDEPENDENT_SIG_MSG`.
+fn parse_message(line: &str) -> Option<Option<MessageDef>> {
+    let mut words = line.split_whitespace().skip(1);
+    let id: u32 = words.next()?.parse().ok()?;
+    let name = words.next()?.strip_suffix(':')?;
+    let dlc = words.next()?.parse().ok()?;
+    let ext_id = id & DBC_EXT_ID_FLAG != 0;
+    Some(
+        CanId::new(id & !DBC_EXT_ID_FLAG, ext_id)
+            .ok()
+            .map(|id| MessageDef {
+                id,
+                name: name.to_string(),
+                dlc,
+                signals: Vec::new(),
+            }),
+    )
+}
+
+/// Parse `SG_ <name> [M|m<value>] : <start>|<len>@<endianness><sign> (<factor>,<offset>) [<min>|<max>] "<unit>" ...`.
+fn parse_signal(line: &str) -> Option<SignalDef> {
+    let (head, tail) = line.split_once(':')?;
+    let mut head = head.split_whitespace().skip(1);
+    let name = head.next()?;
+    let multiplexing = match head.next() {
+        None => Multiplexing::None,
+        Some("M") => Multiplexing::Multiplexor,
+        Some(x) => {
+            let value = x.strip_prefix('m')?;
+            // extended multiplexing, where the signal is a multiplexor as well
+            let value = value.strip_suffix('M').unwrap_or(value);
+            Multiplexing::Multiplexed(value.parse().ok()?)
+        }
+    };
+    let (layout, tail) = tail.trim_start().split_once(char::is_whitespace)?;
+    let (start_bit, layout) = layout.split_once('|')?;
+    let (len, layout) = layout.split_once('@')?;
+    let (little_endian, signed) = match layout {
+        "1+" => (true, false),
+        "1-" => (true, true),
+        "0+" => (false, false),
+        "0-" => (false, true),
+        _ => return None,
+    };
+    let (scaling, tail) = tail.trim_start().strip_prefix('(')?.split_once(')')?;
+    let (factor, offset) = scaling.split_once(',')?;
+    let (_, tail) = tail.split_once('"')?;
+    let (unit, _) = tail.split_once('"')?;
+    let len = len.parse().ok()?;
+    if !(1..=64).contains(&len) {
+        return None;
+    }
+    // the signal must start within the largest CAN-FD frame
+    let start_bit = start_bit.parse().ok()?;
+    if start_bit >= 8 * CANFD_MAX_DLC {
+        return None;
+    }
+    Some(SignalDef {
+        name: name.to_string(),
+        start_bit,
+        len,
+        little_endian,
+        signed,
+        factor: factor.trim().parse().ok()?,
+        offset: offset.trim().parse().ok()?,
+        unit: unit.to_string(),
+        multiplexing,
+    })
+}
+
+impl FromStr for Dbc {
+    type Err = Error;
+
+    /// Parse the content of a DBC file.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut messages = Vec::new();
+        // the message the following signals belong to, `None` if they are skipped
+        let mut current: Option<MessageDef> = None;
+        for (k, line) in s.lines().enumerate() {
+            let line = line.trim();
+            let invalid =
+                |what| Error::Other(format!("Line {}: Invalid {} definition", k + 1, what));
+            if line.starts_with("SG_ ") {
+                let signal = parse_signal(line).ok_or_else(|| invalid("signal"))?;
+                if let Some(msg) = &mut current {
+                    msg.signals.push(signal);
+                }
+                continue;
+            }
+            if line.is_empty() {
+                continue;
+            }
+            messages.extend(current.take());
+            if line.starts_with("BO_ ") {
+                current = parse_message(line).ok_or_else(|| invalid("message"))?;
+            }
+        }
+        messages.extend(current);
+        Ok(Self { messages })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DBC: &str = r#"VERSION ""
+
+NS_ :
+    CM_
+
+BS_:
+
+BU_: ECU Tester
+
+BO_ 291 Engine: 8 ECU
+ SG_ Speed : 0|16@1+ (0.1,0) [0|6553.5] "km/h" Tester
+ SG_ Temperature : 23|8@0- (1,-40) [-40|215] "degC" Tester
+
+BO_ 2566844926 Mux: 8 ECU
+ SG_ Selector M : 0|8@1+ (1,0) [0|255] "" Tester
+ SG_ Voltage m1 : 8|16@1+ (0.001,0) [0|65.535] "V" Tester
+ SG_ Current m2 : 8|16@1- (0.01,0) [-327.68|327.67] "A" Tester
+
+BO_ 3221225472 VECTOR__INDEPENDENT_SIG_MSG: 0 Vector__XXX
+ SG_ Unused : 0|8@1+ (1,0) [0|0] "" Vector__XXX
+
+CM_ SG_ 291 Speed "Vehicle speed";
+"#;
+
+    #[test]
+    fn parse() {
+        let dbc: Dbc = DBC.parse().unwrap();
+        assert_eq!(dbc.messages().len(), 2);
+        let msg = dbc.message_by_name("Engine").unwrap();
+        assert_eq!(msg.id, CanId::standard(0x123).unwrap());
+        assert_eq!(msg.dlc, 8);
+        assert_eq!(
+            msg.signals[1],
+            SignalDef {
+                name: "Temperature".to_string(),
+                start_bit: 23,
+                len: 8,
+                little_endian: false,
+                signed: true,
+                factor: 1.0,
+                offset: -40.0,
+                unit: "degC".to_string(),
+                multiplexing: Multiplexing::None,
+            }
+        );
+        let msg = dbc.message(CanId::extended(0x18FEF1FE).unwrap()).unwrap();
+        assert_eq!(msg.signals[0].multiplexing, Multiplexing::Multiplexor);
+        assert_eq!(msg.signals[2].multiplexing, Multiplexing::Multiplexed(2));
+
+        let err = "BO_ 1 Foo: 8 ECU\n SG_ Bar : 0|8@2+ (1,0) [0|0] \"\" ECU"
+            .parse::<Dbc>()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Other Error: Line 2: Invalid signal definition"
+        );
+        assert!("BO_ 1 Foo: 8 ECU\n SG_ Bar : 511|1@1+ (1,0) [0|0] \"\" ECU"
+            .parse::<Dbc>()
+            .is_ok());
+        assert!(
+            "BO_ 1 Foo: 8 ECU\n SG_ Bar : 18446744073709551615|8@1+ (1,0) [0|0] \"\" ECU"
+                .parse::<Dbc>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn decode() {
+        let dbc: Dbc = DBC.parse().unwrap();
+        let msg = Message::new_data(0x123, false, &[0x10, 0x27, 0xFF]).unwrap();
+        let values = dbc.decode(&msg).unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values["Speed"], 1000.0);
+        assert_eq!(values["Temperature"], -41.0);
+
+        // the signal does not fit into the data
+        let msg = Message::new_data(0x123, false, &[0x10, 0x27]).unwrap();
+        assert_eq!(dbc.decode(&msg).unwrap().len(), 1);
+
+        let msg = Message::new_data(0x18FEF1FE, true, &[2, 0x9C, 0xFF]).unwrap();
+        let values = dbc.decode(&msg).unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values["Selector"], 2.0);
+        assert_eq!(values["Current"], -1.0);
+
+        assert!(dbc
+            .decode(&Message::new_remote(0x123, false, 8).unwrap())
+            .is_none());
+        assert!(dbc
+            .decode(&Message::new_data(0x124, false, &[]).unwrap())
+            .is_none());
+    }
+}
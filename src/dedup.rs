@@ -0,0 +1,110 @@
+//! This module implements suppressing repeated identical messages, such that only changes are recorded, similar
+//! to the change filter of `cansniffer`.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::time::Instant;
+
+use crate::{CanId, Message, Receiver, Result};
+
+/// Wraps a [`crate::Receiver`] and discards messages which are identical to the last message passed with the
+/// same ID, unless that message was passed more than `window` ago.
+///
+/// Messages whose data changed are always passed. Thus, a node sending the same message cyclically only
+/// shows up once per `window`, while each change is passed immediately. With [`Duration::MAX`] as `window`,
+/// only changes are passed.
+pub struct Dedup<R> {
+    inner: R,
+    window: Duration,
+    last: HashMap<CanId, (Message, Instant)>,
+    suppressed: u64,
+}
+
+impl<R> Dedup<R> {
+    pub fn new(inner: R, window: Duration) -> Self {
+        Self {
+            inner,
+            window,
+            last: HashMap::new(),
+            suppressed: 0,
+        }
+    }
+
+    /// Returns the number of messages discarded so far.
+    pub fn suppressed_count(&self) -> u64 {
+        self.suppressed
+    }
+
+    /// Forget the messages seen so far, such that the next message of each ID is passed.
+    pub fn reset(&mut self) {
+        self.last.clear();
+    }
+
+    /// Return the wrapped receiver.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Returns true if `msg` should be passed and records it as last message of its ID in that case.
+    fn pass(&mut self, msg: &Message) -> bool {
+        let now = Instant::now();
+        if let Some((last, passed_at)) = self.last.get(&msg.can_id()) {
+            if last == msg && now.saturating_duration_since(*passed_at) < self.window {
+                self.suppressed += 1;
+                return false;
+            }
+        }
+        self.last.insert(msg.can_id(), (msg.clone(), now));
+        true
+    }
+}
+
+#[async_trait]
+impl<R: Receiver + Send> Receiver for Dedup<R> {
+    async fn recv(&mut self) -> Result<Message> {
+        loop {
+            let msg = self.inner.recv().await?;
+            if self.pass(&msg) {
+                return Ok(msg);
+            }
+        }
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        self.inner.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{loopback, Sender};
+
+    #[tokio::test(start_paused = true)]
+    async fn suppress_repeated() {
+        let (mut tx, rx) = loopback::connect();
+        let mut rx = Dedup::new(rx, Duration::from_secs(1));
+        let a = Message::new_data(0x100, false, &[1]).unwrap();
+        let b = Message::new_data(0x100, false, &[2]).unwrap();
+        let c = Message::new_data(0x200, false, &[1]).unwrap();
+        for msg in [&a, &a, &c, &a, &b, &b] {
+            tx.send(msg.clone()).await.unwrap();
+        }
+        assert_eq!(rx.recv().await.unwrap(), a);
+        assert_eq!(rx.recv().await.unwrap(), c);
+        assert_eq!(rx.recv().await.unwrap(), b);
+
+        tokio::time::advance(Duration::from_millis(1500)).await;
+        tx.send(b.clone()).await.unwrap();
+        tx.send(c.clone()).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap(), b);
+        assert_eq!(rx.recv().await.unwrap(), c);
+        assert_eq!(rx.suppressed_count(), 3);
+
+        rx.reset();
+        tx.send(c.clone()).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap(), c);
+    }
+}
@@ -0,0 +1,151 @@
+//! This module implements [`Device`], which wraps the sender and receiver of any compiled backend in a single
+//! type with static dispatch.
+
+use async_trait::async_trait;
+
+use crate::{loopback, Message, Result, Timestamp};
+
+/// A connection to a CAN bus using one of the compiled backends.
+///
+/// Forwards sending and receiving to the backend. Backend-specific functionality is available by matching
+/// on the variant.
+pub enum Device {
+    #[cfg(feature = "pcan")]
+    Pcan(crate::pcan::Sender, crate::pcan::Receiver),
+    #[cfg(all(target_os = "linux", feature = "socket_can"))]
+    SocketCan(crate::socketcan::CanSocket),
+    #[cfg(feature = "usr_canet")]
+    UsrCanet(crate::usr_canet::Sender, crate::usr_canet::Receiver),
+    Loopback(loopback::Sender, loopback::Receiver),
+}
+
+impl Device {
+    pub async fn send(&mut self, msg: Message) -> Result<()> {
+        match self {
+            #[cfg(feature = "pcan")]
+            Device::Pcan(tx, _) => tx.send(msg).await,
+            #[cfg(all(target_os = "linux", feature = "socket_can"))]
+            Device::SocketCan(socket) => Ok(socket.send(msg).await?),
+            #[cfg(feature = "usr_canet")]
+            Device::UsrCanet(tx, _) => crate::Sender::send(tx, msg).await,
+            Device::Loopback(tx, _) => crate::Sender::send(tx, msg).await,
+        }
+    }
+
+    pub async fn recv(&mut self) -> Result<Message> {
+        self.recv_with_timestamp().await.map(|(msg, _)| msg)
+    }
+
+    /// Receive a message together with its [`Timestamp`], as reported by the backend.
+    ///
+    /// The USR-CANET200 does not report timestamps, hence the time the message was received is used instead.
+    pub async fn recv_with_timestamp(&mut self) -> Result<(Message, Timestamp)> {
+        match self {
+            #[cfg(feature = "pcan")]
+            Device::Pcan(_, rx) => rx.recv_with_timestamp().await,
+            #[cfg(all(target_os = "linux", feature = "socket_can"))]
+            Device::SocketCan(socket) => Ok(socket.recv_with_timestamp().await?),
+            #[cfg(feature = "usr_canet")]
+            Device::UsrCanet(_, rx) => {
+                let msg = crate::Receiver::recv(rx).await?;
+                let timestamp = Timestamp::from_system_time(std::time::SystemTime::now())
+                    .unwrap_or_else(|| Timestamp::from_micros(0));
+                Ok((msg, timestamp))
+            }
+            Device::Loopback(_, rx) => rx.recv_with_timestamp().await,
+        }
+    }
+
+    /// Returns the number of received frames which were dropped since they could not be decoded.
+    pub fn dropped_malformed_count(&self) -> u64 {
+        match self {
+            #[cfg(feature = "pcan")]
+            Device::Pcan(_, rx) => rx.dropped_malformed_count(),
+            #[cfg(all(target_os = "linux", feature = "socket_can"))]
+            Device::SocketCan(socket) => socket.dropped_malformed_count(),
+            #[cfg(feature = "usr_canet")]
+            Device::UsrCanet(_, rx) => rx.dropped_malformed_count(),
+            Device::Loopback(_, _) => 0,
+        }
+    }
+}
+
+#[cfg(feature = "pcan")]
+impl From<(crate::pcan::Sender, crate::pcan::Receiver)> for Device {
+    fn from((tx, rx): (crate::pcan::Sender, crate::pcan::Receiver)) -> Self {
+        Device::Pcan(tx, rx)
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "socket_can"))]
+impl From<crate::socketcan::CanSocket> for Device {
+    fn from(socket: crate::socketcan::CanSocket) -> Self {
+        Device::SocketCan(socket)
+    }
+}
+
+#[cfg(feature = "usr_canet")]
+impl From<(crate::usr_canet::Sender, crate::usr_canet::Receiver)> for Device {
+    fn from((tx, rx): (crate::usr_canet::Sender, crate::usr_canet::Receiver)) -> Self {
+        Device::UsrCanet(tx, rx)
+    }
+}
+
+impl From<(loopback::Sender, loopback::Receiver)> for Device {
+    fn from((tx, rx): (loopback::Sender, loopback::Receiver)) -> Self {
+        Device::Loopback(tx, rx)
+    }
+}
+
+#[async_trait]
+impl crate::Sender for Device {
+    async fn send(&mut self, msg: Message) -> Result<()> {
+        Device::send(self, msg).await
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        match self {
+            #[cfg(feature = "pcan")]
+            Device::Pcan(tx, _) => crate::Sender::shutdown(tx).await,
+            #[cfg(all(target_os = "linux", feature = "socket_can"))]
+            Device::SocketCan(socket) => Ok(socket.close()?),
+            #[cfg(feature = "usr_canet")]
+            Device::UsrCanet(tx, _) => crate::Sender::shutdown(tx).await,
+            Device::Loopback(tx, _) => crate::Sender::shutdown(tx).await,
+        }
+    }
+}
+
+#[async_trait]
+impl crate::Receiver for Device {
+    async fn recv(&mut self) -> Result<Message> {
+        Device::recv(self).await
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        match self {
+            #[cfg(feature = "pcan")]
+            Device::Pcan(_, rx) => crate::Receiver::shutdown(rx).await,
+            #[cfg(all(target_os = "linux", feature = "socket_can"))]
+            Device::SocketCan(socket) => Ok(socket.close()?),
+            #[cfg(feature = "usr_canet")]
+            Device::UsrCanet(_, rx) => crate::Receiver::shutdown(rx).await,
+            Device::Loopback(_, rx) => crate::Receiver::shutdown(rx).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn loopback() {
+        let mut device = Device::from(loopback::connect());
+        let msg = Message::new_data(0x123, false, &[1, 2]).unwrap();
+        device.send(msg.clone()).await.unwrap();
+        let (received, _) = device.recv_with_timestamp().await.unwrap();
+        assert_eq!(received, msg);
+        assert_eq!(device.dropped_malformed_count(), 0);
+    }
+}
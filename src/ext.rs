@@ -0,0 +1,467 @@
+//! Extension traits and adapters to compose [`crate::Sender`] and [`crate::Receiver`] implementations.
+//!
+//! All adapters wrap the underlying sender or receiver and implement the respective trait again, so
+//! they can be chained and boxed like any other backend.
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use futures::{ready, FutureExt, Sink};
+
+use crate::{DataFrame, Error, Message, Receiver, Result, Sender};
+
+/// Adapter methods available on all [`Receiver`] types.
+pub trait ReceiverExt: Receiver {
+    /// Apply `f` to every message received from this receiver.
+    ///
+    /// This is useful to remap IDs or mask data when translating between buses.
+    fn map<F>(self, f: F) -> Map<Self, F>
+    where
+        Self: Sized,
+        F: Fn(Message) -> Message,
+    {
+        Map { inner: self, f }
+    }
+
+    /// Apply `f` to every message received from this receiver and drop it if `f` returns `None`.
+    fn filter_map<F>(self, f: F) -> FilterMap<Self, F>
+    where
+        Self: Sized,
+        F: Fn(Message) -> Option<Message>,
+    {
+        FilterMap { inner: self, f }
+    }
+
+    /// Receive the next message, waiting for at most `timeout`. Returns `Ok(None)` if no message was received
+    /// in time.
+    ///
+    /// The receiver is polled before the deadline is checked, so a message which is available right at the
+    /// deadline is returned rather than dropped. This is cancel-safe if [`Receiver::recv()`] of the underlying
+    /// receiver is cancel-safe.
+    fn recv_timeout(&mut self, timeout: Duration) -> BoxFuture<'_, Result<Option<Message>>>
+    where
+        Self: Send,
+    {
+        async move {
+            match tokio::time::timeout(timeout, self.recv()).await {
+                Ok(msg) => msg.map(Some),
+                Err(_) => Ok(None),
+            }
+        }
+        .boxed()
+    }
+
+    /// Receive messages until one satisfies `pred` and return it. All other messages are discarded.
+    ///
+    /// Fails with [`Error::Timeout`] if no matching message is received within `timeout`.
+    fn wait_for<'a, F>(&'a mut self, pred: F, timeout: Duration) -> BoxFuture<'a, Result<Message>>
+    where
+        Self: Send,
+        F: Fn(&Message) -> bool + Send + 'a,
+    {
+        async move {
+            let recv = async move {
+                loop {
+                    let msg = self.recv().await?;
+                    if pred(&msg) {
+                        return Ok(msg);
+                    }
+                }
+            };
+            tokio::time::timeout(timeout, recv)
+                .await
+                .map_err(|_| Error::Timeout)?
+        }
+        .boxed()
+    }
+}
+
+impl<T: Receiver + ?Sized> ReceiverExt for T {}
+
+/// Adapter methods available on all [`Sender`] types.
+pub trait SenderExt: Sender {
+    /// Apply `f` to every message before it is passed on to this sender.
+    fn contramap<F>(self, f: F) -> Contramap<Self, F>
+    where
+        Self: Sized,
+        F: Fn(Message) -> Message,
+    {
+        Contramap { inner: self, f }
+    }
+
+    /// Convert this sender into a [`futures::Sink`], e.g. to `forward()` a stream of messages to the bus.
+    ///
+    /// Each message is sent with [`Sender::send()`]. An error is reported by the subsequent call to
+    /// `poll_ready()` or `poll_flush()`. Closing the sink shuts the sender down with [`Sender::shutdown()`]
+    /// and drops it.
+    fn into_sink(self) -> SenderSink<Self>
+    where
+        Self: Sized + Send + 'static,
+    {
+        SenderSink {
+            state: SinkState::Idle(self),
+        }
+    }
+}
+
+impl<T: Sender + ?Sized> SenderExt for T {}
+
+/// Send a remote frame requesting `id` and wait for the data frame with the same ID sent in response.
+///
+/// All other messages received in the meantime are discarded. Fails with [`Error::Timeout`] if no response is
+/// received within `timeout`.
+pub async fn request<S, R>(
+    sender: &mut S,
+    receiver: &mut R,
+    id: u32,
+    ext_id: bool,
+    dlc: u8,
+    timeout: Duration,
+) -> Result<DataFrame>
+where
+    S: Sender + Send + ?Sized,
+    R: Receiver + Send + ?Sized,
+{
+    sender.send(Message::new_remote(id, ext_id, dlc)?).await?;
+    let is_response = |msg: &Message| matches!(msg, Message::Data(frame) if frame.id() == id && frame.ext_id() == ext_id);
+    match receiver.wait_for(is_response, timeout).await? {
+        Message::Data(frame) => Ok(frame),
+        _ => unreachable!(),
+    }
+}
+
+/// Receiver returned by [`ReceiverExt::map`].
+pub struct Map<R, F> {
+    inner: R,
+    f: F,
+}
+
+impl<R, F> Map<R, F> {
+    /// Return the wrapped receiver.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<R, F> Receiver for Map<R, F>
+where
+    R: Receiver + Send,
+    F: Fn(Message) -> Message + Send,
+{
+    async fn recv(&mut self) -> Result<Message> {
+        let msg = self.inner.recv().await?;
+        Ok((self.f)(msg))
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        self.inner.shutdown().await
+    }
+}
+
+/// Receiver returned by [`ReceiverExt::filter_map`].
+pub struct FilterMap<R, F> {
+    inner: R,
+    f: F,
+}
+
+impl<R, F> FilterMap<R, F> {
+    /// Return the wrapped receiver.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<R, F> Receiver for FilterMap<R, F>
+where
+    R: Receiver + Send,
+    F: Fn(Message) -> Option<Message> + Send,
+{
+    async fn recv(&mut self) -> Result<Message> {
+        loop {
+            let msg = self.inner.recv().await?;
+            if let Some(msg) = (self.f)(msg) {
+                return Ok(msg);
+            }
+        }
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        self.inner.shutdown().await
+    }
+}
+
+/// Sender returned by [`SenderExt::contramap`].
+pub struct Contramap<S, F> {
+    inner: S,
+    f: F,
+}
+
+impl<S, F> Contramap<S, F> {
+    /// Return the wrapped sender.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<S, F> Sender for Contramap<S, F>
+where
+    S: Sender + Send,
+    F: Fn(Message) -> Message + Send,
+{
+    async fn send(&mut self, msg: Message) -> Result<()> {
+        self.inner.send((self.f)(msg)).await
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        self.inner.shutdown().await
+    }
+}
+
+enum SinkState<S> {
+    Idle(S),
+    Sending(BoxFuture<'static, (S, Result<()>)>),
+    Closing(BoxFuture<'static, Result<()>>),
+    Closed,
+}
+
+/// Sink returned by [`SenderExt::into_sink`].
+pub struct SenderSink<S> {
+    state: SinkState<S>,
+}
+
+// the sender is only ever moved, never pinned
+impl<S> Unpin for SenderSink<S> {}
+
+impl<S> SenderSink<S> {
+    /// Return the wrapped sender, or `None` while a message is still being sent or once the sink was closed.
+    pub fn into_inner(self) -> Option<S> {
+        match self.state {
+            SinkState::Idle(sender) => Some(sender),
+            _ => None,
+        }
+    }
+
+    fn poll_idle(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        match &mut self.state {
+            SinkState::Idle(_) => Poll::Ready(Ok(())),
+            SinkState::Sending(fut) => match fut.poll_unpin(cx) {
+                Poll::Ready((sender, result)) => {
+                    self.state = SinkState::Idle(sender);
+                    Poll::Ready(result)
+                }
+                Poll::Pending => Poll::Pending,
+            },
+            SinkState::Closing(_) | SinkState::Closed => {
+                Poll::Ready(Err(Error::Other("Sink is closed".to_string())))
+            }
+        }
+    }
+}
+
+impl<S: Sender + Send + 'static> Sink<Message> for SenderSink<S> {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut().poll_idle(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, msg: Message) -> Result<()> {
+        let this = self.get_mut();
+        match std::mem::replace(&mut this.state, SinkState::Closed) {
+            SinkState::Idle(mut sender) => {
+                this.state = SinkState::Sending(
+                    async move {
+                        let result = sender.send(msg).await;
+                        (sender, result)
+                    }
+                    .boxed(),
+                );
+                Ok(())
+            }
+            state => {
+                this.state = state;
+                Err(Error::Other(
+                    "Sink is not ready, call `poll_ready()` first".to_string(),
+                ))
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut().poll_idle(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        if let SinkState::Sending(_) = this.state {
+            ready!(this.poll_idle(cx))?;
+        }
+        loop {
+            match std::mem::replace(&mut this.state, SinkState::Closed) {
+                SinkState::Idle(mut sender) => {
+                    // the sender is dropped once the future completes
+                    this.state = SinkState::Closing(async move { sender.shutdown().await }.boxed());
+                }
+                SinkState::Closing(mut fut) => {
+                    return match fut.poll_unpin(cx) {
+                        Poll::Ready(result) => Poll::Ready(result),
+                        Poll::Pending => {
+                            this.state = SinkState::Closing(fut);
+                            Poll::Pending
+                        }
+                    };
+                }
+                SinkState::Sending(_) => unreachable!(),
+                SinkState::Closed => return Poll::Ready(Ok(())),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::loopback;
+
+    fn remap(msg: Message) -> Message {
+        Message::new_data(msg.id() + 1, msg.ext_id(), &[]).unwrap()
+    }
+
+    #[tokio::test]
+    async fn map_and_contramap() {
+        let (tx, rx) = loopback::connect();
+        let mut tx = tx.contramap(remap);
+        let mut rx = rx.map(remap);
+        tx.send(Message::new_data(0x100, false, &[]).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(rx.recv().await.unwrap().id(), 0x102);
+    }
+
+    #[tokio::test]
+    async fn forward_into_sink() {
+        use futures::{stream, StreamExt};
+
+        let (tx, mut rx) = loopback::connect();
+        let msgs = (1..=3).map(|id| Ok(Message::new_data(id, false, &[]).unwrap()));
+        stream::iter(msgs).forward(tx.into_sink()).await.unwrap();
+        for id in 1..=3 {
+            assert_eq!(rx.recv().await.unwrap().id(), id);
+        }
+    }
+
+    #[tokio::test]
+    async fn close_sink() {
+        use futures::SinkExt;
+
+        let (tx, mut rx) = loopback::connect();
+        let mut sink = tx.into_sink();
+        let msg = Message::new_data(0x123, false, &[]).unwrap();
+        sink.send(msg.clone()).await.unwrap();
+        sink.close().await.unwrap();
+        // the sender was shut down and dropped, while the sink is still alive
+        assert_eq!(rx.recv().await.unwrap(), msg);
+        assert!(rx.recv().await.unwrap_err().is_disconnected());
+        assert!(sink.send(msg).await.is_err());
+        sink.close().await.unwrap();
+        assert!(sink.into_inner().is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn recv_timeout() {
+        let (mut tx, mut rx) = loopback::connect();
+        let timeout = Duration::from_millis(100);
+        assert!(rx.recv_timeout(timeout).await.unwrap().is_none());
+
+        let msg = Message::new_data(0x123, false, &[]).unwrap();
+        tx.send(msg.clone()).await.unwrap();
+        assert_eq!(rx.recv_timeout(timeout).await.unwrap(), Some(msg.clone()));
+
+        // a message arriving at the deadline is not lost
+        let sender = tokio::spawn(async move {
+            tokio::time::sleep(timeout).await;
+            tx.send(msg).await.unwrap();
+            tx
+        });
+        let received = rx.recv_timeout(timeout).await.unwrap();
+        let tx = sender.await.unwrap();
+        match received {
+            Some(msg) => assert_eq!(msg.id(), 0x123),
+            None => assert_eq!(rx.recv().await.unwrap().id(), 0x123),
+        }
+
+        drop(tx);
+        assert!(rx
+            .recv_timeout(timeout)
+            .await
+            .unwrap_err()
+            .is_disconnected());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn remote_request() {
+        let (mut tx, mut node_rx) = loopback::connect();
+        let (mut node_tx, mut rx) = loopback::connect();
+        let node = tokio::spawn(async move {
+            let request = node_rx.recv().await.unwrap();
+            assert!(matches!(&request, Message::Remote(x) if x.dlc() == 2));
+            for msg in [
+                Message::new_data(0x123, true, &[0xFF]).unwrap(),
+                Message::new_data(0x456, false, &[0xFF]).unwrap(),
+                Message::new_data(0x123, false, &[1, 2]).unwrap(),
+            ] {
+                node_tx.send(msg).await.unwrap();
+            }
+            (node_tx, node_rx)
+        });
+        let timeout = Duration::from_millis(100);
+        let frame = request(&mut tx, &mut rx, 0x123, false, 2, timeout)
+            .await
+            .unwrap();
+        assert_eq!(frame.data(), [1, 2]);
+        // keep the bus connected, such that the next request times out
+        let _node = node.await.unwrap();
+
+        let err = request(&mut tx, &mut rx, 0x123, false, 2, timeout)
+            .await
+            .unwrap_err();
+        assert!(err.is_timeout());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn wait_for_predicate() {
+        let (mut tx, mut rx) = loopback::connect();
+        for data in [[0x7F], [0x50]] {
+            tx.send(Message::new_data(0x7E8, false, &data).unwrap())
+                .await
+                .unwrap();
+        }
+        let is_positive = |msg: &Message| matches!(msg, Message::Data(x) if x.id() == 0x7E8 && x.data().first() == Some(&0x50));
+        let timeout = Duration::from_millis(100);
+        let msg = rx.wait_for(is_positive, timeout).await.unwrap();
+        assert_eq!(msg, Message::new_data(0x7E8, false, &[0x50]).unwrap());
+        assert!(rx
+            .wait_for(is_positive, timeout)
+            .await
+            .unwrap_err()
+            .is_timeout());
+    }
+
+    #[tokio::test]
+    async fn filter_map_drops_frames() {
+        let (mut tx, rx) = loopback::connect();
+        let mut rx = rx.filter_map(|msg| if msg.id() == 1 { None } else { Some(msg) });
+        for id in 1..=2 {
+            tx.send(Message::new_data(id, false, &[]).unwrap())
+                .await
+                .unwrap();
+        }
+        assert_eq!(rx.recv().await.unwrap().id(), 2);
+    }
+}
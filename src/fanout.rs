@@ -0,0 +1,75 @@
+//! This module implements sending each message to several buses at once, for example to drive redundant buses.
+
+use async_trait::async_trait;
+use futures::future::join_all;
+
+use crate::uri::BoxSender;
+use crate::{Message, Result, Sender};
+
+/// A [`Sender`] which sends every message to all of the wrapped senders.
+#[derive(Default)]
+pub struct FanOut {
+    senders: Vec<BoxSender>,
+}
+
+impl FanOut {
+    pub fn new(senders: Vec<BoxSender>) -> Self {
+        Self { senders }
+    }
+
+    /// Add another sender.
+    pub fn with<S: Sender + Send + 'static>(mut self, sender: S) -> Self {
+        self.senders.push(Box::new(sender));
+        self
+    }
+
+    pub fn into_inner(self) -> Vec<BoxSender> {
+        self.senders
+    }
+}
+
+#[async_trait]
+impl Sender for FanOut {
+    /// Send `msg` to all senders concurrently.
+    ///
+    /// All senders are driven to completion, even if one of them fails. Returns the error of the first
+    /// failing sender in the order they were added.
+    async fn send(&mut self, msg: Message) -> Result<()> {
+        let sends = self.senders.iter_mut().map(|tx| tx.send(msg.clone()));
+        join_all(sends).await.into_iter().collect()
+    }
+
+    /// Shut down all senders concurrently and return the first error.
+    async fn shutdown(&mut self) -> Result<()> {
+        let shutdowns = self.senders.iter_mut().map(|tx| tx.shutdown());
+        join_all(shutdowns).await.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{loopback, Receiver};
+
+    #[tokio::test]
+    async fn send_to_all() {
+        let (tx_a, mut rx_a) = loopback::connect();
+        let (tx_b, rx_b) = loopback::connect();
+        let (tx_c, mut rx_c) = loopback::connect();
+        let mut fanout = FanOut::default().with(tx_a).with(tx_b).with(tx_c);
+
+        let msg = Message::new_data(0x123, false, &[1]).unwrap();
+        fanout.send(msg.clone()).await.unwrap();
+        assert_eq!(rx_a.recv().await.unwrap(), msg);
+        assert_eq!(rx_c.recv().await.unwrap(), msg);
+
+        drop(rx_b);
+        assert!(fanout
+            .send(msg.clone())
+            .await
+            .unwrap_err()
+            .is_disconnected());
+        assert_eq!(rx_a.recv().await.unwrap(), msg);
+        assert_eq!(rx_c.recv().await.unwrap(), msg);
+    }
+}
@@ -0,0 +1,216 @@
+//! This module implements software filtering of received messages for backends which do not support
+//! filtering in the kernel or in hardware, as well as the [`Filter`] builder to specify ID filters.
+//!
+//! Filters are given as `(id, mask)` pairs in the same format as SocketCAN filters: A message matches
+//! if `raw_id & mask == id & mask`, where `raw_id` is the message ID with [`EXT_ID_FLAG`] set for
+//! extended IDs.
+
+use std::ops::RangeInclusive;
+
+use async_trait::async_trait;
+
+use crate::{Message, Receiver, Result, CAN_EXT_ID_MASK};
+
+/// Flag marking an extended ID in `(id, mask)` filter pairs. Corresponds to `CAN_EFF_FLAG` of SocketCAN.
+pub const EXT_ID_FLAG: u32 = 0x80000000;
+
+/// Builder for ID filters which compiles to a list of `(id, mask)` pairs, usable with
+/// [`FilteredReceiver`] and, by means of `CanFilter::from`, with SocketCAN.
+///
+/// A message passes if its ID matches any of the configured IDs or ranges. If neither is configured,
+/// all IDs pass. [`Filter::extended_only()`] and [`Filter::standard_only()`] additionally restrict the
+/// type of ID.
+///
+/// ```
+/// use async_can::filter::Filter;
+///
+/// let filters = Filter::new().id_range(0x100..=0x1FF).exact(0x7E8).compile();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    ranges: Vec<RangeInclusive<u32>>,
+    ext_id: Option<bool>,
+}
+
+impl Filter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Accept all IDs in the given range.
+    pub fn id_range(mut self, range: RangeInclusive<u32>) -> Self {
+        self.ranges.push(range);
+        self
+    }
+
+    /// Accept exactly the given ID.
+    pub fn exact(mut self, id: u32) -> Self {
+        self.ranges.push(id..=id);
+        self
+    }
+
+    /// Only accept messages with extended IDs.
+    pub fn extended_only(mut self) -> Self {
+        self.ext_id = Some(true);
+        self
+    }
+
+    /// Only accept messages with standard IDs.
+    pub fn standard_only(mut self) -> Self {
+        self.ext_id = Some(false);
+        self
+    }
+
+    /// Compile the filter to a list of `(id, mask)` pairs.
+    ///
+    /// Each range is split into the minimal number of pairs covering exactly this range.
+    pub fn compile(&self) -> Vec<(u32, u32)> {
+        let mut ret = Vec::new();
+        if self.ranges.is_empty() {
+            ret.push((0, 0));
+        }
+        for range in &self.ranges {
+            let mut lo = (*range.start() & CAN_EXT_ID_MASK) as u64;
+            let hi = (*range.end() & CAN_EXT_ID_MASK) as u64;
+            while lo <= hi {
+                // the largest aligned block starting at `lo` which does not exceed `hi`
+                let mut size = if lo == 0 {
+                    1 << 29
+                } else {
+                    1 << lo.trailing_zeros()
+                };
+                while lo + size - 1 > hi {
+                    size >>= 1;
+                }
+                ret.push((lo as u32, CAN_EXT_ID_MASK & !(size as u32 - 1)));
+                lo += size;
+            }
+        }
+        for (id, mask) in &mut ret {
+            match self.ext_id {
+                Some(true) => {
+                    *id |= EXT_ID_FLAG;
+                    *mask |= EXT_ID_FLAG;
+                }
+                Some(false) => *mask |= EXT_ID_FLAG,
+                None => {}
+            }
+        }
+        ret
+    }
+}
+
+/// Wraps a [`crate::Receiver`] and only yields messages with an ID matching any of the given
+/// `(id, mask)` pairs, refer to the [module documentation](crate::filter). All other messages are discarded.
+///
+/// Note that, like a SocketCAN filter, an empty list of filters discards all messages.
+pub struct FilteredReceiver<R> {
+    inner: R,
+    filters: Vec<(u32, u32)>,
+}
+
+impl<R> FilteredReceiver<R> {
+    /// Wrap `inner` and only accept messages matching any of the `(id, mask)` pairs in `filters`.
+    pub fn new(inner: R, filters: Vec<(u32, u32)>) -> Self {
+        Self { inner, filters }
+    }
+
+    /// Returns true if the given message passes the filters.
+    pub fn matches(&self, msg: &Message) -> bool {
+        let msg_id = if msg.ext_id() {
+            msg.id() | EXT_ID_FLAG
+        } else {
+            msg.id()
+        };
+        self.filters
+            .iter()
+            .any(|&(id, mask)| msg_id & mask == id & mask)
+    }
+
+    /// Return the wrapped receiver.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<R: Receiver + Send> Receiver for FilteredReceiver<R> {
+    async fn recv(&mut self) -> Result<Message> {
+        loop {
+            let msg = self.inner.recv().await?;
+            if self.matches(&msg) {
+                return Ok(msg);
+            }
+        }
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        self.inner.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{loopback, Sender};
+
+    #[tokio::test]
+    async fn id_range() {
+        let (mut tx, rx) = loopback::connect();
+        let mut rx = FilteredReceiver::new(rx, vec![(0x100, 0x7F8)]);
+        for id in [0x0FF, 0x108, 0x100, 0x107] {
+            tx.send(Message::new_data(id, false, &[]).unwrap())
+                .await
+                .unwrap();
+        }
+        assert_eq!(rx.recv().await.unwrap().id(), 0x100);
+        assert_eq!(rx.recv().await.unwrap().id(), 0x107);
+    }
+
+    #[test]
+    fn compile_ranges() {
+        assert_eq!(Filter::new().compile(), vec![(0, 0)]);
+        assert_eq!(
+            Filter::new().exact(0x7E8).compile(),
+            vec![(0x7E8, 0x1FFFFFFF)]
+        );
+        assert_eq!(
+            Filter::new().id_range(0x100..=0x1FF).compile(),
+            vec![(0x100, 0x1FFFFF00)]
+        );
+        assert_eq!(
+            Filter::new().id_range(0x0FF..=0x101).compile(),
+            vec![(0x0FF, 0x1FFFFFFF), (0x100, 0x1FFFFFFE)]
+        );
+        assert_eq!(
+            Filter::new().extended_only().compile(),
+            vec![(EXT_ID_FLAG, EXT_ID_FLAG)]
+        );
+        assert_eq!(
+            Filter::new().exact(0x10).standard_only().compile(),
+            vec![(0x10, 0x1FFFFFFF | EXT_ID_FLAG)]
+        );
+    }
+
+    #[test]
+    fn compiled_filter_matches() {
+        let (_, rx) = loopback::connect();
+        let filters = Filter::new()
+            .id_range(0x100..=0x10A)
+            .extended_only()
+            .compile();
+        let rx = FilteredReceiver::new(rx, filters);
+        for id in 0x0F0..0x120 {
+            let ext = Message::new_data(id, true, &[]).unwrap();
+            assert_eq!(rx.matches(&ext), (0x100..=0x10A).contains(&id));
+            assert!(!rx.matches(&Message::new_data(id, false, &[]).unwrap()));
+        }
+    }
+
+    #[test]
+    fn empty_filter_matches_nothing() {
+        let (_, rx) = loopback::connect();
+        let rx = FilteredReceiver::new(rx, vec![]);
+        assert!(!rx.matches(&Message::new_data(0x100, false, &[]).unwrap()));
+    }
+}
@@ -0,0 +1,149 @@
+//! This module implements a human-readable serde representation of [`Message`], in which the ID and the data
+//! are encoded as hex strings.
+//!
+//! For example, a data frame is serialized to JSON as `{"id":"0x1a0","ext":false,"data":"deadbeef"}`.
+//! Remote frames carry `"rtr":true` and the `"dlc"` instead of the data, CAN-FD frames carry `"fd":true`
+//! and their `"brs"` and `"esi"` flags.
+
+use serde::de::Error as SerdeDeError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{decode_hex, CanFdFrame, Message};
+
+/// Wraps a [`Message`] to (de-)serialize it with hex encoded ID and data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HexMessage(pub Message);
+
+impl From<Message> for HexMessage {
+    fn from(msg: Message) -> Self {
+        HexMessage(msg)
+    }
+}
+
+impl From<HexMessage> for Message {
+    fn from(msg: HexMessage) -> Self {
+        msg.0
+    }
+}
+
+fn is_false(x: &bool) -> bool {
+    !x
+}
+
+#[derive(Serialize, Deserialize)]
+struct Repr {
+    id: String,
+    ext: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    rtr: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dlc: Option<u8>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    fd: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    brs: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    esi: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data: Option<String>,
+}
+
+fn encode_data(data: &[u8]) -> Option<String> {
+    Some(data.iter().map(|x| format!("{:02x}", x)).collect())
+}
+
+impl Serialize for HexMessage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let msg = &self.0;
+        let mut repr = Repr {
+            id: format!("0x{:x}", msg.id()),
+            ext: msg.ext_id(),
+            rtr: false,
+            dlc: None,
+            fd: false,
+            brs: false,
+            esi: false,
+            data: None,
+        };
+        match msg {
+            Message::Data(frame) => repr.data = encode_data(frame.data()),
+            Message::Remote(frame) => {
+                repr.rtr = true;
+                repr.dlc = Some(frame.dlc());
+            }
+            Message::Fd(frame) => {
+                repr.fd = true;
+                repr.brs = frame.brs();
+                repr.esi = frame.esi();
+                repr.data = encode_data(frame.data());
+            }
+        }
+        repr.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for HexMessage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = Repr::deserialize(deserializer)?;
+        let id = repr.id.trim_start_matches("0x").trim_start_matches("0X");
+        let id = u32::from_str_radix(id, 16).map_err(|_| D::Error::custom("Invalid CAN Id"))?;
+        let data = repr.data.as_deref().unwrap_or("");
+        let data = decode_hex(data.as_bytes()).map_err(|_| D::Error::custom("Invalid hex data"))?;
+        let msg = if repr.rtr {
+            Message::new_remote(id, repr.ext, repr.dlc.unwrap_or(0))
+        } else if repr.fd {
+            CanFdFrame::new(id, repr.ext, repr.brs, data)
+                .map(|frame| Message::Fd(frame.with_esi(repr.esi)))
+        } else {
+            Message::new_data(id, repr.ext, &data)
+        };
+        msg.map(HexMessage)
+            .map_err(|err| D::Error::custom(format!("Invalid frame: {:?}", err)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn json_round_trip() {
+        let msgs = [
+            (
+                Message::new_data(0x1A0, false, &[0xDE, 0xAD, 0xBE, 0xEF]).unwrap(),
+                r#"{"id":"0x1a0","ext":false,"data":"deadbeef"}"#,
+            ),
+            (
+                Message::new_remote(0x12345, true, 3).unwrap(),
+                r#"{"id":"0x12345","ext":true,"rtr":true,"dlc":3}"#,
+            ),
+            (
+                Message::new_fd(0x7FF, false, true, &[0x01; 12]).unwrap(),
+                r#"{"id":"0x7ff","ext":false,"fd":true,"brs":true,"data":"010101010101010101010101"}"#,
+            ),
+        ];
+        for (msg, json) in msgs {
+            let hex = HexMessage(msg);
+            assert_eq!(serde_json::to_string(&hex).unwrap(), json);
+            assert_eq!(serde_json::from_str::<HexMessage>(json).unwrap(), hex);
+        }
+    }
+
+    #[test]
+    fn json_invalid() {
+        let msg: HexMessage =
+            serde_json::from_str(r#"{"id":"1A0","ext":false,"data":"DEAD"}"#).unwrap();
+        assert_eq!(
+            msg.0,
+            Message::new_data(0x1A0, false, &[0xDE, 0xAD]).unwrap()
+        );
+        for json in [
+            r#"{"id":"0x800","ext":false,"data":""}"#,
+            r#"{"id":"0xXYZ","ext":false,"data":""}"#,
+            r#"{"id":"0x1","ext":false,"data":"abc"}"#,
+            r#"{"id":"0x1","ext":false,"data":"112233445566778899"}"#,
+        ] {
+            assert!(serde_json::from_str::<HexMessage>(json).is_err());
+        }
+    }
+}
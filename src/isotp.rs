@@ -0,0 +1,325 @@
+//! This module implements the ISO-TP (ISO 15765-2) transport protocol on top of any [`crate::Sender`] and
+//! [`crate::Receiver`], for example for UDS diagnostics.
+//!
+//! Payloads of up to 7 bytes are transmitted in a single frame. Longer payloads of up to 4095 bytes are
+//! segmented into a first frame and consecutive frames, whose transmission is paced by flow control frames
+//! of the receiving node. Only normal addressing on classic CAN is supported.
+
+use std::time::Duration;
+
+use crate::{Error, Message, Receiver, Result, Sender};
+
+/// Maximum payload length which can be transferred.
+pub const MAX_PAYLOAD_LEN: usize = 0xFFF;
+
+const SINGLE_FRAME: u8 = 0x00;
+const FIRST_FRAME: u8 = 0x10;
+const CONSECUTIVE_FRAME: u8 = 0x20;
+const FLOW_CONTROL: u8 = 0x30;
+
+const FC_CONTINUE: u8 = 0;
+const FC_WAIT: u8 = 1;
+const FC_OVERFLOW: u8 = 2;
+
+/// Timeout for waiting on a flow control or consecutive frame (N_Bs and N_Cr).
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1000);
+
+fn decode_st_min(st_min: u8) -> Duration {
+    match st_min {
+        0x00..=0x7F => Duration::from_millis(st_min as u64),
+        0xF1..=0xF9 => Duration::from_micros((st_min - 0xF0) as u64 * 100),
+        // reserved values shall be interpreted as the longest separation time
+        _ => Duration::from_millis(0x7F),
+    }
+}
+
+fn encode_st_min(st_min: Duration) -> u8 {
+    let micros = st_min.as_micros();
+    if micros == 0 {
+        0
+    } else if micros < 1000 {
+        0xF0 + micros.div_ceil(100) as u8
+    } else {
+        micros.div_ceil(1000).min(0x7F) as u8
+    }
+}
+
+/// An ISO-TP channel between this node and a peer, built on a [`crate::Sender`] and [`crate::Receiver`].
+///
+/// ```no_run
+/// # tokio_test::block_on(async {
+/// use async_can::isotp::IsoTp;
+/// use async_can::usr_canet;
+///
+/// let (tx, rx) = usr_canet::connect("192.168.1.10:1").await.unwrap();
+/// let mut isotp = IsoTp::new(tx, rx, 0x7E0, 0x7E8, false);
+/// isotp.send(&[0x22, 0xF1, 0x90]).await.unwrap();
+/// let response = isotp.recv().await.unwrap();
+/// # });
+/// ```
+pub struct IsoTp<S, R> {
+    sender: S,
+    receiver: R,
+    tx_id: u32,
+    rx_id: u32,
+    ext_id: bool,
+    block_size: u8,
+    st_min: Duration,
+    timeout: Duration,
+    padding: Option<u8>,
+}
+
+impl<S: Sender + Send, R: Receiver + Send> IsoTp<S, R> {
+    /// Create a channel transmitting frames with `tx_id` and receiving frames with `rx_id`.
+    ///
+    /// Flow control frames are transmitted with `tx_id` as well. Frames with other IDs are ignored.
+    pub fn new(sender: S, receiver: R, tx_id: u32, rx_id: u32, ext_id: bool) -> Self {
+        Self {
+            sender,
+            receiver,
+            tx_id,
+            rx_id,
+            ext_id,
+            block_size: 0,
+            st_min: Duration::ZERO,
+            timeout: DEFAULT_TIMEOUT,
+            padding: None,
+        }
+    }
+
+    /// The number of consecutive frames the peer may send before waiting for the next flow control frame.
+    /// Defaults to 0, i.e. no further flow control frames.
+    pub fn block_size(mut self, block_size: u8) -> Self {
+        self.block_size = block_size;
+        self
+    }
+
+    /// The minimum separation time between two consecutive frames requested from the peer.
+    /// Defaults to 0.
+    pub fn st_min(mut self, st_min: Duration) -> Self {
+        self.st_min = st_min;
+        self
+    }
+
+    /// The timeout when waiting for a flow control or consecutive frame. Defaults to 1s.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Pad all transmitted frames to 8 bytes with the given byte, as required by some ECUs.
+    /// Defaults to no padding.
+    pub fn padding(mut self, padding: Option<u8>) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Return the wrapped sender and receiver.
+    pub fn into_inner(self) -> (S, R) {
+        (self.sender, self.receiver)
+    }
+
+    async fn send_frame(&mut self, mut data: Vec<u8>) -> Result<()> {
+        if let Some(padding) = self.padding {
+            data.resize(8, padding);
+        }
+        let msg = Message::new_data(self.tx_id, self.ext_id, &data)?;
+        self.sender.send(msg).await
+    }
+
+    /// Receive the next data frame with `rx_id`, waiting for at most `timeout` if given.
+    async fn recv_frame(&mut self, timeout: Option<Duration>) -> Result<Vec<u8>> {
+        let (rx_id, ext_id) = (self.rx_id, self.ext_id);
+        let receiver = &mut self.receiver;
+        let recv = async move {
+            loop {
+                match receiver.recv().await? {
+                    Message::Data(frame)
+                        if frame.id() == rx_id && frame.ext_id() == ext_id && frame.dlc() > 0 =>
+                    {
+                        return Ok(frame.take_data())
+                    }
+                    _ => {}
+                }
+            }
+        };
+        match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, recv)
+                .await
+                .map_err(|_| Error::Timeout)?,
+            None => recv.await,
+        }
+    }
+
+    /// Wait for a flow control frame allowing to continue. Returns the block size and separation time.
+    async fn recv_flow_control(&mut self) -> Result<(u8, Duration)> {
+        loop {
+            let frame = self.recv_frame(Some(self.timeout)).await?;
+            if frame[0] & 0xF0 != FLOW_CONTROL || frame.len() < 3 {
+                continue;
+            }
+            match frame[0] & 0x0F {
+                FC_CONTINUE => return Ok((frame[1], decode_st_min(frame[2]))),
+                FC_WAIT => {}
+                FC_OVERFLOW => {
+                    return Err(Error::Other(
+                        "ISO-TP receiver reported an overflow".to_string(),
+                    ))
+                }
+                x => return Err(Error::Other(format!("Invalid ISO-TP flow status: {}", x))),
+            }
+        }
+    }
+
+    /// Send `data` to the peer, segmenting it if it does not fit into a single frame.
+    pub async fn send(&mut self, data: &[u8]) -> Result<()> {
+        if data.len() > MAX_PAYLOAD_LEN {
+            return Err(Error::DataTooLong);
+        }
+        if data.len() <= 7 {
+            let mut frame = vec![SINGLE_FRAME | data.len() as u8];
+            frame.extend_from_slice(data);
+            return self.send_frame(frame).await;
+        }
+        let mut frame = vec![
+            FIRST_FRAME | (data.len() >> 8) as u8,
+            (data.len() & 0xFF) as u8,
+        ];
+        frame.extend_from_slice(&data[..6]);
+        self.send_frame(frame).await?;
+
+        let (mut block_size, mut st_min) = self.recv_flow_control().await?;
+        let mut sent_in_block = 0;
+        for (k, chunk) in data[6..].chunks(7).enumerate() {
+            if block_size > 0 && sent_in_block == block_size {
+                (block_size, st_min) = self.recv_flow_control().await?;
+                sent_in_block = 0;
+            } else if k > 0 {
+                tokio::time::sleep(st_min).await;
+            }
+            let sequence_number = ((k + 1) & 0x0F) as u8;
+            let mut frame = vec![CONSECUTIVE_FRAME | sequence_number];
+            frame.extend_from_slice(chunk);
+            self.send_frame(frame).await?;
+            sent_in_block += 1;
+        }
+        Ok(())
+    }
+
+    async fn send_flow_control(&mut self) -> Result<()> {
+        let frame = vec![
+            FLOW_CONTROL | FC_CONTINUE,
+            self.block_size,
+            encode_st_min(self.st_min),
+        ];
+        self.send_frame(frame).await
+    }
+
+    /// Receive the next payload from the peer, reassembling it if it is segmented.
+    ///
+    /// Waits for the start of a transfer without a timeout. Consecutive frames which are not received in
+    /// time or out of sequence abort the transfer with an error.
+    pub async fn recv(&mut self) -> Result<Vec<u8>> {
+        let (len, mut data) = loop {
+            let frame = self.recv_frame(None).await?;
+            match frame[0] & 0xF0 {
+                SINGLE_FRAME => {
+                    let len = (frame[0] & 0x0F) as usize;
+                    if len > 0 && len < frame.len() {
+                        return Ok(frame[1..1 + len].to_vec());
+                    }
+                }
+                FIRST_FRAME if frame.len() == 8 => {
+                    let len = ((frame[0] & 0x0F) as usize) << 8 | frame[1] as usize;
+                    if len > 7 {
+                        break (len, frame[2..].to_vec());
+                    }
+                }
+                _ => {}
+            }
+            log::debug!("Ignoring unexpected ISO-TP frame: {:02x?}", frame);
+        };
+        self.send_flow_control().await?;
+        let mut sequence_number = 1_u8;
+        let mut received_in_block = 0;
+        while data.len() < len {
+            let frame = self.recv_frame(Some(self.timeout)).await?;
+            if frame[0] & 0xF0 != CONSECUTIVE_FRAME {
+                continue;
+            }
+            if frame[0] & 0x0F != sequence_number {
+                return Err(Error::Other(format!(
+                    "ISO-TP sequence number mismatch: expected {}, got {}",
+                    sequence_number,
+                    frame[0] & 0x0F
+                )));
+            }
+            let remaining = len - data.len();
+            data.extend_from_slice(&frame[1..frame.len().min(remaining + 1)]);
+            sequence_number = (sequence_number + 1) & 0x0F;
+            received_in_block += 1;
+            if self.block_size > 0 && received_in_block == self.block_size && data.len() < len {
+                self.send_flow_control().await?;
+                received_in_block = 0;
+            }
+        }
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::loopback;
+
+    fn pair() -> (
+        IsoTp<loopback::Sender, loopback::Receiver>,
+        IsoTp<loopback::Sender, loopback::Receiver>,
+    ) {
+        let (tx1, rx1) = loopback::connect();
+        let (tx2, rx2) = loopback::connect();
+        (
+            IsoTp::new(tx1, rx2, 0x7E0, 0x7E8, false),
+            IsoTp::new(tx2, rx1, 0x7E8, 0x7E0, false),
+        )
+    }
+
+    #[test]
+    fn st_min() {
+        assert_eq!(decode_st_min(0x05), Duration::from_millis(5));
+        assert_eq!(decode_st_min(0xF3), Duration::from_micros(300));
+        assert_eq!(decode_st_min(0x80), Duration::from_millis(127));
+        assert_eq!(encode_st_min(Duration::from_micros(250)), 0xF3);
+        assert_eq!(encode_st_min(Duration::from_millis(10)), 0x0A);
+        assert_eq!(encode_st_min(Duration::from_secs(1)), 0x7F);
+    }
+
+    #[tokio::test]
+    async fn single_frame() {
+        let (mut a, mut b) = pair();
+        a.send(&[0x22, 0xF1, 0x90]).await.unwrap();
+        assert_eq!(b.recv().await.unwrap(), [0x22, 0xF1, 0x90]);
+    }
+
+    #[tokio::test]
+    async fn multi_frame() {
+        let (mut a, b) = pair();
+        let mut b = b
+            .block_size(2)
+            .st_min(Duration::from_micros(100))
+            .padding(Some(0xCC));
+        let data: Vec<u8> = (0..200).map(|x| x as u8).collect();
+        let (sent, received) = tokio::join!(a.send(&data), b.recv());
+        sent.unwrap();
+        assert_eq!(received.unwrap(), data);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn flow_control_timeout() {
+        let (a, _b) = pair();
+        let mut a = a.timeout(Duration::from_millis(100));
+        let err = a.send(&[0; 20]).await.unwrap_err();
+        assert!(err.is_timeout());
+        assert!(matches!(a.send(&[0; 4096]).await, Err(Error::DataTooLong)));
+    }
+}
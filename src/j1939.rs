@@ -0,0 +1,210 @@
+//! This module implements decoding and encoding the 29-bit extended IDs used by SAE J1939.
+//!
+//! A J1939 ID is composed of, from the most to the least significant bit:
+//!
+//!  * 3 bits of priority, where 0 is the highest priority
+//!  * 1 bit of extended data page (EDP) and 1 bit of data page (DP)
+//!  * 8 bits of PDU format (PF)
+//!  * 8 bits of PDU specific (PS)
+//!  * 8 bits of source address (SA)
+//!
+//! If PF is below 240 (PDU1 format), PS is the destination address of the message and the parameter group
+//! number (PGN) is composed of EDP, DP and PF only. Otherwise (PDU2 format), the message is broadcast and PS is
+//! part of the PGN as group extension.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::result::Result as StdResult;
+
+use crate::{CanFrameError, CanId, CAN_EXT_ID_MASK};
+
+/// The address used to send a PDU1 message to all nodes.
+pub const GLOBAL_ADDRESS: u8 = 0xFF;
+
+/// The source address used by nodes which have not claimed an address yet.
+pub const NULL_ADDRESS: u8 = 0xFE;
+
+/// PDU formats starting from this value denote broadcast (PDU2) messages.
+const PDU2_MIN_FORMAT: u8 = 240;
+
+/// A J1939 ID, refer to the [module documentation](crate::j1939) for its layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct J1939Id {
+    priority: u8,
+    data_page: u8,
+    pdu_format: u8,
+    pdu_specific: u8,
+    source_address: u8,
+}
+
+impl J1939Id {
+    /// Create an ID from its parts. For PDU1 PGNs, `destination_address` is encoded in the PS field, for PDU2 PGNs
+    /// it is ignored.
+    ///
+    /// Returns an error if `priority` exceeds 7, `pgn` exceeds 18 bits or the lower 8 bits of a PDU1 PGN are not 0.
+    pub fn new(
+        priority: u8,
+        pgn: u32,
+        destination_address: u8,
+        source_address: u8,
+    ) -> StdResult<Self, CanFrameError> {
+        if priority > 7 || pgn > 0x3FFFF {
+            return Err(CanFrameError::InvalidFormat);
+        }
+        let pdu_format = (pgn >> 8) as u8;
+        let pdu_specific = if pdu_format < PDU2_MIN_FORMAT {
+            if pgn & 0xFF != 0 {
+                return Err(CanFrameError::InvalidFormat);
+            }
+            destination_address
+        } else {
+            pgn as u8
+        };
+        Ok(Self {
+            priority,
+            data_page: (pgn >> 16) as u8,
+            pdu_format,
+            pdu_specific,
+            source_address,
+        })
+    }
+
+    /// Decode an extended ID. Returns an error if `id` exceeds 29 bits.
+    pub fn from_id(id: u32) -> StdResult<Self, CanFrameError> {
+        if id > CAN_EXT_ID_MASK {
+            return Err(CanFrameError::IdTooLong);
+        }
+        Ok(Self {
+            priority: (id >> 26) as u8,
+            data_page: ((id >> 24) & 0x3) as u8,
+            pdu_format: (id >> 16) as u8,
+            pdu_specific: (id >> 8) as u8,
+            source_address: id as u8,
+        })
+    }
+
+    /// Encode the extended ID.
+    pub fn to_id(&self) -> u32 {
+        (self.priority as u32) << 26
+            | (self.data_page as u32) << 24
+            | (self.pdu_format as u32) << 16
+            | (self.pdu_specific as u32) << 8
+            | self.source_address as u32
+    }
+
+    pub fn priority(&self) -> u8 {
+        self.priority
+    }
+
+    /// Returns the parameter group number, which includes the PS field only for PDU2 messages.
+    pub fn pgn(&self) -> u32 {
+        let pgn = (self.data_page as u32) << 16 | (self.pdu_format as u32) << 8;
+        if self.is_pdu1() {
+            pgn
+        } else {
+            pgn | self.pdu_specific as u32
+        }
+    }
+
+    pub fn pdu_format(&self) -> u8 {
+        self.pdu_format
+    }
+
+    pub fn pdu_specific(&self) -> u8 {
+        self.pdu_specific
+    }
+
+    pub fn source_address(&self) -> u8 {
+        self.source_address
+    }
+
+    /// Returns the destination address of PDU1 messages, which may be [`GLOBAL_ADDRESS`], or `None` for PDU2
+    /// messages, which are always broadcast.
+    pub fn destination_address(&self) -> Option<u8> {
+        if self.is_pdu1() {
+            Some(self.pdu_specific)
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if the message is addressed to a specific node, i.e. the PDU format is below 240.
+    pub fn is_pdu1(&self) -> bool {
+        self.pdu_format < PDU2_MIN_FORMAT
+    }
+}
+
+impl From<J1939Id> for CanId {
+    fn from(id: J1939Id) -> Self {
+        // a J1939 ID always fits into 29 bits
+        CanId::extended(id.to_id()).unwrap()
+    }
+}
+
+/// Fails with [`CanFrameError::InvalidFormat`] for standard IDs, which are not used by J1939.
+impl TryFrom<CanId> for J1939Id {
+    type Error = CanFrameError;
+
+    fn try_from(id: CanId) -> StdResult<Self, Self::Error> {
+        if !id.ext_id() {
+            return Err(CanFrameError::InvalidFormat);
+        }
+        J1939Id::from_id(id.id())
+    }
+}
+
+/// Formats the ID as e.g. `prio=3 pgn=61444 sa=0x00` or `prio=6 pgn=59904 da=0xFF sa=0x00`.
+impl fmt::Display for J1939Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "prio={} pgn={}", self.priority, self.pgn())?;
+        if let Some(da) = self.destination_address() {
+            write!(f, " da={:#04X}", da)?;
+        }
+        write!(f, " sa={:#04X}", self.source_address)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pdu2() {
+        // EEC1, engine speed broadcast by the engine
+        let id = J1939Id::from_id(0x0CF00400).unwrap();
+        assert_eq!(id.priority(), 3);
+        assert_eq!(id.pgn(), 61444);
+        assert_eq!(id.pdu_format(), 0xF0);
+        assert_eq!(id.pdu_specific(), 0x04);
+        assert_eq!(id.source_address(), 0x00);
+        assert_eq!(id.destination_address(), None);
+        assert_eq!(id.to_id(), 0x0CF00400);
+        assert_eq!(J1939Id::new(3, 61444, 0x12, 0x00).unwrap(), id);
+        assert_eq!(id.to_string(), "prio=3 pgn=61444 sa=0x00");
+    }
+
+    #[test]
+    fn pdu1() {
+        // request PGN sent to all nodes
+        let id = J1939Id::from_id(0x18EAFFF9).unwrap();
+        assert_eq!(id.priority(), 6);
+        assert_eq!(id.pgn(), 0xEA00);
+        assert_eq!(id.destination_address(), Some(GLOBAL_ADDRESS));
+        assert_eq!(id.source_address(), 0xF9);
+        assert_eq!(J1939Id::new(6, 0xEA00, 0xFF, 0xF9).unwrap(), id);
+        assert_eq!(id.to_string(), "prio=6 pgn=59904 da=0xFF sa=0xF9");
+
+        let can_id = CanId::from(id);
+        assert_eq!(can_id, CanId::extended(0x18EAFFF9).unwrap());
+        assert_eq!(J1939Id::try_from(can_id).unwrap(), id);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(J1939Id::from_id(0x2000_0000).is_err());
+        assert!(J1939Id::try_from(CanId::standard(0x123).unwrap()).is_err());
+        assert!(J1939Id::new(8, 0xF004, 0, 0).is_err());
+        assert!(J1939Id::new(3, 0x40000, 0, 0).is_err());
+        assert!(J1939Id::new(6, 0xEA01, 0, 0).is_err());
+    }
+}
@@ -50,6 +50,31 @@
 //! # });
 //! ```
 //!
+//! ## slcan adapters
+//!
+//! Adapters speaking the ASCII slcan protocol over a serial port are supported with the `slcan` feature.
+//!
+//! ```ignore
+//! # tokio_test::block_on(async {
+//! use async_can::slcan;
+//!
+//! let (sender, receiver) = slcan::connect("/dev/ttyACM0", 500000).await.unwrap();
+//! # });
+//! ```
+//!
+//! ## socketcand
+//!
+//! A CAN bus of a remote Linux machine running [socketcand](https://github.com/linux-can/socketcand) can be accessed
+//! over TCP with the `socketcand` feature.
+//!
+//! ```ignore
+//! # tokio_test::block_on(async {
+//! use async_can::socketcand;
+//!
+//! let (sender, receiver) = socketcand::connect("192.168.1.10:29536", "can0").await.unwrap();
+//! # });
+//! ```
+//!
 //! ## Listing CAN devices
 //!
 //! ```no_run
@@ -69,18 +94,68 @@
 //!
 //! This allows serializing the [`Message`] and related type.
 //!
+//! ## Tracing Support
+//!
+//! ```toml
+//! async-can = {version = "*", features = ["tracing"]}
+//! ```
+//!
+//! All backends then emit a `TRACE` event with the ID, DLC and extended flag of each message sent or received,
+//! and a `WARN` event for each bus error. Without a `tracing` subscriber, the events are forwarded to `log`.
 //!
 #![allow(dead_code)]
 
 use async_trait::async_trait;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
 use std::io;
 use std::result::Result as StdResult;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 
 #[cfg(feature = "usr_canet")]
 pub mod usr_canet;
 
+#[cfg(feature = "slcan")]
+pub mod slcan;
+
+#[cfg(feature = "socketcand")]
+pub mod socketcand;
+
+pub mod blocking;
+pub mod bridge;
+pub mod busload;
+pub mod cyclic;
+#[cfg(feature = "dbc")]
+pub mod dbc;
+pub mod dedup;
+pub mod device;
+pub mod ext;
+pub mod fanout;
+pub mod filter;
+#[cfg(feature = "serde")]
+pub mod hex;
+pub mod isotp;
+pub mod j1939;
+pub mod logger;
 pub mod loopback;
+pub mod merge;
+pub mod observer;
+pub mod ratelimit;
+pub mod reconnect;
+pub mod replay;
+pub mod retry;
+pub mod signal;
+pub mod stats;
+mod trace;
+pub mod uri;
+
+pub use device::Device;
+pub use ext::{ReceiverExt, SenderExt};
+pub use uri::{connect, recv_once, send_once};
 
 #[cfg(feature = "serde")]
 use serde::{de::Error as SerdeDeError, Deserialize, Deserializer, Serialize};
@@ -94,11 +169,112 @@ pub const CAN_STD_ID_MASK: u32 = 0x7FF;
 /// Maximum data length or dlc in a CAN message
 pub const CAN_MAX_DLC: usize = 8;
 
+/// Maximum data length in a CAN-FD message
+pub const CANFD_MAX_DLC: usize = 64;
+
+const FD_DLC_TO_LEN: [usize; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 12, 16, 20, 24, 32, 48, 64];
+
+/// Convert a CAN-FD DLC code to the length of the data field.
+///
+/// Only the lower 4 bits of `dlc` are considered, i.e. DLC 9 to 15 map to 12, 16, 20, 24, 32, 48 and 64 bytes.
+pub fn dlc_to_len(dlc: u8) -> usize {
+    FD_DLC_TO_LEN[(dlc & 0xF) as usize]
+}
+
+/// Convert the length of a CAN-FD data field to its DLC code.
+///
+/// Returns `None` if the length cannot be represented in a CAN-FD frame.
+pub fn len_to_dlc(len: usize) -> Option<u8> {
+    FD_DLC_TO_LEN
+        .iter()
+        .position(|&x| x == len)
+        .map(|dlc| dlc as u8)
+}
+
+/// A validated CAN ID, either a standard 11-bit or an extended 29-bit ID.
+///
+/// IDs are ordered by their priority during arbitration, i.e. `a < b` if a frame with ID `a` wins the
+/// arbitration against a frame with ID `b`: The 11 most significant bits are compared first. If they are
+/// equal, a standard ID wins against an extended ID, since the IDE bit is dominant for standard frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CanId {
+    id: u32,
+    ext_id: bool,
+}
+
+impl CanId {
+    /// Create an ID, returns an error in case the ID is out of range.
+    pub fn new(id: u32, ext_id: bool) -> StdResult<Self, CanFrameError> {
+        CanFrameError::validate_id(id, ext_id)?;
+        Ok(Self { id, ext_id })
+    }
+
+    /// Create a standard 11-bit ID, returns an error in case the ID is out of range.
+    pub fn standard(id: u16) -> StdResult<Self, CanFrameError> {
+        Self::new(id as u32, false)
+    }
+
+    /// Create an extended 29-bit ID, returns an error in case the ID is out of range.
+    pub fn extended(id: u32) -> StdResult<Self, CanFrameError> {
+        Self::new(id, true)
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn ext_id(&self) -> bool {
+        self.ext_id
+    }
+
+    /// Returns the 11 bits which are transmitted first, i.e. the whole standard ID or the base ID of an
+    /// extended ID.
+    fn base_id(&self) -> u32 {
+        if self.ext_id {
+            self.id >> 18
+        } else {
+            self.id
+        }
+    }
+}
+
+impl Ord for CanId {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.base_id(), self.ext_id, self.id).cmp(&(other.base_id(), other.ext_id, other.id))
+    }
+}
+
+impl PartialOrd for CanId {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl From<CanId> for (u32, bool) {
+    fn from(id: CanId) -> Self {
+        (id.id, id.ext_id)
+    }
+}
+
+impl TryFrom<(u32, bool)> for CanId {
+    type Error = CanFrameError;
+
+    fn try_from((id, ext_id): (u32, bool)) -> StdResult<Self, Self::Error> {
+        CanId::new(id, ext_id)
+    }
+}
+
+impl fmt::Display for CanId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_id(f, self.id, self.ext_id)
+    }
+}
+
 pub(crate) mod base {
     #[cfg(feature = "serde")]
     use serde::{Deserialize, Serialize};
 
-    #[derive(Debug, Clone, Eq, PartialEq)]
+    #[derive(Debug, Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
     #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub(crate) struct DataFrame {
         pub(crate) id: u32,
@@ -106,17 +282,29 @@ pub(crate) mod base {
         pub(crate) data: Vec<u8>,
     }
 
-    #[derive(Debug, Clone, Eq, PartialEq)]
+    #[derive(Debug, Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
     #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub(crate) struct RemoteFrame {
         pub(crate) id: u32,
         pub(crate) ext_id: bool,
         pub(crate) dlc: u8,
     }
+
+    #[derive(Debug, Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub(crate) struct CanFdFrame {
+        pub(crate) id: u32,
+        pub(crate) ext_id: bool,
+        pub(crate) brs: bool,
+        pub(crate) esi: bool,
+        pub(crate) data: Vec<u8>,
+    }
 }
 
 /// A CAN data frame, i.e. the RTR bit is set to 0
-#[derive(Debug, Clone, Eq, PartialEq)]
+///
+/// Frames are ordered by the priority of their [`CanId`] first.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct DataFrame(base::DataFrame);
 
@@ -155,6 +343,12 @@ impl DataFrame {
     pub fn ext_id(&self) -> bool {
         self.0.ext_id
     }
+    pub fn can_id(&self) -> CanId {
+        CanId {
+            id: self.0.id,
+            ext_id: self.0.ext_id,
+        }
+    }
     pub fn data(&self) -> &[u8] {
         &self.0.data
     }
@@ -166,9 +360,50 @@ impl DataFrame {
     }
 }
 
+impl Ord for DataFrame {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.can_id()
+            .cmp(&other.can_id())
+            .then_with(|| self.0.cmp(&other.0))
+    }
+}
+
+impl PartialOrd for DataFrame {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Formats the CAN ID as in candump, i.e. with 3 hex digits for standard IDs and 8 for extended IDs.
+fn fmt_id(f: &mut fmt::Formatter<'_>, id: u32, ext_id: bool) -> fmt::Result {
+    if ext_id {
+        write!(f, "{:08X}", id)
+    } else {
+        write!(f, "{:03X}", id)
+    }
+}
+
+fn fmt_data(f: &mut fmt::Formatter<'_>, data: &[u8]) -> fmt::Result {
+    for x in data {
+        write!(f, "{:02X}", x)?;
+    }
+    Ok(())
+}
+
+/// Formats the frame in candump format, e.g. `123#DEADBEEF`.
+impl fmt::Display for DataFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_id(f, self.id(), self.ext_id())?;
+        f.write_str("#")?;
+        fmt_data(f, self.data())
+    }
+}
+
 /// A CAN remote frame, i.e. the RTR bit is set to 1. Also, this type of frame
 ///  does not have a data field.
-#[derive(Debug, Clone, Eq, PartialEq)]
+///
+/// Frames are ordered by the priority of their [`CanId`] first.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct RemoteFrame(base::RemoteFrame);
 
@@ -188,11 +423,43 @@ impl RemoteFrame {
     pub fn ext_id(&self) -> bool {
         self.0.ext_id
     }
+    pub fn can_id(&self) -> CanId {
+        CanId {
+            id: self.0.id,
+            ext_id: self.0.ext_id,
+        }
+    }
     pub fn dlc(&self) -> u8 {
         self.0.dlc
     }
 }
 
+impl Ord for RemoteFrame {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.can_id()
+            .cmp(&other.can_id())
+            .then_with(|| self.0.cmp(&other.0))
+    }
+}
+
+impl PartialOrd for RemoteFrame {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Formats the frame in candump format, e.g. `456#R3`. The DLC is omitted if it is 0.
+impl fmt::Display for RemoteFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_id(f, self.id(), self.ext_id())?;
+        f.write_str("#R")?;
+        if self.dlc() > 0 {
+            write!(f, "{}", self.dlc())?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(feature = "serde")]
 impl<'de> Deserialize<'de> for RemoteFrame {
     fn deserialize<D>(deserializer: D) -> StdResult<Self, <D as Deserializer<'de>>::Error>
@@ -212,21 +479,212 @@ impl<'de> Deserialize<'de> for RemoteFrame {
     }
 }
 
+/// A CAN-FD data frame with up to 64 bytes of payload.
+///
+/// The payload length must be one of the lengths which can be encoded in a CAN-FD DLC,
+/// i.e. 0 to 8, 12, 16, 20, 24, 32, 48 or 64 bytes.
+///
+/// Frames are ordered by the priority of their [`CanId`] first.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct CanFdFrame(base::CanFdFrame);
+
+impl CanFdFrame {
+    /// Create a new [`CanFdFrame`] and returns an error in case the ID is out of range or the data length
+    /// is not a valid CAN-FD length.
+    pub fn new(id: u32, ext_id: bool, brs: bool, data: Vec<u8>) -> StdResult<Self, CanFrameError> {
+        CanFrameError::validate_id(id, ext_id)?;
+        CanFrameError::validate_fd_len(data.len())?;
+        Ok(Self(base::CanFdFrame {
+            id,
+            ext_id,
+            brs,
+            esi: false,
+            data,
+        }))
+    }
+
+    pub fn id(&self) -> u32 {
+        self.0.id
+    }
+    pub fn ext_id(&self) -> bool {
+        self.0.ext_id
+    }
+    pub fn can_id(&self) -> CanId {
+        CanId {
+            id: self.0.id,
+            ext_id: self.0.ext_id,
+        }
+    }
+    pub fn data(&self) -> &[u8] {
+        &self.0.data
+    }
+    /// Returns the 4-bit DLC code encoding the length of the data field.
+    pub fn dlc(&self) -> u8 {
+        // the length is validated upon construction
+        len_to_dlc(self.0.data.len()).unwrap_or(15)
+    }
+    /// Returns true if the data phase is transmitted with the higher bit rate.
+    pub fn brs(&self) -> bool {
+        self.0.brs
+    }
+    /// Returns true if the transmitting node was error passive.
+    pub fn esi(&self) -> bool {
+        self.0.esi
+    }
+    /// Set the error state indicator, e.g. to replay a recorded frame. Controllers usually set it themselves
+    /// when transmitting.
+    pub fn with_esi(mut self, esi: bool) -> Self {
+        self.0.esi = esi;
+        self
+    }
+    pub fn take_data(self) -> Vec<u8> {
+        self.0.data
+    }
+}
+
+impl Ord for CanFdFrame {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.can_id()
+            .cmp(&other.can_id())
+            .then_with(|| self.0.cmp(&other.0))
+    }
+}
+
+impl PartialOrd for CanFdFrame {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Formats the frame in candump format, e.g. `123##1DEADBEEF`. The digit following `##` encodes the
+/// flags, with `1` for BRS and `2` for ESI.
+impl fmt::Display for CanFdFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_id(f, self.id(), self.ext_id())?;
+        let flags = u8::from(self.brs()) | (u8::from(self.esi()) << 1);
+        write!(f, "##{:X}", flags)?;
+        fmt_data(f, self.data())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for CanFdFrame {
+    fn deserialize<D>(deserializer: D) -> StdResult<Self, <D as Deserializer<'de>>::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        base::CanFdFrame::deserialize(deserializer).and_then(|x| {
+            if CanFrameError::validate_id(x.id, x.ext_id).is_err() {
+                return Err(D::Error::custom("CAN Id is too long"));
+            }
+            if CanFrameError::validate_fd_len(x.data.len()).is_err() {
+                Err(D::Error::custom("Data field has an invalid length"))
+            } else {
+                Ok(CanFdFrame(x))
+            }
+        })
+    }
+}
+
 /// A timestamp which defines when the CAN message was received on the bus.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Timestamp {
     pub micros: u64,
 }
 
-/// A message on the CAN bus, either a [`DataFrame`] or a [`RemoteFrame`].
+impl Timestamp {
+    pub fn from_micros(micros: u64) -> Timestamp {
+        Timestamp { micros }
+    }
+
+    pub fn as_micros(&self) -> u64 {
+        self.micros
+    }
+
+    /// Create a timestamp from a duration relative to the time base of the timestamp.
+    ///
+    /// Durations which do not fit into 64-bit microseconds saturate.
+    pub fn from_duration(duration: Duration) -> Timestamp {
+        Timestamp {
+            micros: u64::try_from(duration.as_micros()).unwrap_or(u64::MAX),
+        }
+    }
+
+    pub fn as_duration(&self) -> Duration {
+        Duration::from_micros(self.micros)
+    }
+
+    /// Create a timestamp from a wall-clock time, counting the microseconds since the Unix epoch.
+    ///
+    /// Returns `None` if `time` lies before the Unix epoch.
+    pub fn from_system_time(time: SystemTime) -> Option<Timestamp> {
+        let micros = time.duration_since(UNIX_EPOCH).ok()?.as_micros();
+        Some(Timestamp {
+            micros: u64::try_from(micros).ok()?,
+        })
+    }
+
+    /// Interpret the timestamp as microseconds since the Unix epoch and convert it to a wall-clock time.
+    ///
+    /// Note that this is only meaningful if the timestamp is actually in the wall-clock domain. For example,
+    /// the timestamps reported by PCAN devices are relative to the start of the driver.
+    pub fn to_system_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_micros(self.micros)
+    }
+}
+
+impl From<Duration> for Timestamp {
+    fn from(duration: Duration) -> Self {
+        Timestamp::from_duration(duration)
+    }
+}
+
+impl From<Timestamp> for Duration {
+    fn from(timestamp: Timestamp) -> Self {
+        timestamp.as_duration()
+    }
+}
+
+/// A message on the CAN bus, either a [`DataFrame`], a [`RemoteFrame`] or a [`CanFdFrame`].
 ///
-/// In the future this will also contain a CAN-FD frame type.
-#[derive(Debug, Clone, Eq, PartialEq)]
+/// Messages are ordered by their priority on the bus, such that a sorted list of messages reflects the order
+/// in which they would win the arbitration: First by [`CanId`], then data frames before remote frames, since
+/// the RTR bit is dominant for data frames. The remaining fields only break ties.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Message {
     Data(DataFrame),
     Remote(RemoteFrame),
+    Fd(CanFdFrame),
+}
+
+impl Ord for Message {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let key = |msg: &Message| {
+            (
+                msg.can_id(),
+                matches!(msg, Message::Remote(_)),
+                matches!(msg, Message::Fd(_)),
+            )
+        };
+        key(self)
+            .cmp(&key(other))
+            .then_with(|| match (self, other) {
+                (Message::Data(a), Message::Data(b)) => a.cmp(b),
+                (Message::Remote(a), Message::Remote(b)) => a.cmp(b),
+                (Message::Fd(a), Message::Fd(b)) => a.cmp(b),
+                // the variants are equal if the keys are equal
+                _ => Ordering::Equal,
+            })
+    }
+}
+
+impl PartialOrd for Message {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
 impl Message {
@@ -256,10 +714,27 @@ impl Message {
         })))
     }
 
+    /// Create a new message containing a CAN-FD frame. Returns an error in case the ID is out of range or the
+    /// data length is not a valid CAN-FD length.
+    pub fn new_fd(
+        id: u32,
+        ext_id: bool,
+        brs: bool,
+        data: &[u8],
+    ) -> StdResult<Message, CanFrameError> {
+        Ok(Message::Fd(CanFdFrame::new(
+            id,
+            ext_id,
+            brs,
+            data.to_vec(),
+        )?))
+    }
+
     pub fn id(&self) -> u32 {
         match self {
             Message::Data(x) => x.0.id,
             Message::Remote(x) => x.0.id,
+            Message::Fd(x) => x.0.id,
         }
     }
 
@@ -267,22 +742,203 @@ impl Message {
         match self {
             Message::Data(x) => x.0.ext_id,
             Message::Remote(x) => x.0.ext_id,
+            Message::Fd(x) => x.0.ext_id,
         }
     }
 
+    /// Returns the ID of the message as [`CanId`].
+    pub fn can_id(&self) -> CanId {
+        CanId {
+            id: self.id(),
+            ext_id: self.ext_id(),
+        }
+    }
+
+    /// Returns the DLC of the message. For [`Message::Fd`] this is the 4-bit DLC code
+    /// rather than the length of the data field.
     pub fn dlc(&self) -> u8 {
         match self {
             Message::Data(x) => x.dlc(),
             Message::Remote(x) => x.0.dlc,
+            Message::Fd(x) => x.dlc(),
+        }
+    }
+
+    /// Returns true if the numeric ID does not fit into a standard 11-bit ID.
+    ///
+    /// This is independent of [`Message::ext_id()`], i.e. a message with an extended ID may still
+    /// return false here if it could also be represented with a standard ID.
+    pub fn requires_extended(&self) -> bool {
+        self.id() > CAN_STD_ID_MASK
+    }
+
+    /// Encode the message into a compact byte representation, which is independent of the `serde` feature.
+    ///
+    /// The layout is:
+    ///  * 1 byte of flags: bit 0 for an extended ID, bit 1 for a remote frame, bit 2 for a CAN-FD frame,
+    ///    bit 3 for BRS and bit 4 for ESI. All other bits are reserved and set to 0.
+    ///  * 4 bytes of the ID in big endian
+    ///  * 1 byte of DLC, which is the 4-bit DLC code for CAN-FD frames
+    ///  * the data field, which is empty for remote frames
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (mut flags, data) = match self {
+            Message::Data(x) => (0, x.data()),
+            Message::Remote(_) => (WIRE_FLAG_RTR, &[][..]),
+            Message::Fd(x) => {
+                let mut flags = WIRE_FLAG_FD;
+                if x.brs() {
+                    flags |= WIRE_FLAG_BRS;
+                }
+                if x.esi() {
+                    flags |= WIRE_FLAG_ESI;
+                }
+                (flags, x.data())
+            }
+        };
+        if self.ext_id() {
+            flags |= WIRE_FLAG_EXT;
+        }
+        let mut ret = Vec::with_capacity(6 + data.len());
+        ret.push(flags);
+        ret.extend_from_slice(&self.id().to_be_bytes());
+        ret.push(self.dlc());
+        ret.extend_from_slice(data);
+        ret
+    }
+
+    /// Decode a message encoded with [`Message::to_bytes()`]. Returns an error if the length of `data` does not
+    /// match the DLC, reserved flags are set or the frame is invalid.
+    pub fn from_bytes(data: &[u8]) -> StdResult<Message, CanFrameError> {
+        if data.len() < 6 {
+            return Err(CanFrameError::InvalidFormat);
+        }
+        let flags = data[0];
+        let id = u32::from_be_bytes([data[1], data[2], data[3], data[4]]);
+        let dlc = data[5];
+        let payload = &data[6..];
+        let ext_id = flags & WIRE_FLAG_EXT != 0;
+        if flags & !WIRE_FLAG_MASK != 0 {
+            return Err(CanFrameError::InvalidFormat);
+        }
+        if flags & WIRE_FLAG_FD != 0 {
+            if flags & WIRE_FLAG_RTR != 0 || dlc > 15 || payload.len() != dlc_to_len(dlc) {
+                return Err(CanFrameError::InvalidFormat);
+            }
+            let frame = CanFdFrame::new(id, ext_id, flags & WIRE_FLAG_BRS != 0, payload.to_vec())?;
+            return Ok(Message::Fd(frame.with_esi(flags & WIRE_FLAG_ESI != 0)));
+        }
+        if flags & (WIRE_FLAG_BRS | WIRE_FLAG_ESI) != 0 {
+            return Err(CanFrameError::InvalidFormat);
+        }
+        if flags & WIRE_FLAG_RTR != 0 {
+            if !payload.is_empty() {
+                return Err(CanFrameError::InvalidFormat);
+            }
+            return Message::new_remote(id, ext_id, dlc);
+        }
+        if payload.len() != dlc as usize {
+            return Err(CanFrameError::InvalidFormat);
+        }
+        Message::new_data(id, ext_id, payload)
+    }
+}
+
+const WIRE_FLAG_EXT: u8 = 0x01;
+const WIRE_FLAG_RTR: u8 = 0x02;
+const WIRE_FLAG_FD: u8 = 0x04;
+const WIRE_FLAG_BRS: u8 = 0x08;
+const WIRE_FLAG_ESI: u8 = 0x10;
+const WIRE_FLAG_MASK: u8 = 0x1F;
+
+/// Formats the message in candump format, see the [`fmt::Display`] implementations of the individual frame types.
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Message::Data(x) => x.fmt(f),
+            Message::Remote(x) => x.fmt(f),
+            Message::Fd(x) => x.fmt(f),
+        }
+    }
+}
+
+/// Decode pairs of hex digits into bytes, as used by the text based protocols and formats.
+pub(crate) fn decode_hex(data: &[u8]) -> StdResult<Vec<u8>, CanFrameError> {
+    if !data.len().is_multiple_of(2) {
+        return Err(CanFrameError::InvalidFormat);
+    }
+    data.chunks(2)
+        .map(|x| {
+            let high = (x[0] as char).to_digit(16);
+            let low = (x[1] as char).to_digit(16);
+            match (high, low) {
+                (Some(high), Some(low)) => Ok((high << 4 | low) as u8),
+                _ => Err(CanFrameError::InvalidFormat),
+            }
+        })
+        .collect()
+}
+
+fn parse_data(data: &str) -> StdResult<Vec<u8>, CanFrameError> {
+    // cansend allows separating bytes with dots for readability
+    decode_hex(data.replace('.', "").as_bytes())
+}
+
+/// Parses a message in candump format, i.e. `<id>#<data>` for data frames, `<id>#R<dlc>` for remote frames
+/// and `<id>##<flags><data>` for CAN-FD frames. The DLC of remote frames is optional and defaults to 0.
+///
+/// IDs with up to 3 hex digits are standard IDs, IDs with 4 to 8 hex digits are extended IDs.
+impl FromStr for Message {
+    type Err = CanFrameError;
+
+    fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+        let (id, frame) = s
+            .trim()
+            .split_once('#')
+            .ok_or(CanFrameError::InvalidFormat)?;
+        if id.is_empty() || id.len() > 8 {
+            return Err(CanFrameError::InvalidFormat);
+        }
+        let ext_id = id.len() > 3;
+        let id = u32::from_str_radix(id, 16).map_err(|_| CanFrameError::InvalidFormat)?;
+        if let Some(frame) = frame.strip_prefix('#') {
+            let mut chars = frame.chars();
+            let flags = chars
+                .next()
+                .and_then(|x| x.to_digit(16))
+                .ok_or(CanFrameError::InvalidFormat)?;
+            let data = parse_data(chars.as_str())?;
+            let frame = CanFdFrame::new(id, ext_id, flags & 1 != 0, data)?;
+            Ok(Message::Fd(frame.with_esi(flags & 2 != 0)))
+        } else if let Some(dlc) = frame.strip_prefix('R') {
+            let dlc = match dlc {
+                "" => 0,
+                dlc => dlc.parse().map_err(|_| CanFrameError::InvalidFormat)?,
+            };
+            Message::new_remote(id, ext_id, dlc)
+        } else {
+            Message::new_data(id, ext_id, &parse_data(frame)?)
         }
     }
 }
 
 /// Encodes errors that may occur when attempting to create/validate CAN message fields.
-#[derive(Debug)]
+#[derive(Error, Debug)]
 pub enum CanFrameError {
+    #[error("CAN id is too long")]
     IdTooLong,
+    #[error("data field is too long")]
     DataTooLong,
+    #[error("data length is not a valid CAN-FD length")]
+    InvalidFdLength,
+    #[error("invalid frame format")]
+    InvalidFormat,
+}
+
+/// Maps to [`io::ErrorKind::InvalidInput`] with the [`CanFrameError`] as inner error.
+impl From<CanFrameError> for io::Error {
+    fn from(x: CanFrameError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, x)
+    }
 }
 
 impl From<CanFrameError> for crate::Error {
@@ -290,6 +946,8 @@ impl From<CanFrameError> for crate::Error {
         match x {
             CanFrameError::IdTooLong => Error::IdTooLong,
             CanFrameError::DataTooLong => Error::DataTooLong,
+            CanFrameError::InvalidFdLength => Error::InvalidFdLength,
+            CanFrameError::InvalidFormat => Error::InvalidFormat,
         }
     }
 }
@@ -305,6 +963,16 @@ impl CanFrameError {
         }
         Ok(())
     }
+
+    fn validate_fd_len(len: usize) -> StdResult<(), CanFrameError> {
+        if len > CANFD_MAX_DLC {
+            Err(CanFrameError::DataTooLong)
+        } else if len_to_dlc(len).is_none() {
+            Err(CanFrameError::InvalidFdLength)
+        } else {
+            Ok(())
+        }
+    }
 }
 
 /// This enum encodes errors/warning that may occur on the CAN bus
@@ -321,11 +989,35 @@ pub enum BusError {
     Off,
 }
 
+/// The state of a CAN controller with respect to the fault confinement of the CAN protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BusState {
+    ErrorActive,
+    ErrorWarning,
+    ErrorPassive,
+    BusOff,
+    Stopped,
+    Sleeping,
+}
+
+impl From<BusError> for BusState {
+    fn from(err: BusError) -> Self {
+        match err {
+            BusError::LightWarning | BusError::HeavyWarning => BusState::ErrorWarning,
+            BusError::Passive => BusState::ErrorPassive,
+            BusError::Off => BusState::BusOff,
+        }
+    }
+}
+
 /// Error type encoding all possible errors that may occur in this crate
-#[derive(Error, Debug)]
+///
+/// The error is cheap to clone, the contained [`io::Error`] is shared by means of an [`Arc`].
+#[derive(Error, Debug, Clone)]
 pub enum Error {
     #[error("Io Error: {0}")]
-    Io(io::Error),
+    Io(Arc<io::Error>),
     #[error("Invalid interface address")]
     InvalidInterfaceAddress,
     #[error("Invalid bitrate")]
@@ -344,18 +1036,82 @@ pub enum Error {
     IdTooLong,
     #[error("Data is too long")]
     DataTooLong,
+    #[error("Data length is not a valid CAN-FD length")]
+    InvalidFdLength,
+    #[error("Invalid frame format")]
+    InvalidFormat,
     #[error("Interface type was not recognized: {0}")]
     PCanUnknownInterfaceType(u16),
     #[error("Other PCAN Error {0}: `{1}`")]
     PCanOtherError(u32, String),
+    #[error("PCAN receive thread panicked: {0}")]
+    PCanReceiverPanicked(String),
+    #[error("Disconnected")]
+    Disconnected,
+    #[error("Operation timed out")]
+    Timeout,
+    #[error("Not supported: {0}")]
+    NotSupported(String),
     #[error("Other Error: {0}")]
     Other(String),
 }
 
 impl From<io::Error> for Error {
     fn from(x: io::Error) -> Self {
-        Error::Io(x)
+        // backends based on `io::Error`, such as SocketCAN, report bus errors wrapped in an `io::Error`
+        match x.get_ref().and_then(|err| err.downcast_ref::<BusError>()) {
+            Some(err) => Error::BusError(err.clone()),
+            None => Error::Io(Arc::new(x)),
+        }
+    }
+}
+
+impl Error {
+    /// Returns the kind of the underlying [`io::Error`], if any.
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        match self {
+            Error::Io(err) => Some(err.kind()),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the connection to the device or bus was lost, e.g. because a TCP connection
+    /// was closed, the CAN interface went down or the other end of a channel was dropped.
+    pub fn is_disconnected(&self) -> bool {
+        match self {
+            Error::Disconnected => true,
+            Error::Io(err) => {
+                matches!(
+                    err.kind(),
+                    io::ErrorKind::UnexpectedEof
+                        | io::ErrorKind::ConnectionReset
+                        | io::ErrorKind::ConnectionAborted
+                        | io::ErrorKind::BrokenPipe
+                        | io::ErrorKind::NotConnected
+                ) || is_interface_down(err)
+            }
+            _ => false,
+        }
     }
+
+    /// Returns true if an operation timed out, i.e. for [`Error::Timeout`] and I/O errors of kind
+    /// [`io::ErrorKind::TimedOut`].
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Error::Timeout) || self.io_kind() == Some(io::ErrorKind::TimedOut)
+    }
+}
+
+#[cfg(unix)]
+fn is_interface_down(err: &io::Error) -> bool {
+    matches!(
+        err.raw_os_error(),
+        Some(libc::ENETDOWN) | Some(libc::ENODEV)
+    )
+}
+
+#[cfg(not(unix))]
+fn is_interface_down(_err: &io::Error) -> bool {
+    false
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -366,6 +1122,13 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[async_trait]
 pub trait Sender {
     async fn send(&mut self, msg: Message) -> Result<()>;
+
+    /// Flush pending messages and close the connection, instead of relying on `Drop`.
+    ///
+    /// Sending after shutting down fails. The default implementation does nothing.
+    async fn shutdown(&mut self) -> Result<()> {
+        Ok(())
+    }
 }
 
 /// `#[async_trait]` that defines an interface to receive CAN messages.
@@ -374,6 +1137,13 @@ pub trait Sender {
 #[async_trait]
 pub trait Receiver {
     async fn recv(&mut self) -> Result<Message>;
+
+    /// Close the connection, instead of relying on `Drop`.
+    ///
+    /// Receiving after shutting down fails. The default implementation does nothing.
+    async fn shutdown(&mut self) -> Result<()> {
+        Ok(())
+    }
 }
 
 #[cfg(feature = "pcan")]
@@ -384,7 +1154,7 @@ pub mod socketcan;
 
 /// Captures CAN device information of devices connected to the host.
 #[derive(Clone, Debug)]
-#[cfg_attr(feature = "serde", derive(Deserialize))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct DeviceInfo {
     pub interface_name: String,
     pub is_ready: bool,
@@ -393,7 +1163,15 @@ pub struct DeviceInfo {
 
 #[cfg(test)]
 mod test {
-    use crate::CanFrameError;
+    use std::collections::HashSet;
+    use std::convert::TryFrom;
+    use std::io;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::{
+        decode_hex, dlc_to_len, len_to_dlc, BusError, CanFdFrame, CanFrameError, CanId, Error,
+        Message, StdResult, Timestamp,
+    };
 
     #[test]
     fn validate_id() {
@@ -408,4 +1186,316 @@ mod test {
             Err(CanFrameError::IdTooLong)
         ));
     }
+
+    #[test]
+    fn fd_frame() {
+        let frame = CanFdFrame::new(0x123, false, true, vec![0xAB; 64]).unwrap();
+        assert_eq!(frame.data().len(), 64);
+        assert_eq!(frame.dlc(), 15);
+        assert!(frame.brs());
+        assert!(!frame.esi());
+
+        let frame = CanFdFrame::new(0x123, true, false, vec![0; 12]).unwrap();
+        assert_eq!(frame.dlc(), 9);
+        assert!(!frame.brs());
+
+        assert!(matches!(
+            CanFdFrame::new(0x123, false, false, vec![0; 9]),
+            Err(CanFrameError::InvalidFdLength)
+        ));
+        assert!(matches!(
+            CanFdFrame::new(0x123, false, false, vec![0; 65]),
+            Err(CanFrameError::DataTooLong)
+        ));
+        assert!(matches!(
+            CanFdFrame::new(0x800, false, false, vec![]),
+            Err(CanFrameError::IdTooLong)
+        ));
+    }
+
+    #[test]
+    fn fd_dlc_mapping() {
+        for dlc in 0..=15 {
+            assert_eq!(len_to_dlc(dlc_to_len(dlc)), Some(dlc));
+        }
+        assert_eq!(dlc_to_len(8), 8);
+        assert_eq!(dlc_to_len(9), 12);
+        assert_eq!(dlc_to_len(15), 64);
+        assert_eq!(len_to_dlc(48), Some(14));
+        assert_eq!(len_to_dlc(9), None);
+        assert_eq!(len_to_dlc(65), None);
+    }
+
+    #[test]
+    fn new_fd() {
+        let msg = Message::new_fd(0x1234, true, true, &[0x55; 48]).unwrap();
+        assert!(matches!(msg, Message::Fd(_)));
+        assert_eq!(msg.id(), 0x1234);
+        assert!(msg.ext_id());
+        assert_eq!(msg.dlc(), 14);
+        assert!(Message::new_fd(0x1234, false, true, &[]).is_err());
+        assert!(Message::new_fd(0x123, false, true, &[0; 10]).is_err());
+    }
+
+    #[test]
+    fn requires_extended() {
+        let msg = Message::new_data(0x7FF, false, &[]).unwrap();
+        assert!(!msg.requires_extended());
+        let msg = Message::new_data(0x7FF, true, &[]).unwrap();
+        assert!(!msg.requires_extended());
+        let msg = Message::new_remote(0x800, true, 0).unwrap();
+        assert!(msg.requires_extended());
+    }
+
+    #[test]
+    fn timestamp_system_time() {
+        let ts = Timestamp::from_system_time(UNIX_EPOCH).unwrap();
+        assert_eq!(ts.micros, 0);
+        assert_eq!(ts.to_system_time(), UNIX_EPOCH);
+
+        let time = UNIX_EPOCH + Duration::from_micros(1);
+        let ts = Timestamp::from_system_time(time).unwrap();
+        assert_eq!(ts.micros, 1);
+        assert_eq!(ts.to_system_time(), time);
+
+        let before_epoch = UNIX_EPOCH - Duration::from_micros(1);
+        assert!(Timestamp::from_system_time(before_epoch).is_none());
+    }
+
+    #[test]
+    fn bus_error_from_io_error() {
+        let err = io::Error::other(BusError::Passive);
+        assert!(matches!(
+            Error::from(err),
+            Error::BusError(BusError::Passive)
+        ));
+        let err = io::Error::other("other");
+        assert!(matches!(Error::from(err), Error::Io(_)));
+    }
+
+    #[test]
+    fn clone_error() {
+        let err = Error::from(io::Error::other("failed"));
+        let cloned = err.clone();
+        assert_eq!(err.to_string(), "Io Error: failed");
+        assert_eq!(cloned.to_string(), err.to_string());
+    }
+
+    #[test]
+    fn error_kinds() {
+        assert!(Error::Disconnected.is_disconnected());
+        let err = Error::from(io::Error::from(io::ErrorKind::UnexpectedEof));
+        assert!(err.is_disconnected());
+        assert_eq!(err.io_kind(), Some(io::ErrorKind::UnexpectedEof));
+        let err = Error::from(io::Error::from(io::ErrorKind::TimedOut));
+        assert!(err.is_timeout());
+        assert!(!err.is_disconnected());
+        assert!(Error::Timeout.is_timeout());
+        assert!(!Error::NotSupported("CAN-FD".to_string()).is_timeout());
+        assert!(!Error::TransmitQueueFull.is_disconnected());
+        assert_eq!(Error::TransmitQueueFull.io_kind(), None);
+    }
+
+    #[test]
+    fn display_candump() {
+        let msg = Message::new_data(0x123, false, &[0xDE, 0xAD, 0xBE, 0xEF]).unwrap();
+        assert_eq!(msg.to_string(), "123#DEADBEEF");
+        let msg = Message::new_data(0x1234, true, &[]).unwrap();
+        assert_eq!(msg.to_string(), "00001234#");
+        let msg = Message::new_remote(0x456, false, 3).unwrap();
+        assert_eq!(msg.to_string(), "456#R3");
+        let msg = Message::new_remote(0x1, true, 0).unwrap();
+        assert_eq!(msg.to_string(), "00000001#R");
+        let msg = Message::new_fd(0x12, false, true, &[0x01, 0x02]).unwrap();
+        assert_eq!(msg.to_string(), "012##10102");
+    }
+
+    #[test]
+    fn hex_data() {
+        assert_eq!(decode_hex(b"0aFF").unwrap(), vec![0x0A, 0xFF]);
+        assert!(decode_hex(b"").unwrap().is_empty());
+        assert!(decode_hex(b"0aF").is_err());
+        assert!(decode_hex(b"0g").is_err());
+        assert!(decode_hex("ä1".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn parse_candump() {
+        let msg: Message = "12A#11223344".parse().unwrap();
+        assert_eq!(
+            msg,
+            Message::new_data(0x12A, false, &[0x11, 0x22, 0x33, 0x44]).unwrap()
+        );
+        let msg: Message = "1F334455#R".parse().unwrap();
+        assert_eq!(msg, Message::new_remote(0x1F334455, true, 0).unwrap());
+        let msg: Message = "456#R3".parse().unwrap();
+        assert_eq!(msg, Message::new_remote(0x456, false, 3).unwrap());
+        let msg: Message = "00000123#de.ad".parse().unwrap();
+        assert_eq!(msg, Message::new_data(0x123, true, &[0xDE, 0xAD]).unwrap());
+        let msg: Message = "123##3AB".parse().unwrap();
+        assert_eq!(msg.to_string(), "123##3AB");
+        let msg: Message = "7FF#".parse().unwrap();
+        assert_eq!(msg, Message::new_data(0x7FF, false, &[]).unwrap());
+
+        for msg in ["123#DEADBEEF", "00001234#", "456#R3", "012##10102"] {
+            assert_eq!(msg.parse::<Message>().unwrap().to_string(), msg);
+        }
+
+        assert!(matches!(
+            "800#".parse::<Message>(),
+            Err(CanFrameError::IdTooLong)
+        ));
+        assert!(matches!(
+            "123#112233445566778899".parse::<Message>(),
+            Err(CanFrameError::DataTooLong)
+        ));
+        assert!(matches!(
+            "123##0112233445566778899".parse::<Message>(),
+            Err(CanFrameError::InvalidFdLength)
+        ));
+        for msg in [
+            "123",
+            "#11",
+            "123#1",
+            "123#XY",
+            "123456789#",
+            "123#R9X",
+            "123##",
+        ] {
+            assert!(matches!(
+                msg.parse::<Message>(),
+                Err(CanFrameError::InvalidFormat)
+            ));
+        }
+    }
+
+    #[test]
+    fn can_id() {
+        assert!(CanId::standard(0x800).is_err());
+        assert!(CanId::extended(0x2000_0000).is_err());
+        let id = CanId::try_from((0x123, true)).unwrap();
+        assert_eq!(<(u32, bool)>::from(id), (0x123, true));
+        assert_eq!(id.to_string(), "00000123");
+        assert_eq!(CanId::standard(0x12).unwrap().to_string(), "012");
+        let msg = Message::new_data(0x1FFFFFFF, true, &[]).unwrap();
+        assert_eq!(msg.can_id(), CanId::extended(0x1FFFFFFF).unwrap());
+
+        let mut ids = [
+            CanId::extended(0x1FFFFFFF).unwrap(),
+            CanId::standard(0x101).unwrap(),
+            CanId::extended(0x100 << 18).unwrap(),
+            CanId::standard(0x100).unwrap(),
+            CanId::extended(0x0FF << 18 | 0x3FFFF).unwrap(),
+            CanId::extended((0x100 << 18) + 1).unwrap(),
+        ];
+        ids.sort();
+        assert_eq!(
+            ids.map(|id| (id.id(), id.ext_id())),
+            [
+                (0x0FF << 18 | 0x3FFFF, true),
+                (0x100, false),
+                (0x100 << 18, true),
+                ((0x100 << 18) + 1, true),
+                (0x101, false),
+                (0x1FFFFFFF, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn frame_error() {
+        fn parse(id: u32) -> StdResult<Message, Box<dyn std::error::Error>> {
+            Ok(Message::new_data(id, false, &[])?)
+        }
+        assert_eq!(parse(0x800).unwrap_err().to_string(), "CAN id is too long");
+
+        let err = io::Error::from(CanFrameError::DataTooLong);
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(err.to_string(), "data field is too long");
+    }
+
+    #[test]
+    fn message_ordering() {
+        let mut msgs = vec![
+            Message::new_data(0x200, false, &[]).unwrap(),
+            Message::new_remote(0x100, false, 1).unwrap(),
+            Message::new_data(0x100 << 18, true, &[]).unwrap(),
+            Message::new_data(0x100, false, &[2]).unwrap(),
+            Message::new_fd(0x100, false, false, &[]).unwrap(),
+            Message::new_data(0x100, false, &[1]).unwrap(),
+        ];
+        msgs.sort();
+        let expected = vec![
+            Message::new_data(0x100, false, &[1]).unwrap(),
+            Message::new_data(0x100, false, &[2]).unwrap(),
+            Message::new_fd(0x100, false, false, &[]).unwrap(),
+            Message::new_remote(0x100, false, 1).unwrap(),
+            Message::new_data(0x100 << 18, true, &[]).unwrap(),
+            Message::new_data(0x200, false, &[]).unwrap(),
+        ];
+        assert_eq!(msgs, expected);
+
+        let set: HashSet<_> = msgs.into_iter().chain(expected).collect();
+        assert_eq!(set.len(), 6);
+    }
+
+    #[test]
+    fn wire_encoding() {
+        let msg = Message::new_data(0x123, false, &[0xAB, 0xCD]).unwrap();
+        assert_eq!(msg.to_bytes(), [0x00, 0, 0, 0x01, 0x23, 2, 0xAB, 0xCD]);
+        let msg = Message::new_remote(0x1234567, true, 5).unwrap();
+        assert_eq!(msg.to_bytes(), [0x03, 0x01, 0x23, 0x45, 0x67, 5]);
+
+        let msgs = [
+            Message::new_data(0x7FF, false, &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap(),
+            Message::new_remote(0x1FFFFFFF, true, 8).unwrap(),
+            Message::new_fd(0x123, true, true, &[0x55; 48]).unwrap(),
+            "012##2".parse().unwrap(),
+        ];
+        for msg in msgs {
+            assert_eq!(Message::from_bytes(&msg.to_bytes()).unwrap(), msg);
+        }
+        let frame = CanFdFrame::new(0x123, false, true, vec![0xAA; 12])
+            .unwrap()
+            .with_esi(true);
+        let msg = Message::Fd(frame);
+        let bytes = msg.to_bytes();
+        assert_eq!(bytes[..6], [0x1C, 0, 0, 0x01, 0x23, 9]);
+        match Message::from_bytes(&bytes).unwrap() {
+            Message::Fd(frame) => assert!(frame.brs() && frame.esi()),
+            _ => panic!("Expected a CAN-FD frame"),
+        }
+
+        for data in [
+            &[0x00, 0, 0, 0, 1][..],
+            &[0x00, 0, 0, 0, 1, 2, 0xAA],
+            &[0x20, 0, 0, 0, 1, 0],
+            &[0x08, 0, 0, 0, 1, 0],
+            &[0x02, 0, 0, 0, 1, 1, 0xAA],
+            &[0x06, 0, 0, 0, 1, 0],
+            &[0x04, 0, 0, 0, 1, 9, 0, 0, 0, 0, 0, 0, 0, 0],
+        ] {
+            assert!(matches!(
+                Message::from_bytes(data),
+                Err(CanFrameError::InvalidFormat)
+            ));
+        }
+        assert!(matches!(
+            Message::from_bytes(&[0x00, 0, 0, 0x08, 0, 0]),
+            Err(CanFrameError::IdTooLong)
+        ));
+    }
+
+    #[test]
+    fn timestamp_duration() {
+        let ts = Timestamp::from(Duration::from_millis(1500));
+        assert_eq!(ts, Timestamp::from_micros(1_500_000));
+        assert_eq!(ts.as_micros(), 1_500_000);
+        assert_eq!(Duration::from(ts.clone()), Duration::from_millis(1500));
+        assert!(Timestamp::from_micros(1) < ts);
+        assert_eq!(
+            Timestamp::from_duration(Duration::MAX),
+            Timestamp::from_micros(u64::MAX)
+        );
+    }
 }
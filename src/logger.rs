@@ -0,0 +1,216 @@
+//! This module implements recording received CAN frames to a log file in `candump -L` format.
+//!
+//! The resulting files can be replayed with [`crate::replay::FileReceiver`] or the `canplayer` tool
+//! of can-utils.
+
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use tokio::fs::File;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::time::Instant;
+
+use crate::replay::LogEntry;
+use crate::{Message, Receiver, Result, Timestamp};
+
+/// Default interval after which buffered log lines are flushed to the file.
+const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Wraps a [`crate::Receiver`] and writes every received message to a log file in `candump -L` format.
+///
+/// The messages are timestamped with the wall-clock time when they are received and returned to the
+/// caller unchanged. Errors of the wrapped receiver are passed through without being logged. If writing
+/// to the log file fails, the error is returned instead of the message.
+///
+/// The log lines are buffered and flushed to the file at least every second, which may be changed with
+/// [`LoggingReceiver::flush_interval()`]. Buffered lines are also flushed while waiting for the next message,
+/// such that they are written in time even if the bus goes quiet.
+///
+/// Receiving is cancel-safe: a message received from the wrapped receiver is kept until it was logged and
+/// returned, and its log line is written exactly once.
+pub struct LoggingReceiver<R> {
+    inner: R,
+    file: BufWriter<File>,
+    interface: String,
+    flush_interval: Duration,
+    last_flush: Instant,
+    /// True if log lines were written since the last flush
+    dirty: bool,
+    pending: Option<PendingLine>,
+}
+
+/// A received message whose log line is being written
+struct PendingLine {
+    msg: Message,
+    line: Vec<u8>,
+    /// Number of bytes of `line` already written to the file
+    written: usize,
+}
+
+impl<R> LoggingReceiver<R> {
+    /// Wrap `inner` and log to a newly created file at `path`. An existing file is truncated.
+    ///
+    /// `interface` is the interface name written to each line, e.g. `can0`.
+    pub async fn create<P: AsRef<Path>>(inner: R, path: P, interface: &str) -> Result<Self> {
+        let file = File::create(path).await?;
+        Ok(Self {
+            inner,
+            file: BufWriter::new(file),
+            interface: interface.to_string(),
+            flush_interval: DEFAULT_FLUSH_INTERVAL,
+            last_flush: Instant::now(),
+            dirty: false,
+            pending: None,
+        })
+    }
+
+    /// Change the interval after which buffered log lines are flushed to the file.
+    pub fn flush_interval(mut self, flush_interval: Duration) -> Self {
+        self.flush_interval = flush_interval;
+        self
+    }
+
+    /// Flush all buffered log lines to the file.
+    pub async fn flush(&mut self) -> Result<()> {
+        self.file.flush().await?;
+        self.last_flush = Instant::now();
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Flush the log file and return the wrapped receiver.
+    pub async fn into_inner(mut self) -> Result<R> {
+        self.flush().await?;
+        Ok(self.inner)
+    }
+
+    fn pending_line(&self, msg: Message) -> PendingLine {
+        let entry = LogEntry {
+            timestamp: Timestamp::from_system_time(SystemTime::now())
+                .unwrap_or(Timestamp { micros: 0 }),
+            interface: self.interface.clone(),
+            msg,
+        };
+        PendingLine {
+            line: format!("{}\n", entry).into_bytes(),
+            msg: entry.msg,
+            written: 0,
+        }
+    }
+
+    /// Write the log line of the pending message and return the message. The message is dropped if
+    /// writing fails.
+    async fn write_pending(&mut self) -> Result<Message> {
+        match self.try_write_pending().await {
+            Ok(()) => Ok(self.pending.take().unwrap().msg),
+            Err(err) => {
+                self.pending = None;
+                Err(err)
+            }
+        }
+    }
+
+    async fn try_write_pending(&mut self) -> Result<()> {
+        let pending = self.pending.as_mut().unwrap();
+        // unlike `write_all()`, each `write()` is cancel-safe
+        while pending.written < pending.line.len() {
+            let written = self.file.write(&pending.line[pending.written..]).await?;
+            if written == 0 {
+                return Err(io::Error::from(io::ErrorKind::WriteZero).into());
+            }
+            pending.written += written;
+            self.dirty = true;
+        }
+        if self.last_flush.elapsed() >= self.flush_interval {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<R: Receiver + Send> Receiver for LoggingReceiver<R> {
+    async fn recv(&mut self) -> Result<Message> {
+        while self.pending.is_none() {
+            let msg = if self.dirty {
+                let deadline = self.last_flush + self.flush_interval;
+                match tokio::time::timeout_at(deadline, self.inner.recv()).await {
+                    Ok(msg) => msg?,
+                    Err(_) => {
+                        self.flush().await?;
+                        continue;
+                    }
+                }
+            } else {
+                self.inner.recv().await?
+            };
+            self.pending = Some(self.pending_line(msg));
+        }
+        self.write_pending().await
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        self.flush().await?;
+        self.inner.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ext::ReceiverExt;
+    use crate::replay::{FileReceiver, Timing};
+    use crate::{loopback, Sender};
+
+    #[tokio::test]
+    async fn log_and_replay() {
+        let path =
+            std::env::temp_dir().join(format!("async-can-logger-{}.log", std::process::id()));
+        let (mut tx, rx) = loopback::connect();
+        let mut rx = LoggingReceiver::create(rx, &path, "vcan0").await.unwrap();
+
+        let msgs = [
+            Message::new_data(0x123, false, &[1, 2, 3]).unwrap(),
+            Message::new_remote(0x1ABCDEF, true, 2).unwrap(),
+        ];
+        for msg in &msgs {
+            tx.send(msg.clone()).await.unwrap();
+            assert_eq!(&rx.recv().await.unwrap(), msg);
+        }
+        rx.into_inner().await.unwrap();
+
+        let mut replay = FileReceiver::open(&path, Timing::AsFastAsPossible)
+            .await
+            .unwrap();
+        for msg in &msgs {
+            let entry = replay.recv_entry().await.unwrap();
+            assert_eq!(&entry.msg, msg);
+            assert_eq!(entry.interface, "vcan0");
+        }
+        assert!(replay.recv().await.unwrap_err().is_disconnected());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn flush_while_idle() {
+        let path =
+            std::env::temp_dir().join(format!("async-can-logger-idle-{}.log", std::process::id()));
+        let (mut tx, rx) = loopback::connect();
+        let mut rx = LoggingReceiver::create(rx, &path, "vcan0")
+            .await
+            .unwrap()
+            .flush_interval(Duration::from_millis(50));
+        let msg = Message::new_data(0x123, false, &[1, 2, 3]).unwrap();
+        tx.send(msg.clone()).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap(), msg);
+
+        // no further traffic, the buffered line is flushed while waiting
+        let timeout = Duration::from_millis(200);
+        assert_eq!(rx.recv_timeout(timeout).await.unwrap(), None);
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.ends_with(" vcan0 123#010203\n"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,40 +1,496 @@
 //! This module implements "dummy" loopback deviec. This is mostly intended for testing.
 
+use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use async_trait::async_trait;
+use tokio::sync::broadcast;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::time::Instant;
 
-use crate::Message;
+use crate::busload::frame_bits;
+use crate::{trace, BusError, Message, Timestamp};
+
+/// A message or an injected error in flight from a [`Sender`] to a [`Receiver`].
+struct Delivery {
+    msg: Result<Message, BusError>,
+    timestamp: Timestamp,
+    /// Time at which the frame was completely transmitted on the simulated bus
+    deliver_at: Option<Instant>,
+}
+
+/// The simulated bus of a loopback device created with [`connect_with_bitrate()`].
+struct SimulatedBus {
+    bitrate: NonZeroU32,
+    /// Time at which the frame transmitted last is completed
+    idle_at: Mutex<Instant>,
+}
 
-#[derive(Clone)]
 pub struct Sender {
-    tx: UnboundedSender<Message>,
+    tx: UnboundedSender<Delivery>,
+    start: Instant,
+    bus: Option<Arc<SimulatedBus>>,
+    drop_rate: f32,
+    rng_state: u64,
 }
 
 pub struct Receiver {
-    rx: UnboundedReceiver<Message>,
+    rx: UnboundedReceiver<Delivery>,
+    /// The delivery taken from the channel, which is kept until it is yielded such that receiving is cancel-safe
+    pending: Option<Delivery>,
 }
 
 /// Create a connected [`Sender`] and [`Receiver`] using an MPSC channel.
+///
+/// Messages, including [`Message::Fd`] frames, are passed through unchanged.
 pub fn connect() -> (Sender, Receiver) {
     let (tx, rx) = unbounded_channel();
-    (Sender { tx }, Receiver { rx })
+    let start = Instant::now();
+    (
+        Sender {
+            tx,
+            start,
+            bus: None,
+            drop_rate: 0.0,
+            rng_state: random_seed(),
+        },
+        Receiver { rx, pending: None },
+    )
+}
+
+/// Create a connected [`Sender`] and [`Receiver`] which simulate the transmission time of the frames on a bus
+/// with the given bitrate.
+///
+/// Sending does not block, but the receiver only yields a frame once the time required to transmit it, as
+/// computed by [`crate::busload::frame_bits()`], has elapsed. Frames sent while the simulated bus is busy are
+/// queued and delivered in order.
+pub fn connect_with_bitrate(bitrate: NonZeroU32) -> (Sender, Receiver) {
+    let (mut tx, rx) = connect();
+    tx.bus = Some(Arc::new(SimulatedBus {
+        bitrate,
+        idle_at: Mutex::new(tx.start),
+    }));
+    (tx, rx)
+}
+
+fn random_seed() -> u64 {
+    // seeds requested within the same clock tick still differ thanks to the counter
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.subsec_nanos())
+        .unwrap_or(0);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    // the state of the xorshift generator must not be zero
+    (nanos as u64 ^ count.wrapping_mul(0x9E37_79B9_7F4A_7C15)) | 1
+}
+
+impl Clone for Sender {
+    /// Clones the sender, which sends to the same [`Receiver`]. The clone drops messages at the same rate but
+    /// with its own random sequence.
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            start: self.start,
+            bus: self.bus.clone(),
+            drop_rate: self.drop_rate,
+            rng_state: random_seed(),
+        }
+    }
+}
+
+impl Sender {
+    /// Make the paired [`Receiver`] fail with [`crate::Error::BusError`] after it received all messages sent
+    /// before.
+    pub fn inject_error(&self, err: BusError) -> crate::Result<()> {
+        let delivery = Delivery {
+            msg: Err(err),
+            timestamp: Timestamp::from_duration(self.start.elapsed()),
+            deliver_at: None,
+        };
+        self.tx
+            .send(delivery)
+            .map_err(|_| crate::Error::Disconnected)
+    }
+
+    /// Randomly drop the given fraction of the messages sent with this sender, e.g. `0.1` to lose
+    /// every tenth message on average. Dropped messages are reported as sent successfully.
+    ///
+    /// Panics if `drop_rate` is not within `0.0..=1.0`.
+    pub fn set_drop_rate(&mut self, drop_rate: f32) {
+        assert!(
+            (0.0..=1.0).contains(&drop_rate),
+            "The drop rate must be within 0.0..=1.0"
+        );
+        self.drop_rate = drop_rate;
+    }
+
+    /// Returns a uniformly distributed random number in `[0, 1)` using a xorshift generator.
+    fn random(&mut self) -> f32 {
+        self.rng_state ^= self.rng_state << 13;
+        self.rng_state ^= self.rng_state >> 7;
+        self.rng_state ^= self.rng_state << 17;
+        (self.rng_state >> 40) as f32 / (1_u64 << 24) as f32
+    }
 }
 
 #[async_trait]
 impl crate::Sender for Sender {
     async fn send(&mut self, msg: Message) -> crate::Result<()> {
+        trace::send("loopback", &msg);
+        if self.drop_rate > 0.0 && self.random() < self.drop_rate {
+            return Ok(());
+        }
+        let deliver_at = self.bus.as_ref().map(|bus| {
+            let micros = frame_bits(&msg) as u64 * 1_000_000 / bus.bitrate.get() as u64;
+            let mut idle_at = bus.idle_at.lock().unwrap();
+            *idle_at = (*idle_at).max(Instant::now()) + Duration::from_micros(micros);
+            *idle_at
+        });
+        let sent_at = deliver_at.unwrap_or_else(Instant::now);
+        let timestamp = Timestamp {
+            micros: (sent_at - self.start).as_micros() as u64,
+        };
+        let delivery = Delivery {
+            msg: Ok(msg),
+            timestamp,
+            deliver_at,
+        };
         self.tx
-            .send(msg)
-            .map_err(|_| crate::Error::Other(format!("Disconnected")))
+            .send(delivery)
+            .map_err(|_| crate::Error::Disconnected)
+    }
+}
+
+impl Receiver {
+    /// Receive a message together with the [`crate::Timestamp`] when it was sent.
+    ///
+    /// The timestamp is taken from a monotonic clock and counts the microseconds since the
+    /// loopback device was created with [`connect()`]. For devices created with [`connect_with_bitrate()`],
+    /// it refers to the time the transmission of the frame was completed.
+    ///
+    /// This is cancel-safe, a frame whose simulated transmission is not completed yet is kept for the next call.
+    pub async fn recv_with_timestamp(&mut self) -> crate::Result<(Message, Timestamp)> {
+        if self.pending.is_none() {
+            self.pending = Some(self.rx.recv().await.ok_or(crate::Error::Disconnected)?);
+        }
+        if let Some(deliver_at) = self.pending.as_ref().and_then(|x| x.deliver_at) {
+            tokio::time::sleep_until(deliver_at).await;
+        }
+        let delivery = self.pending.take().unwrap();
+        match delivery.msg {
+            Ok(msg) => {
+                trace::recv("loopback", &msg);
+                Ok((msg, delivery.timestamp))
+            }
+            Err(err) => {
+                trace::bus_error("loopback", &err);
+                Err(crate::Error::BusError(err))
+            }
+        }
     }
 }
 
 #[async_trait]
 impl crate::Receiver for Receiver {
     async fn recv(&mut self) -> crate::Result<Message> {
-        self.rx
-            .recv()
-            .await
-            .ok_or_else(|| crate::Error::Other(format!("Disconnected")))
+        self.recv_with_timestamp().await.map(|(msg, _)| msg)
+    }
+}
+
+/// Number of messages buffered on a broadcast [`Bus`] before slow receivers start missing messages.
+const BROADCAST_CAPACITY: usize = 1024;
+
+/// A message on a broadcast [`Bus`] together with the node that sent it.
+#[derive(Clone)]
+struct BusMessage {
+    msg: Message,
+    timestamp: Timestamp,
+    origin: usize,
+}
+
+/// A shared virtual bus on which every message sent is delivered to all receivers.
+///
+/// Created with [`broadcast()`]. Each node of the simulated bus is created with [`Bus::node()`], additional
+/// listeners receiving all messages can be attached with [`Bus::subscribe()`].
+#[derive(Clone)]
+pub struct Bus {
+    tx: broadcast::Sender<BusMessage>,
+    start: Instant,
+    next_node: Arc<AtomicUsize>,
+}
+
+/// A sender attached to a broadcast [`Bus`]. Implements [`crate::Sender`].
+///
+/// Clones of a sender belong to the same node.
+#[derive(Clone)]
+pub struct BroadcastSender {
+    tx: broadcast::Sender<BusMessage>,
+    start: Instant,
+    node: usize,
+}
+
+/// A receiver attached to a broadcast [`Bus`]. Implements [`crate::Receiver`].
+pub struct BroadcastReceiver {
+    rx: broadcast::Receiver<BusMessage>,
+    node: Option<usize>,
+    echo: bool,
+}
+
+/// Create a shared virtual bus and a first [`BroadcastSender`] attached to it.
+///
+/// Messages sent while no receiver is subscribed are dropped, similar to a frame on a bus without
+/// any other node.
+pub fn broadcast() -> (BroadcastSender, Bus) {
+    let (tx, _) = broadcast::channel(BROADCAST_CAPACITY);
+    let bus = Bus {
+        tx,
+        start: Instant::now(),
+        next_node: Arc::new(AtomicUsize::new(0)),
+    };
+    (bus.sender(), bus)
+}
+
+impl Bus {
+    /// Create a sender for a new node on the bus.
+    pub fn sender(&self) -> BroadcastSender {
+        BroadcastSender {
+            tx: self.tx.clone(),
+            start: self.start,
+            node: self.next_node.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// Create a receiver which receives all messages sent on the bus from now on.
+    pub fn subscribe(&self) -> BroadcastReceiver {
+        BroadcastReceiver {
+            rx: self.tx.subscribe(),
+            node: None,
+            echo: true,
+        }
+    }
+
+    /// Create a new node on the bus, consisting of a sender and a receiver.
+    ///
+    /// If `echo` is `false`, the receiver does not receive the messages sent by the sender of the same node,
+    /// as is the case for most CAN controllers.
+    pub fn node(&self, echo: bool) -> (BroadcastSender, BroadcastReceiver) {
+        let tx = self.sender();
+        let rx = BroadcastReceiver {
+            rx: self.tx.subscribe(),
+            node: Some(tx.node),
+            echo,
+        };
+        (tx, rx)
+    }
+}
+
+#[async_trait]
+impl crate::Sender for BroadcastSender {
+    async fn send(&mut self, msg: Message) -> crate::Result<()> {
+        trace::send("loopback", &msg);
+        let timestamp = Timestamp {
+            micros: self.start.elapsed().as_micros() as u64,
+        };
+        // fails only if there is no receiver, in which case the message is dropped
+        let _ = self.tx.send(BusMessage {
+            msg,
+            timestamp,
+            origin: self.node,
+        });
+        Ok(())
+    }
+}
+
+impl BroadcastReceiver {
+    /// Receive a message together with the [`crate::Timestamp`] when it was sent.
+    ///
+    /// The timestamp counts the microseconds since the bus was created with [`broadcast()`].
+    pub async fn recv_with_timestamp(&mut self) -> crate::Result<(Message, Timestamp)> {
+        loop {
+            match self.rx.recv().await {
+                Ok(x) if self.echo || self.node != Some(x.origin) => {
+                    trace::recv("loopback", &x.msg);
+                    return Ok((x.msg, x.timestamp));
+                }
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    log::warn!("Broadcast receiver lagged behind, {} messages lost", count);
+                }
+                Err(broadcast::error::RecvError::Closed) => return Err(crate::Error::Disconnected),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl crate::Receiver for BroadcastReceiver {
+    async fn recv(&mut self) -> crate::Result<Message> {
+        self.recv_with_timestamp().await.map(|(msg, _)| msg)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::num::NonZeroU32;
+
+    use crate::ext::ReceiverExt;
+    use crate::{BusError, Message, Receiver, Sender};
+
+    #[tokio::test]
+    async fn broadcast_to_all_nodes() {
+        let (mut tx, bus) = super::broadcast();
+        let mut monitor = bus.subscribe();
+        let (mut tx_a, mut rx_a) = bus.node(false);
+        let (_, mut rx_b) = bus.node(true);
+
+        let msg_a = Message::new_data(0x1, false, &[1]).unwrap();
+        let msg = Message::new_data(0x2, false, &[2]).unwrap();
+        tx_a.send(msg_a.clone()).await.unwrap();
+        tx.send(msg.clone()).await.unwrap();
+
+        // node a does not receive its own message
+        assert_eq!(rx_a.recv().await.unwrap(), msg);
+        for rx in [&mut monitor, &mut rx_b] {
+            assert_eq!(rx.recv().await.unwrap(), msg_a);
+            assert_eq!(rx.recv().await.unwrap(), msg);
+        }
+
+        drop((tx, tx_a, bus));
+        assert!(rx_b.recv().await.unwrap_err().is_disconnected());
+    }
+
+    #[tokio::test]
+    async fn fd_round_trip() {
+        let (mut tx, mut rx) = super::connect();
+        let data: Vec<u8> = (0..64).collect();
+        let tx_msg = Message::new_fd(0x1ABCDEF, true, true, &data).unwrap();
+        tx.send(tx_msg.clone()).await.unwrap();
+        let rx_msg = rx.recv().await.unwrap();
+        assert_eq!(tx_msg, rx_msg);
+
+        let frame = crate::CanFdFrame::new(0x123, false, true, data)
+            .unwrap()
+            .with_esi(true);
+        tx.send(Message::Fd(frame)).await.unwrap();
+        match rx.recv().await.unwrap() {
+            Message::Fd(frame) => assert!(frame.brs() && frame.esi()),
+            _ => panic!("Expected a CAN-FD frame"),
+        }
+    }
+
+    #[tokio::test]
+    async fn timestamps_increase() {
+        let (mut tx, mut rx) = super::connect();
+        for id in 1..=2 {
+            tx.send(Message::new_data(id, false, &[]).unwrap())
+                .await
+                .unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+        let (msg, first) = rx.recv_with_timestamp().await.unwrap();
+        assert_eq!(msg.id(), 1);
+        let (msg, second) = rx.recv_with_timestamp().await.unwrap();
+        assert_eq!(msg.id(), 2);
+        assert!(second.micros >= first.micros + 1000);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn simulated_bitrate() {
+        let (mut tx, mut rx) = super::connect_with_bitrate(NonZeroU32::new(125_000).unwrap());
+        let start = tokio::time::Instant::now();
+        // 135 bits with worst-case stuffing take 1080us at 125 kbit/s
+        let msg = Message::new_data(0x123, false, &[0; 8]).unwrap();
+        for _ in 0..3 {
+            tx.send(msg.clone()).await.unwrap();
+        }
+        assert_eq!(start.elapsed(), std::time::Duration::ZERO);
+        for k in 1..=3 {
+            let (_, timestamp) = rx.recv_with_timestamp().await.unwrap();
+            assert_eq!(timestamp.micros, k * 1080);
+            assert!(start.elapsed() >= std::time::Duration::from_micros(k * 1080));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cancel_delayed_recv() {
+        let (mut tx, mut rx) = super::connect_with_bitrate(NonZeroU32::new(125_000).unwrap());
+        let msg = Message::new_data(0x123, false, &[0; 8]).unwrap();
+        tx.send(msg.clone()).await.unwrap();
+        // cancelled during the simulated transmission of 1080us
+        let timeout = std::time::Duration::from_micros(500);
+        assert_eq!(rx.recv_timeout(timeout).await.unwrap(), None);
+        let (received, timestamp) = rx.recv_with_timestamp().await.unwrap();
+        assert_eq!(received, msg);
+        assert_eq!(timestamp.micros, 1080);
+    }
+
+    #[tokio::test]
+    async fn inject_error() {
+        let (mut tx, mut rx) = super::connect();
+        let msg = Message::new_data(0x1, false, &[]).unwrap();
+        tx.send(msg.clone()).await.unwrap();
+        tx.inject_error(BusError::Off).unwrap();
+        tx.send(msg.clone()).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap(), msg);
+        assert!(matches!(
+            rx.recv().await,
+            Err(crate::Error::BusError(BusError::Off))
+        ));
+        assert_eq!(rx.recv().await.unwrap(), msg);
+    }
+
+    #[tokio::test]
+    async fn drop_rate() {
+        let (mut tx, mut rx) = super::connect();
+        tx.set_drop_rate(1.0);
+        for id in 0..100 {
+            tx.send(Message::new_data(id, false, &[]).unwrap())
+                .await
+                .unwrap();
+        }
+        tx.set_drop_rate(0.5);
+        for id in 0..1000 {
+            tx.send(Message::new_data(id, false, &[]).unwrap())
+                .await
+                .unwrap();
+        }
+        drop(tx);
+        let mut received = 0;
+        while rx.recv().await.is_ok() {
+            received += 1;
+        }
+        assert!((400..600).contains(&received));
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_drop_rate() {
+        let (mut tx, _rx) = super::connect();
+        tx.set_drop_rate(f32::NAN);
+    }
+
+    #[test]
+    fn clone_reseeds() {
+        let (mut tx, _rx) = super::connect();
+        let mut cloned = tx.clone();
+        let a: Vec<f32> = (0..8).map(|_| tx.random()).collect();
+        let b: Vec<f32> = (0..8).map(|_| cloned.random()).collect();
+        assert_ne!(a, b);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn fd_serde_round_trip() {
+        let data: Vec<u8> = (0..64).collect();
+        let msg = Message::new_fd(0x1ABCDEF, true, true, &data).unwrap();
+        let json = serde_json::to_string(&msg).unwrap();
+        let decoded: Message = serde_json::from_str(&json).unwrap();
+        assert_eq!(msg, decoded);
+
+        let json = json.replace(",63]", "]");
+        assert!(serde_json::from_str::<Message>(&json).is_err());
     }
 }
@@ -0,0 +1,98 @@
+//! This module implements merging several receivers, for example from multiple interfaces, into a single
+//! [`Stream`] of messages tagged with the name of their source.
+
+use futures::stream::{self, Stream};
+
+use crate::{Error, Message, Receiver, Result};
+
+/// Merge `receivers` into a single stream, in which each message is tagged with the name of the receiver
+/// it was received from.
+///
+/// All receivers are polled concurrently and in turns, so a busy receiver does not starve the others. At
+/// most one message is received from each receiver before it is consumed from the stream, so backpressure
+/// is propagated to the receivers.
+///
+/// Errors are forwarded as they occur. A receiver is removed from the stream once it is disconnected or
+/// failed permanently, e.g. because the receive thread of a PCAN receiver panicked. The stream ends once all
+/// receivers are removed.
+pub fn merge<R>(
+    receivers: Vec<(String, R)>,
+) -> impl Stream<Item = Result<(String, Message)>> + Send + Unpin
+where
+    R: Receiver + Send + 'static,
+{
+    let streams = receivers.into_iter().map(|(name, rx)| {
+        Box::pin(stream::unfold(Some((name, rx)), |state| async move {
+            let (name, mut rx) = state?;
+            match rx.recv().await {
+                Ok(msg) => Some((Ok((name.clone(), msg)), Some((name, rx)))),
+                Err(err) if is_terminal(&err) => Some((Err(err), None)),
+                Err(err) => Some((Err(err), Some((name, rx)))),
+            }
+        }))
+    });
+    stream::select_all(streams)
+}
+
+/// Returns true if the receiver will fail with every subsequent call, which would otherwise never end the
+/// stream.
+fn is_terminal(err: &Error) -> bool {
+    err.is_disconnected() || matches!(err, Error::PCanReceiverPanicked(_))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{loopback, Sender};
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn tagged_and_fair() {
+        let (mut tx_a, rx_a) = loopback::connect();
+        let (mut tx_b, rx_b) = loopback::connect();
+        for k in 0..10 {
+            let msg = Message::new_data(0x100, false, &[k]).unwrap();
+            tx_a.send(msg).await.unwrap();
+        }
+        let msg_b = Message::new_data(0x200, false, &[]).unwrap();
+        tx_b.send(msg_b.clone()).await.unwrap();
+
+        let mut merged = merge(vec![("a".to_string(), rx_a), ("b".to_string(), rx_b)]);
+        let first = merged.next().await.unwrap().unwrap();
+        let second = merged.next().await.unwrap().unwrap();
+        // the busy receiver must not delay the other one
+        assert_ne!(first.0, second.0);
+        assert!([first, second].contains(&("b".to_string(), msg_b)));
+
+        drop(tx_a);
+        drop(tx_b);
+        let mut received = 0;
+        while let Some(item) = merged.next().await {
+            match item {
+                Ok((name, _)) => {
+                    assert_eq!(name, "a");
+                    received += 1;
+                }
+                Err(err) => assert!(err.is_disconnected()),
+            }
+        }
+        assert_eq!(received, 9);
+    }
+
+    struct Panicked;
+
+    #[async_trait::async_trait]
+    impl Receiver for Panicked {
+        async fn recv(&mut self) -> Result<Message> {
+            Err(Error::PCanReceiverPanicked("boom".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn remove_failed_receiver() {
+        let mut merged = merge(vec![("a".to_string(), Panicked)]);
+        let err = merged.next().await.unwrap().unwrap_err();
+        assert!(matches!(err, Error::PCanReceiverPanicked(_)));
+        assert!(merged.next().await.is_none());
+    }
+}
@@ -0,0 +1,163 @@
+//! This module implements observing which IDs are present on a bus and at which rate, similar to a summary
+//! of `cansniffer`, e.g. for reverse-engineering an unknown bus.
+
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+
+use crate::{CanId, Message, Receiver, Result, Timestamp};
+
+/// The messages observed with a single ID by an [`IdObserver`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdStats {
+    pub id: CanId,
+    /// Number of messages received with this ID
+    pub count: u64,
+    /// Time the first message with this ID was received
+    pub first_seen: Timestamp,
+    /// Time the last message with this ID was received
+    pub last_seen: Timestamp,
+    /// Data of the last message with this ID, which is empty for remote frames
+    pub last_data: Vec<u8>,
+}
+
+impl IdStats {
+    /// Returns the average number of messages per second received with this ID.
+    ///
+    /// Returns `None` if less than two messages were received, since no rate can be computed then.
+    pub fn rate(&self) -> Option<f64> {
+        let micros = self.last_seen.micros.checked_sub(self.first_seen.micros)?;
+        if self.count < 2 || micros == 0 {
+            return None;
+        }
+        Some((self.count - 1) as f64 * 1e6 / micros as f64)
+    }
+}
+
+/// Wraps a [`crate::Receiver`] and records the count, the time last seen and the last data of the received
+/// messages per ID.
+///
+/// Messages are timestamped with the system time when they are returned by the wrapped receiver.
+pub struct IdObserver<R> {
+    inner: R,
+    ids: BTreeMap<CanId, IdStats>,
+}
+
+impl<R> IdObserver<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            ids: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the statistics of all IDs observed so far, sorted by bus priority of the IDs.
+    pub fn ids(&self) -> Vec<IdStats> {
+        self.ids.values().cloned().collect()
+    }
+
+    /// Returns the statistics of the given ID, if it was observed.
+    pub fn get(&self, id: CanId) -> Option<&IdStats> {
+        self.ids.get(&id)
+    }
+
+    /// Forget all IDs observed so far.
+    pub fn clear(&mut self) {
+        self.ids.clear();
+    }
+
+    /// Return the wrapped receiver.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn observe(&mut self, msg: &Message, timestamp: Timestamp) {
+        let data = match msg {
+            Message::Data(frame) => frame.data(),
+            Message::Remote(_) => &[],
+            Message::Fd(frame) => frame.data(),
+        };
+        let id = msg.can_id();
+        let stats = self.ids.entry(id).or_insert_with(|| IdStats {
+            id,
+            count: 0,
+            first_seen: timestamp.clone(),
+            last_seen: timestamp.clone(),
+            last_data: Vec::new(),
+        });
+        stats.count += 1;
+        stats.last_seen = timestamp;
+        stats.last_data.clear();
+        stats.last_data.extend_from_slice(data);
+    }
+}
+
+#[async_trait]
+impl<R: Receiver + Send> Receiver for IdObserver<R> {
+    async fn recv(&mut self) -> Result<Message> {
+        let msg = self.inner.recv().await?;
+        let timestamp = Timestamp::from_system_time(SystemTime::now())
+            .unwrap_or_else(|| Timestamp::from_micros(0));
+        self.observe(&msg, timestamp);
+        Ok(msg)
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        self.inner.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{loopback, Sender};
+
+    #[tokio::test]
+    async fn observe_ids() {
+        let (mut tx, rx) = loopback::connect();
+        let mut rx = IdObserver::new(rx);
+        let msgs = [
+            Message::new_data(0x200, false, &[1]).unwrap(),
+            Message::new_data(0x100, true, &[1, 2]).unwrap(),
+            Message::new_data(0x200, false, &[2, 3]).unwrap(),
+            Message::new_remote(0x100, false, 4).unwrap(),
+        ];
+        for msg in msgs {
+            tx.send(msg.clone()).await.unwrap();
+            assert_eq!(rx.recv().await.unwrap(), msg);
+        }
+
+        let ids = rx.ids();
+        let summary: Vec<_> = ids
+            .iter()
+            .map(|x| (x.id, x.count, x.last_data.clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (CanId::extended(0x100).unwrap(), 1, vec![1, 2]),
+                (CanId::standard(0x100).unwrap(), 1, vec![]),
+                (CanId::standard(0x200).unwrap(), 2, vec![2, 3]),
+            ]
+        );
+        let stats = rx.get(CanId::standard(0x200).unwrap()).unwrap();
+        assert!(stats.first_seen <= stats.last_seen);
+        assert_eq!(ids[1].rate(), None);
+
+        rx.clear();
+        assert!(rx.ids().is_empty());
+    }
+
+    #[test]
+    fn rate() {
+        let stats = IdStats {
+            id: CanId::standard(0x123).unwrap(),
+            count: 11,
+            first_seen: Timestamp::from_micros(1_000_000),
+            last_seen: Timestamp::from_micros(2_000_000),
+            last_data: vec![],
+        };
+        assert_eq!(stats.rate(), Some(10.0));
+    }
+}
@@ -2,28 +2,40 @@
 
 use std::{
     ffi::c_void,
-    ffi::CStr,
-    io::Write,
-    mem::{size_of, MaybeUninit},
+    ffi::{CStr, CString},
+    mem::{size_of, size_of_val, MaybeUninit},
 };
 
 #[cfg(target_os = "linux")]
 use std::os::unix::prelude::RawFd;
 
 use super::{sys, DeviceInfo};
-use crate::{CanFrameError, Message};
+use crate::{CanFdFrame, CanFrameError, Message, CAN_EXT_ID_MASK, CAN_STD_ID_MASK};
 use dlopen::wrapper::{Container, WrapperApi};
 use dlopen_derive::WrapperApi;
 use lazy_static::lazy_static;
 use std::os::raw::c_char;
-use tempfile::NamedTempFile;
 
-#[cfg(target_os = "windows")]
+#[cfg(all(target_os = "windows", not(feature = "pcan_system_lib")))]
 const PCAN_LIB: &[u8] = include_bytes!("../../lib/PCANBasic.dll");
 
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", not(feature = "pcan_system_lib")))]
 const PCAN_LIB: &[u8] = include_bytes!("../../lib/libpcanbasic.so");
 
+#[cfg(target_os = "windows")]
+const PCAN_LIB_NAME: &str = "PCANBasic.dll";
+
+#[cfg(target_os = "linux")]
+const PCAN_LIB_NAME: &str = "libpcanbasic.so";
+
+/// 64-bit FNV-1a hash, which is stable across Rust versions, unlike `DefaultHasher`.
+#[cfg(not(feature = "pcan_system_lib"))]
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, &x| {
+        (hash ^ x as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
 pub type Handle = u16;
 pub type Status = u32;
 pub type Parameter = u8;
@@ -54,7 +66,34 @@ pub fn get_baud(bitrate: u32) -> crate::Result<u16> {
     Ok(ret as u16)
 }
 
+/// Compute the bitrate resulting from a BTR0BTR1 register value, based on the 8 MHz CAN clock
+/// of the SJA1000 compatible register layout.
+pub fn btr_bitrate(btr0btr1: u16) -> u32 {
+    let [btr0, btr1] = btr0btr1.to_be_bytes();
+    let brp = (btr0 & 0x3F) as u32 + 1;
+    let tseg1 = (btr1 & 0x0F) as u32 + 1;
+    let tseg2 = ((btr1 >> 4) & 0x07) as u32 + 1;
+    8_000_000 / (brp * (1 + tseg1 + tseg2))
+}
+
+/// Value of the `PCAN_ACCEPTANCE_FILTER_11BIT/29BIT` parameters, holding the code in the upper and the mask
+/// in the lower 32 bits.
+pub fn acceptance_filter_value(code: u32, mask: u32) -> u64 {
+    ((code as u64) << 32) | mask as u64
+}
+
+/// Value of the acceptance filter in its default state, in which all IDs pass.
+pub fn open_acceptance_filter(ext_id: bool) -> u64 {
+    let mask = if ext_id {
+        CAN_EXT_ID_MASK
+    } else {
+        CAN_STD_ID_MASK
+    };
+    acceptance_filter_value(0, mask)
+}
+
 #[repr(C)]
+#[derive(Debug, Clone, Copy)]
 pub struct PCanMessage {
     pub id: u32,
     pub tp: u8,
@@ -63,12 +102,12 @@ pub struct PCanMessage {
 }
 
 impl PCanMessage {
-    pub fn from_message(msg: Message) -> Result<Self, CanFrameError> {
+    pub fn from_message(msg: Message) -> crate::Result<Self> {
         CanFrameError::validate_id(msg.id(), msg.ext_id())?;
         match msg {
             Message::Data(frame) => {
                 if frame.data().len() > 8 {
-                    return Err(CanFrameError::DataTooLong);
+                    return Err(CanFrameError::DataTooLong.into());
                 }
 
                 let mut data = [0_u8; 8];
@@ -87,7 +126,7 @@ impl PCanMessage {
             }
             Message::Remote(frame) => {
                 if frame.dlc() > 8 {
-                    return Err(CanFrameError::DataTooLong);
+                    return Err(CanFrameError::DataTooLong.into());
                 }
                 let mut tp = if frame.ext_id() {
                     sys::PCAN_MESSAGE_EXTENDED
@@ -102,6 +141,9 @@ impl PCanMessage {
                     data: [0_u8; 8],
                 })
             }
+            Message::Fd(_) => Err(crate::Error::Other(
+                "CAN-FD requires a channel connected with `connect_fd()`".to_string(),
+            )),
         }
     }
 
@@ -111,11 +153,74 @@ impl PCanMessage {
         if rtr {
             Ok(Message::new_remote(self.id, ext_id, self.len)?)
         } else {
-            Ok(Message::new_data(
-                self.id,
-                ext_id,
-                &self.data[0..self.len as usize],
-            )?)
+            let data = self
+                .data
+                .get(0..self.len as usize)
+                .ok_or(CanFrameError::DataTooLong)?;
+            Ok(Message::new_data(self.id, ext_id, data)?)
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PCanMessageFd {
+    pub id: u32,
+    pub tp: u8,
+    pub dlc: u8,
+    pub data: [u8; 64],
+}
+
+impl PCanMessageFd {
+    pub fn from_message(msg: Message) -> crate::Result<Self> {
+        CanFrameError::validate_id(msg.id(), msg.ext_id())?;
+        let mut tp = if msg.ext_id() {
+            sys::PCAN_MESSAGE_EXTENDED
+        } else {
+            sys::PCAN_MESSAGE_STANDARD
+        };
+        let mut data = [0_u8; 64];
+        match &msg {
+            Message::Data(frame) => {
+                data[0..frame.data().len()].copy_from_slice(frame.data());
+            }
+            Message::Remote(_) => {
+                tp |= sys::PCAN_MESSAGE_RTR;
+            }
+            Message::Fd(frame) => {
+                tp |= sys::PCAN_MESSAGE_FD;
+                if frame.brs() {
+                    tp |= sys::PCAN_MESSAGE_BRS;
+                }
+                if frame.esi() {
+                    tp |= sys::PCAN_MESSAGE_ESI;
+                }
+                data[0..frame.data().len()].copy_from_slice(frame.data());
+            }
+        }
+        Ok(PCanMessageFd {
+            id: msg.id(),
+            tp: tp as u8,
+            dlc: msg.dlc(),
+            data,
+        })
+    }
+
+    pub fn into_message(self) -> crate::Result<Message> {
+        let ext_id = (self.tp & sys::PCAN_MESSAGE_EXTENDED as u8) > 0;
+        let rtr = self.tp & (sys::PCAN_MESSAGE_RTR as u8) > 0;
+        let fd = self.tp & (sys::PCAN_MESSAGE_FD as u8) > 0;
+        if rtr {
+            return Ok(Message::new_remote(self.id, ext_id, self.dlc)?);
+        }
+        let data = &self.data[0..crate::dlc_to_len(self.dlc)];
+        if fd {
+            let brs = self.tp & (sys::PCAN_MESSAGE_BRS as u8) > 0;
+            let esi = self.tp & (sys::PCAN_MESSAGE_ESI as u8) > 0;
+            let frame = CanFdFrame::new(self.id, ext_id, brs, data.to_vec())?.with_esi(esi);
+            Ok(Message::Fd(frame))
+        } else {
+            Ok(Message::new_data(self.id, ext_id, data)?)
         }
     }
 }
@@ -136,6 +241,7 @@ struct Api {
         port: u32,
         interrupt: u16,
     ) -> Status,
+    CAN_InitializeFD: unsafe extern "C" fn(channel: Handle, bitrate_fd: *const c_char) -> Status,
     CAN_Uninitialize: unsafe extern "C" fn(channel: Handle) -> Status,
     CAN_Reset: unsafe extern "C" fn(channel: Handle) -> Status,
     CAN_GetStatus: unsafe extern "C" fn(channel: Handle) -> Status,
@@ -145,6 +251,12 @@ struct Api {
         timestamp: *mut Timestamp,
     ) -> Status,
     CAN_Write: unsafe extern "C" fn(channel: Handle, msg: *const PCanMessage) -> Status,
+    CAN_ReadFD: unsafe extern "C" fn(
+        channel: Handle,
+        msg: *mut PCanMessageFd,
+        timestamp: *mut u64,
+    ) -> Status,
+    CAN_WriteFD: unsafe extern "C" fn(channel: Handle, msg: *const PCanMessageFd) -> Status,
     CAN_GetErrorText: unsafe extern "C" fn(error: Status, lang: u16, buf: *const c_char),
     CAN_SetValue:
         unsafe extern "C" fn(channel: Handle, param: u8, buf: *const c_void, len: u32) -> Status,
@@ -213,15 +325,49 @@ pub struct PCan {
 
 impl PCan {
     pub fn new() -> Self {
-        let mut tmpfile = NamedTempFile::new().unwrap();
-        tmpfile.write_all(PCAN_LIB).unwrap();
-        let (_, path) = tmpfile.keep().unwrap();
-        let name = path.to_str().unwrap();
-        let api: Container<Api> = unsafe { Container::load(name) }
+        let name = Self::library_path();
+        let api: Container<Api> = unsafe { Container::load(&name) }
             .expect("Could not load PCan: Is the driver installed?");
         PCan { api }
     }
 
+    /// Load the library installed with the driver from the system library path.
+    #[cfg(feature = "pcan_system_lib")]
+    fn library_path() -> String {
+        PCAN_LIB_NAME.to_string()
+    }
+
+    /// Extract the embedded library to the temp directory.
+    ///
+    /// The file name contains a hash of the library, such that the file is extracted once and reused by
+    /// subsequent processes rather than accumulating a new file on every start.
+    #[cfg(not(feature = "pcan_system_lib"))]
+    fn library_path() -> String {
+        use std::io::Write;
+
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!(
+            "async-can-{:016x}-{}",
+            fnv1a(PCAN_LIB),
+            PCAN_LIB_NAME
+        ));
+        if std::fs::read(&path).map_or(true, |x| x != PCAN_LIB) {
+            let mut tmpfile = tempfile::NamedTempFile::new_in(&dir).unwrap();
+            tmpfile.write_all(PCAN_LIB).unwrap();
+            // fails if the file is in use, e.g. because it is loaded by another process on windows
+            if let Err(err) = tmpfile.persist(&path) {
+                log::warn!(
+                    "Could not extract PCAN library to {:?}: {}",
+                    path,
+                    err.error
+                );
+                let (_, path) = err.file.keep().unwrap();
+                return path.to_str().unwrap().to_string();
+            }
+        }
+        path.to_str().unwrap().to_string()
+    }
+
     pub fn describe_status(status: u32) -> String {
         let mut data: MaybeUninit<[c_char; 512]> = MaybeUninit::uninit();
         unsafe {
@@ -239,13 +385,12 @@ impl PCan {
 
     pub fn initalize(
         channel: Handle,
-        bitrate: u32,
+        baud: u16,
         hw_type: HwType,
         port: u32,
         interrupt: u16,
     ) -> Result<(), Error> {
-        // already checked in caller
-        let baud = get_baud(bitrate).unwrap();
+        let bitrate = btr_bitrate(baud);
 
         let mut current_speed: u32 = 0;
         let status = {
@@ -281,6 +426,123 @@ impl PCan {
             return Ok(());
         }
         Error::result(status)?;
+        Self::enable_busoff_autoreset(channel)
+    }
+
+    /// Initialize the channel in CAN-FD mode with the given bit-timing string, such as
+    /// `f_clock_mhz=80, nom_brp=10, nom_tseg1=5, nom_tseg2=2, nom_sjw=1, data_brp=4, data_tseg1=7, data_tseg2=2, data_sjw=1`.
+    ///
+    /// In case the channel is already initialized in CAN-FD mode, it is left untouched. If it is initialized
+    /// in classic mode, it is re-initialized in CAN-FD mode.
+    pub fn initialize_fd(channel: Handle, bitrate: &CString) -> Result<(), Error> {
+        let mut status = unsafe { PCAN.api.CAN_InitializeFD(channel, bitrate.as_ptr()) };
+        if status == sys::PCAN_ERROR_INITIALIZE {
+            if Self::is_initialized_fd(channel) {
+                return Ok(());
+            }
+            log::debug!("Channel is initialized in classic mode, re-initializing in CAN-FD mode");
+            Error::result(unsafe { PCAN.api.CAN_Uninitialize(channel) })?;
+            status = unsafe { PCAN.api.CAN_InitializeFD(channel, bitrate.as_ptr()) };
+        }
+        Error::result(status)?;
+        Self::enable_busoff_autoreset(channel)
+    }
+
+    /// Returns true if the channel was initialized with [`PCan::initialize_fd()`], in which case the driver
+    /// reports its CAN-FD bit timing.
+    fn is_initialized_fd(channel: Handle) -> bool {
+        let mut value = [0 as c_char; 256];
+        let status = unsafe {
+            PCAN.api.CAN_GetValue(
+                channel,
+                sys::PCAN_BITRATE_INFO_FD as u8,
+                value.as_mut_ptr() as *mut c_void,
+                size_of_val(&value) as u32,
+            )
+        };
+        status == sys::PCAN_ERROR_OK
+    }
+
+    /// Configure the acceptance filter of the channel. Bits set in `mask` are "don't care", i.e. a message
+    /// passes the filter if `id & !mask == code & !mask`.
+    pub fn set_acceptance_filter(
+        channel: Handle,
+        ext_id: bool,
+        code: u32,
+        mask: u32,
+    ) -> Result<(), Error> {
+        let value = acceptance_filter_value(code, mask);
+        Self::set_acceptance_filter_value(channel, ext_id, value)
+    }
+
+    fn set_acceptance_filter_value(channel: Handle, ext_id: bool, value: u64) -> Result<(), Error> {
+        let param = if ext_id {
+            sys::PCAN_ACCEPTANCE_FILTER_29BIT
+        } else {
+            sys::PCAN_ACCEPTANCE_FILTER_11BIT
+        };
+        let status = unsafe {
+            PCAN.api.CAN_SetValue(
+                channel,
+                param as u8,
+                &value as *const u64 as *const c_void,
+                size_of::<u64>() as u32,
+            )
+        };
+        Error::result(status)
+    }
+
+    /// Fully open the message filter and reset the acceptance filters of the channel such that all messages
+    /// are received.
+    pub fn open_filter(channel: Handle) -> Result<(), Error> {
+        for ext_id in [false, true] {
+            Self::set_acceptance_filter_value(channel, ext_id, open_acceptance_filter(ext_id))?;
+        }
+        let status = unsafe {
+            let open = sys::PCAN_FILTER_OPEN as u8;
+            PCAN.api.CAN_SetValue(
+                channel,
+                sys::PCAN_MESSAGE_FILTER as u8,
+                &open as *const u8 as *const c_void,
+                size_of::<u8>() as u32,
+            )
+        };
+        Error::result(status)
+    }
+
+    /// Enable or disable listen-only mode, in which the controller neither transmits nor acknowledges frames.
+    pub fn set_listen_only(channel: Handle, on: bool) -> Result<(), Error> {
+        let status = unsafe {
+            let value = if on {
+                sys::PCAN_PARAMETER_ON
+            } else {
+                sys::PCAN_PARAMETER_OFF
+            };
+            PCAN.api.CAN_SetValue(
+                channel,
+                sys::PCAN_LISTEN_ONLY as u8,
+                &value as *const u32 as *const c_void,
+                size_of::<u32>() as u32,
+            )
+        };
+        Error::result(status)
+    }
+
+    pub fn is_listen_only(channel: Handle) -> Result<bool, Error> {
+        let mut value = 0_u32;
+        let status = unsafe {
+            PCAN.api.CAN_GetValue(
+                channel,
+                sys::PCAN_LISTEN_ONLY as u8,
+                &mut value as *mut u32 as *mut c_void,
+                size_of::<u32>() as u32,
+            )
+        };
+        Error::result(status)?;
+        Ok(value == sys::PCAN_PARAMETER_ON)
+    }
+
+    fn enable_busoff_autoreset(channel: Handle) -> Result<(), Error> {
         let status = unsafe {
             let on = sys::PCAN_PARAMETER_ON as i32;
             PCAN.api.CAN_SetValue(
@@ -321,9 +583,10 @@ impl PCan {
     }
 
     pub fn read(channel: Handle) -> (Option<Error>, Option<(PCanMessage, Timestamp)>) {
+        // zero-initialized since the driver does not write the message if no frame was received
         let (err, msg, timestamp) = unsafe {
-            let mut msg = MaybeUninit::<PCanMessage>::uninit();
-            let mut timestamp = MaybeUninit::<Timestamp>::uninit();
+            let mut msg = MaybeUninit::<PCanMessage>::zeroed();
+            let mut timestamp = MaybeUninit::<Timestamp>::zeroed();
             let status = PCAN
                 .api
                 .CAN_Read(channel, msg.as_mut_ptr(), timestamp.as_mut_ptr());
@@ -331,7 +594,7 @@ impl PCan {
             let timestamp = timestamp.assume_init();
             (Error::new(status), msg, timestamp)
         };
-        if msg.tp & 0x03 > 0 || msg.tp == 0 {
+        if err.is_none() && (msg.tp & 0x03 > 0 || msg.tp == 0) {
             // rtr, std, ext
             (err, Some((msg, timestamp)))
         } else {
@@ -344,6 +607,31 @@ impl PCan {
         Error::result(status)
     }
 
+    /// Read a message from a channel initialized with [`PCan::initialize_fd()`]. The returned
+    /// timestamp is in microseconds.
+    pub fn read_fd(channel: Handle) -> (Option<Error>, Option<(PCanMessageFd, u64)>) {
+        let (err, msg, timestamp) = unsafe {
+            let mut msg = MaybeUninit::<PCanMessageFd>::zeroed();
+            let mut timestamp = 0_u64;
+            let status = PCAN
+                .api
+                .CAN_ReadFD(channel, msg.as_mut_ptr(), &mut timestamp as *mut u64);
+            (Error::new(status), msg.assume_init(), timestamp)
+        };
+        if err.is_none()
+            && msg.tp & (sys::PCAN_MESSAGE_ERRFRAME | sys::PCAN_MESSAGE_STATUS) as u8 == 0
+        {
+            (err, Some((msg, timestamp)))
+        } else {
+            (err, None)
+        }
+    }
+
+    pub fn write_fd(channel: Handle, msg: PCanMessageFd) -> Result<(), Error> {
+        let status = unsafe { PCAN.api.CAN_WriteFD(channel, &msg as *const PCanMessageFd) };
+        Error::result(status)
+    }
+
     pub fn list_devices() -> Result<Vec<DeviceInfo>, Error> {
         let channel_info = MaybeUninit::<sys::TPCANChannelInformation>::uninit();
         let infos = unsafe {
@@ -371,14 +659,37 @@ impl PCan {
         };
         Ok(infos
             .iter()
-            .map(|x| unsafe {
+            .map(|x| {
+                let info = unsafe { x.assume_init() };
+                let hardware_name = unsafe { CStr::from_ptr(info.device_name.as_ptr()) };
                 DeviceInfo {
-                    handle: x.assume_init().channel_handle,
+                    handle: info.channel_handle,
+                    condition: info.channel_condition,
+                    features: info.device_features,
+                    hardware_name: hardware_name.to_string_lossy().into_owned(),
+                    device_id: info.device_id,
+                    firmware_version: Self::firmware_version(info.channel_handle).ok(),
                 }
             })
             .collect())
     }
 
+    /// Query the firmware version of the device the channel belongs to. Not supported by all devices.
+    pub fn firmware_version(handle: Handle) -> Result<String, Error> {
+        let mut data = [0 as c_char; sys::MAX_LENGTH_VERSION_STRING as usize];
+        let status = unsafe {
+            PCAN.api.CAN_GetValue(
+                handle,
+                sys::PCAN_FIRMWARE_VERSION as u8,
+                data.as_mut_ptr() as *mut c_void,
+                data.len() as u32,
+            )
+        };
+        Error::result(status)?;
+        let version = unsafe { CStr::from_ptr(data.as_ptr()) };
+        Ok(version.to_string_lossy().into_owned())
+    }
+
     #[cfg(target_os = "linux")]
     pub fn get_fd(handle: Handle) -> Result<RawFd, Error> {
         use std::os::raw::c_int;
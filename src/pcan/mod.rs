@@ -7,6 +7,9 @@
 //! Note that on linux it is generally recommended to use the SocketCAN interface. With most recent distributions
 //! no installation is required.
 //!
+//! With the `pcan_system_lib` feature, the library is not embedded. Instead, `PCANBasic.dll` or `libpcanbasic.so`
+//! as installed with the driver is loaded from the system library path.
+//!
 //!
 //! ## Interface Names
 //!
@@ -18,14 +21,37 @@
 //!
 //! If you know that only a single USB dongle will be connected to the host, it's safe to just hard-code the "usb1" string.
 //!
+//! ## Custom Bitrates
+//!
+//! Besides the common bitrates accepted by [`Sender::connect()`], arbitrary bitrates and sample points can be configured
+//! with [`Sender::connect_with_btr()`] and [`Receiver::connect_with_btr()`]. These accept the raw value of the BTR0 and BTR1
+//! registers of an SJA1000 compatible controller with an 8 MHz CAN clock, with BTR0 in the upper byte:
+//!
+//!  * BTR0: bits 7-6 are SJW - 1, bits 5-0 are BRP - 1
+//!  * BTR1: bit 7 selects triple sampling, bits 6-4 are TSEG2 - 1, bits 3-0 are TSEG1 - 1
+//!
+//! The resulting bitrate is `8 MHz / (BRP * (1 + TSEG1 + TSEG2))` and the sample point is at `(1 + TSEG1) / (1 + TSEG1 + TSEG2)`.
+//! For example, `0x852B` results in 83.333 kbit/s with a sample point of 81.25%.
+//!
+//! ## CAN-FD
+//!
+//! Channels are initialized in CAN-FD mode with [`Sender::connect_fd()`] and [`Receiver::connect_fd()`], which accept
+//! a PCAN bit-timing string instead of a bitrate, e.g.
+//! `"f_clock_mhz=80, nom_brp=10, nom_tseg1=5, nom_tseg2=2, nom_sjw=1, data_brp=4, data_tseg1=7, data_tseg2=2, data_sjw=1"`.
+//!
 
 mod api;
 mod sys;
-use crate::{Error, Result};
-use crate::{Message, Timestamp};
+use crate::{trace, Message, Timestamp};
+use crate::{BusState, CanFrameError, Error, Result};
 use api::PCan;
-use api::{Handle, PCanMessage};
+use api::{Handle, PCanMessage, PCanMessageFd};
 use async_trait::async_trait;
+use std::any::Any;
+use std::ffi::CString;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::thread;
 use tokio::sync::mpsc::{self, UnboundedSender};
 use tokio::task::{self, spawn_blocking};
@@ -71,15 +97,60 @@ fn parse_ifname(ifname: &str) -> Result<Handle> {
 }
 
 fn connect_handle(ifname: &str, bitrate: u32) -> Result<Handle> {
-    let _ = get_baud(bitrate)?;
+    connect_handle_btr(ifname, get_baud(bitrate)?)
+}
+
+fn connect_handle_btr(ifname: &str, btr0btr1: u16) -> Result<Handle> {
+    let handle = parse_ifname(ifname)?;
+    if let Err(err) = PCan::initalize(
+        handle,
+        btr0btr1,
+        sys::PCAN_TYPE_ISA as u8,
+        IOPORT,
+        INTERRUPT,
+    ) {
+        return Err(Error::PCanInitFailed(err.code, err.description()));
+    }
+    Ok(handle)
+}
+
+fn connect_handle_fd(ifname: &str, bitrate: &str) -> Result<Handle> {
+    let bitrate = CString::new(bitrate).map_err(|_| Error::InvalidBitRate)?;
     let handle = parse_ifname(ifname)?;
-    if let Err(err) = PCan::initalize(handle, bitrate, sys::PCAN_TYPE_ISA as u8, IOPORT, INTERRUPT)
-    {
+    if let Err(err) = PCan::initialize_fd(handle, &bitrate) {
         return Err(Error::PCanInitFailed(err.code, err.description()));
     }
     Ok(handle)
 }
 
+/// A message read from the driver, depending on whether the channel is in CAN-FD mode
+#[derive(Debug, Clone, Copy)]
+enum Frame {
+    Classic(PCanMessage),
+    Fd(PCanMessageFd),
+}
+
+impl Frame {
+    fn into_message(self) -> Result<Message> {
+        match self {
+            Frame::Classic(msg) => msg.into_message(),
+            Frame::Fd(msg) => msg.into_message(),
+        }
+    }
+}
+
+fn read(handle: Handle, fd: bool) -> (Option<api::Error>, Option<(Frame, Timestamp)>) {
+    if fd {
+        let (err, data) = PCan::read_fd(handle);
+        let data = data.map(|(msg, micros)| (Frame::Fd(msg), Timestamp { micros }));
+        (err, data)
+    } else {
+        let (err, data) = PCan::read(handle);
+        let data = data.map(|(msg, timestamp)| (Frame::Classic(msg), timestamp.into()));
+        (err, data)
+    }
+}
+
 /// Attempt de-initialize an interface, thus disconnecting from the CAN bus
 pub async fn deinitialize(ifname: &str) -> Result<()> {
     let handle = parse_ifname(ifname)?;
@@ -97,32 +168,106 @@ pub async fn deinitialize(ifname: &str) -> Result<()> {
     .unwrap()
 }
 
+/// Return the state of the CAN controller of the given interface, which must have been connected before.
+///
+/// This allows detecting a bus-off condition, after which the channel may be recovered with [`Sender::reset()`].
+pub fn status(ifname: &str) -> Result<BusState> {
+    let handle = parse_ifname(ifname)?;
+    match PCan::get_status(handle) {
+        Some(err) if err.bus_error() != 0 => Ok(api::parse_bus_error(err.bus_error()).into()),
+        Some(err) if err.other_error() != 0 => {
+            Err(Error::PCanOtherError(err.code, err.description()))
+        }
+        // queue overruns do not affect the state of the controller
+        _ => Ok(BusState::ErrorActive),
+    }
+}
+
 /// Allows sending messages to the CAN bus.
+///
+/// Sending fails with [`Error::PCanWriteFailed`] if the channel was in listen-only mode when the sender
+/// was connected, see [`Receiver::connect_listen_only()`].
 pub struct Sender {
     handle: Handle,
+    fd: bool,
+    /// Whether the channel was in listen-only mode when connecting
+    listen_only: bool,
 }
 
 impl Sender {
+    fn new(handle: Handle, fd: bool) -> Self {
+        Self {
+            handle,
+            fd,
+            listen_only: PCan::is_listen_only(handle).unwrap_or(false),
+        }
+    }
+
     /// Connect the given interface and initializes the adapter to the given bitrate (if required).
     /// For nameing interafaces, refer to the [module documentation](crate::pcan).
     pub fn connect(ifname: &str, bitrate: u32) -> Result<Self> {
         let handle = connect_handle(ifname, bitrate)?;
-        Ok(Self { handle })
+        Ok(Self::new(handle, false))
+    }
+
+    /// Same as [`Sender::connect()`], but initializes the adapter on the blocking thread pool instead of
+    /// blocking the executor.
+    pub async fn connect_async(ifname: &str, bitrate: u32) -> Result<Self> {
+        let ifname = ifname.to_string();
+        spawn_blocking(move || Self::connect(&ifname, bitrate))
+            .await
+            .unwrap()
+    }
+
+    /// Connect the given interface and initializes the adapter with a raw BTR0BTR1 register value (if required).
+    /// Refer to the [module documentation](crate::pcan) for how to compute the register value.
+    pub fn connect_with_btr(ifname: &str, btr0btr1: u16) -> Result<Self> {
+        let handle = connect_handle_btr(ifname, btr0btr1)?;
+        Ok(Self::new(handle, false))
+    }
+
+    /// Connect the given interface and initializes the adapter in CAN-FD mode with the given bit-timing string.
+    /// Refer to the [module documentation](crate::pcan) for the format of the bit-timing string.
+    pub fn connect_fd(ifname: &str, bitrate: &str) -> Result<Self> {
+        let handle = connect_handle_fd(ifname, bitrate)?;
+        Ok(Self::new(handle, true))
+    }
+
+    /// Reset the channel, which clears the transmit and receive queues of the driver.
+    ///
+    /// This also recovers the controller from the bus-off state.
+    pub fn reset(&self) -> Result<()> {
+        PCan::reset(self.handle).map_err(|err| Error::PCanOtherError(err.code, err.description()))
     }
 
     /// Send a message to the CAN bus
     pub async fn send(&mut self, msg: Message) -> Result<()> {
+        // the driver would discard the message without reporting an error
+        if self.listen_only {
+            return Err(Error::PCanWriteFailed(
+                sys::PCAN_ERROR_ILLOPERATION,
+                "Channel is in listen-only mode".to_string(),
+            ));
+        }
         let handle = self.handle;
+        let fd = self.fd;
         // we unwrap because shouldn't panic
         task::spawn_blocking(move || {
-            let msg = PCanMessage::from_message(msg)?;
-            match PCan::write(handle, msg) {
+            trace::send("pcan", &msg);
+            let result = if fd {
+                PCan::write_fd(handle, PCanMessageFd::from_message(msg)?)
+            } else {
+                PCan::write(handle, PCanMessage::from_message(msg)?)
+            };
+            match result {
                 Err(err) => {
                     if err.other_error() != 0 {
                         let err = api::Error::new(err.other_error()).unwrap();
                         Err(Error::PCanWriteFailed(err.code, err.description()))
                     } else if err.bus_error() != 0 {
-                        Err(Error::BusError(api::parse_bus_error(err.bus_error())))
+                        let err = api::parse_bus_error(err.bus_error());
+                        trace::bus_error("pcan", &err);
+                        Err(Error::BusError(err))
                     } else if err.tx_overflow() {
                         Err(Error::TransmitQueueFull)
                     } else {
@@ -135,6 +280,14 @@ impl Sender {
         .await
         .unwrap()
     }
+
+    /// Single transmission without automatic retransmission is not supported by the PCAN-Basic API,
+    /// hence this always fails with [`Error::NotSupported`].
+    pub async fn send_once(&mut self, _msg: Message) -> Result<()> {
+        Err(Error::NotSupported(
+            "PCAN does not support one-shot transmission".to_string(),
+        ))
+    }
 }
 
 #[async_trait]
@@ -144,11 +297,95 @@ impl crate::Sender for Sender {
     }
 }
 
+/// Receiving end of the channel fed by the receive thread.
+///
+/// Receiving is cancel-safe: A message is only taken from the channel once it is returned, hence, dropping a
+/// pending receive future does not lose any messages.
+struct Queue {
+    rx: mpsc::UnboundedReceiver<Result<(Message, Timestamp)>>,
+    panicked: Option<String>,
+    /// Error encountered by [`Queue::recv_many()`] after it already received some messages
+    deferred_error: Option<Error>,
+}
+
+impl Queue {
+    fn new(rx: mpsc::UnboundedReceiver<Result<(Message, Timestamp)>>) -> Self {
+        Self {
+            rx,
+            panicked: None,
+            deferred_error: None,
+        }
+    }
+
+    async fn recv_with_timestamp(&mut self) -> Result<(Message, Timestamp)> {
+        if let Some(msg) = &self.panicked {
+            return Err(Error::PCanReceiverPanicked(msg.clone()));
+        }
+        if let Some(err) = self.deferred_error.take() {
+            return Err(err);
+        }
+        let result = self.rx.recv().await;
+        self.handle_received(result)
+    }
+
+    fn handle_received(
+        &mut self,
+        result: Option<Result<(Message, Timestamp)>>,
+    ) -> Result<(Message, Timestamp)> {
+        match result {
+            Some(Err(Error::PCanReceiverPanicked(msg))) => {
+                self.panicked = Some(msg.clone());
+                Err(Error::PCanReceiverPanicked(msg))
+            }
+            Some(msg) => msg,
+            None => Err(crate::Error::Disconnected),
+        }
+    }
+
+    async fn recv_many(&mut self, buf: &mut Vec<Message>, max: usize) -> Result<usize> {
+        if max == 0 {
+            return Ok(0);
+        }
+        buf.push(self.recv_with_timestamp().await?.0);
+        let mut count = 1;
+        while count < max {
+            let result = match self.rx.try_recv() {
+                Ok(result) => Some(result),
+                Err(mpsc::error::TryRecvError::Empty) => break,
+                Err(mpsc::error::TryRecvError::Disconnected) => None,
+            };
+            match self.handle_received(result) {
+                Ok((msg, _)) => {
+                    buf.push(msg);
+                    count += 1;
+                }
+                Err(err) => {
+                    self.deferred_error = Some(err);
+                    break;
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    /// Discard all pending messages and return the panic message if the receive thread panicked.
+    fn drain(&mut self) -> Option<String> {
+        while let Ok(result) = self.rx.try_recv() {
+            if let Err(Error::PCanReceiverPanicked(msg)) = result {
+                self.panicked = Some(msg);
+            }
+        }
+        self.panicked.take()
+    }
+}
+
 /// Allows receiving message from the CAN bus.
 pub struct Receiver {
     handle: Handle,
-    rx: mpsc::UnboundedReceiver<Result<(Message, Timestamp)>>,
+    queue: Queue,
     waiter_handle: WaiterHandle,
+    thread: Option<thread::JoinHandle<()>>,
+    dropped_malformed: Arc<AtomicU64>,
 }
 
 impl Receiver {
@@ -156,37 +393,87 @@ impl Receiver {
     /// For nameing interafaces, refer to the [module documentation](crate::pcan).
     pub fn connect(ifname: &str, bitrate: u32) -> Result<Self> {
         let handle = connect_handle(ifname, bitrate)?;
-        Self::start_receive(handle)
+        Self::start_receive(handle, false)
+    }
+
+    /// Connect the given interface in listen-only mode, such that the adapter neither transmits nor acknowledges
+    /// any frames. Senders connected to the interface afterwards fail with [`Error::PCanWriteFailed`].
+    pub fn connect_listen_only(ifname: &str, bitrate: u32) -> Result<Self> {
+        let handle = connect_handle(ifname, bitrate)?;
+        PCan::set_listen_only(handle, true)
+            .map_err(|err| Error::PCanInitFailed(err.code, err.description()))?;
+        Self::start_receive(handle, false)
+    }
+
+    /// Same as [`Receiver::connect()`], but initializes the adapter on the blocking thread pool instead of
+    /// blocking the executor.
+    pub async fn connect_async(ifname: &str, bitrate: u32) -> Result<Self> {
+        let ifname = ifname.to_string();
+        spawn_blocking(move || Self::connect(&ifname, bitrate))
+            .await
+            .unwrap()
+    }
+
+    /// Connect the given interface and initializes the adapter with a raw BTR0BTR1 register value (if required).
+    /// Refer to the [module documentation](crate::pcan) for how to compute the register value.
+    pub fn connect_with_btr(ifname: &str, btr0btr1: u16) -> Result<Self> {
+        let handle = connect_handle_btr(ifname, btr0btr1)?;
+        Self::start_receive(handle, false)
+    }
+
+    /// Connect the given interface and initializes the adapter in CAN-FD mode with the given bit-timing string.
+    /// Refer to the [module documentation](crate::pcan) for the format of the bit-timing string.
+    pub fn connect_fd(ifname: &str, bitrate: &str) -> Result<Self> {
+        let handle = connect_handle_fd(ifname, bitrate)?;
+        Self::start_receive(handle, true)
     }
 
     fn receive_loop(
         handle: Handle,
+        fd: bool,
         waiter: Waiter,
         tx: UnboundedSender<crate::Result<(Message, Timestamp)>>,
+        dropped_malformed: Arc<AtomicU64>,
     ) {
         loop {
             if tx.is_closed() {
                 log::debug!("Channel closed, quitting.");
                 break;
             }
-            let (err, data) = PCan::read(handle);
+            let (err, data) = read(handle, fd);
             let to_send = match err {
                 Some(err) if err.other_error() != 0 => Some(Err(Error::PCanReadFailed(
                     err.other_error(),
                     err.description(),
                 ))),
-                Some(err) if err.rx_empty() | err.rx_overflow() => match waiter.wait_for_event() {
-                    Ok(false) => continue,
-                    Ok(true) => {
-                        log::debug!("Waker cancelled!");
-                        break;
+                Some(err) if err.rx_empty() | err.rx_overflow() => {
+                    // the status flags are combined, so report a bus error before waiting
+                    if err.bus_error() != 0 {
+                        let err = api::parse_bus_error(err.bus_error());
+                        trace::bus_error("pcan", &err);
+                        if tx.send(Err(Error::BusError(err))).is_err() {
+                            log::debug!("Channel closed, quitting.");
+                            break;
+                        }
                     }
-                    Err(x) => {
-                        log::debug!("Error occurred, quitting receiver: {:?}", x);
-                        let _ = tx.send(Err(x)).is_err();
-                        break;
+                    match waiter.wait_for_event() {
+                        Ok(false) => continue,
+                        Ok(true) => {
+                            log::debug!("Waker cancelled!");
+                            break;
+                        }
+                        Err(x) => {
+                            log::debug!("Error occurred, quitting receiver: {:?}", x);
+                            let _ = tx.send(Err(x)).is_err();
+                            break;
+                        }
                     }
-                },
+                }
+                Some(err) if err.bus_error() != 0 => {
+                    let err = api::parse_bus_error(err.bus_error());
+                    trace::bus_error("pcan", &err);
+                    Some(Err(Error::BusError(err)))
+                }
                 Some(err) => Some(Err(Error::PCanReadFailed(err.code, err.description()))),
                 None => None,
             };
@@ -196,11 +483,18 @@ impl Receiver {
                     break;
                 }
             }
-            if let Some((msg, timestamp)) = data {
-                if let Ok(msg) = msg.into_message() {
-                    if tx.send(Ok((msg, timestamp.into()))).is_err() {
-                        log::debug!("Channel closed, quitting.");
-                        break;
+            if let Some((frame, timestamp)) = data {
+                match frame.into_message() {
+                    Ok(msg) => {
+                        trace::recv("pcan", &msg);
+                        if tx.send(Ok((msg, timestamp))).is_err() {
+                            log::debug!("Channel closed, quitting.");
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        dropped_malformed.fetch_add(1, Ordering::Relaxed);
+                        log::warn!("Dropping malformed PCAN frame ({}): {:?}", err, frame);
                     }
                 }
             }
@@ -208,14 +502,28 @@ impl Receiver {
         log::debug!("Leaving receiver.");
     }
 
-    fn start_receive(handle: Handle) -> crate::Result<Self> {
+    fn start_receive(handle: Handle, fd: bool) -> crate::Result<Self> {
         let (tx, rx) = mpsc::unbounded_channel();
         let (waiter, waiter_handle) = Waiter::new(handle)?;
-        thread::spawn(move || Self::receive_loop(handle, waiter, tx));
+        let dropped_malformed = Arc::new(AtomicU64::new(0));
+        let dropped = dropped_malformed.clone();
+        let thread = thread::spawn(move || {
+            let panic_tx = tx.clone();
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                Self::receive_loop(handle, fd, waiter, tx, dropped)
+            }));
+            if let Err(payload) = result {
+                let msg = panic_message(payload.as_ref());
+                log::error!("Receive thread panicked: {}", msg);
+                let _ = panic_tx.send(Err(Error::PCanReceiverPanicked(msg)));
+            }
+        });
         Ok(Self {
-            rx,
             handle,
+            queue: Queue::new(rx),
             waiter_handle,
+            thread: Some(thread),
+            dropped_malformed,
         })
     }
 
@@ -226,17 +534,80 @@ impl Receiver {
 
     /// Try to receive a message from the CAN bus, returning a message and an associated [crate::Timestamp] when the
     /// message was received.
+    ///
+    /// If the receive thread panicked, this and all subsequent calls return [`Error::PCanReceiverPanicked`].
     pub async fn recv_with_timestamp(&mut self) -> Result<(Message, Timestamp)> {
-        match self.rx.recv().await {
-            Some(msg) => msg,
-            None => Err(crate::Error::Other("Receiver disconnected.".to_string())),
+        self.queue.recv_with_timestamp().await
+    }
+
+    /// Receive up to `max` messages into `buf`, returning the number of messages received.
+    ///
+    /// Waits for the first message, then drains all further messages which were already received by the
+    /// receive thread without waiting. If an error occurs after some messages were received, these messages
+    /// are returned and the error is returned by the next receive call.
+    pub async fn recv_many(&mut self, buf: &mut Vec<Message>, max: usize) -> Result<usize> {
+        self.queue.recv_many(buf, max).await
+    }
+
+    /// Returns the number of received frames which were dropped because they could not be decoded
+    /// into a [`crate::Message`].
+    pub fn dropped_malformed_count(&self) -> u64 {
+        self.dropped_malformed.load(Ordering::Relaxed)
+    }
+
+    /// Configure the hardware acceptance filter to only receive messages with IDs from `from_id` to
+    /// `to_id` (inclusive), either for standard or extended IDs.
+    ///
+    /// The acceptance filter works with a code and a mask, hence, if the range is not aligned to a power
+    /// of two, it is widened to the smallest aligned range containing it. Use
+    /// [`crate::filter::FilteredReceiver`] to additionally filter in software if an exact range is required.
+    pub fn set_filter(&self, from_id: u32, to_id: u32, ext_id: bool) -> Result<()> {
+        CanFrameError::validate_id(from_id, ext_id)?;
+        CanFrameError::validate_id(to_id, ext_id)?;
+        if from_id > to_id {
+            return Err(Error::Other(format!(
+                "Invalid filter range: {:#x} > {:#x}",
+                from_id, to_id
+            )));
         }
+        let (code, mask) = acceptance_code_mask(from_id, to_id);
+        PCan::set_acceptance_filter(self.handle, ext_id, code, mask)
+            .map_err(|err| Error::PCanOtherError(err.code, err.description()))
     }
 
-    /// Close the device and drop the underlying handle.
+    /// Remove any filter, including the acceptance filter set by [`Receiver::set_filter()`], such that all
+    /// messages are received again.
+    pub fn clear_filter(&self) -> Result<()> {
+        PCan::open_filter(self.handle)
+            .map_err(|err| Error::PCanOtherError(err.code, err.description()))
+    }
+
+    /// Stop the receive thread and wait until it has finished, such that the interface may safely be
+    /// connected again.
+    ///
+    /// Returns [`Error::PCanReceiverPanicked`] if the receive thread panicked.
     pub fn close(mut self) -> Result<()> {
-        self.rx.close();
-        Ok(())
+        match self.stop() {
+            Some(thread) => self.joined(thread.join()),
+            None => Ok(()),
+        }
+    }
+
+    /// Signal the receive thread to stop and return it, unless it was already stopped.
+    fn stop(&mut self) -> Option<thread::JoinHandle<()>> {
+        self.queue.rx.close();
+        let thread = self.thread.take()?;
+        self.waiter_handle.close();
+        Some(thread)
+    }
+
+    fn joined(&mut self, result: thread::Result<()>) -> Result<()> {
+        result.map_err(|payload| Error::PCanReceiverPanicked(panic_message(payload.as_ref())))?;
+        // the panic may not have been observed by `recv()` yet
+        match self.queue.drain() {
+            Some(msg) => Err(Error::PCanReceiverPanicked(msg)),
+            None => Ok(()),
+        }
     }
 }
 
@@ -245,11 +616,47 @@ impl crate::Receiver for Receiver {
     async fn recv(&mut self) -> Result<Message> {
         self.recv().await
     }
+
+    /// Same as [`Receiver::close()`], but joins the receive thread on the blocking thread pool.
+    async fn shutdown(&mut self) -> Result<()> {
+        match self.stop() {
+            Some(thread) => {
+                let result = spawn_blocking(move || thread.join()).await.unwrap();
+                self.joined(result)
+            }
+            None => Ok(()),
+        }
+    }
 }
 
 impl Drop for Receiver {
     fn drop(&mut self) {
-        self.waiter_handle.close();
+        // the waiter is already closed if the receiver was closed explicitly
+        if self.thread.is_some() {
+            self.waiter_handle.close();
+        }
+    }
+}
+
+/// Compute the acceptance code and "don't care" mask of the smallest aligned ID range containing
+/// `from_id..=to_id`.
+fn acceptance_code_mask(from_id: u32, to_id: u32) -> (u32, u32) {
+    let diff = from_id ^ to_id;
+    let mask = if diff == 0 {
+        0
+    } else {
+        u32::MAX >> diff.leading_zeros()
+    };
+    (from_id & !mask, mask)
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "Unknown panic".to_string()
     }
 }
 
@@ -257,6 +664,11 @@ impl Drop for Receiver {
 #[derive(Clone, Debug)]
 pub struct DeviceInfo {
     handle: Handle,
+    condition: u32,
+    features: u32,
+    hardware_name: String,
+    device_id: u32,
+    firmware_version: Option<String>,
 }
 
 impl DeviceInfo {
@@ -275,6 +687,44 @@ impl DeviceInfo {
         }
         Err(crate::Error::PCanUnknownInterfaceType(self.handle))
     }
+
+    /// Returns the name of the hardware, e.g. "PCAN-USB FD".
+    pub fn hardware_name(&self) -> &str {
+        &self.hardware_name
+    }
+
+    /// Returns the device ID, which can be configured by the user to tell multiple devices of the same type apart.
+    ///
+    /// The PCAN-Basic API does not expose the serial number of a device, so this is the closest means of
+    /// identifying a specific device.
+    pub fn device_id(&self) -> u32 {
+        self.device_id
+    }
+
+    /// Returns the firmware version of the device, or `None` if the device does not report it.
+    pub fn firmware_version(&self) -> Option<&str> {
+        self.firmware_version.as_deref()
+    }
+
+    /// Returns true if the channel is available to be connected, i.e. not occupied by another application.
+    pub fn is_available(&self) -> bool {
+        self.condition & sys::PCAN_CHANNEL_AVAILABLE != 0
+    }
+
+    /// Returns true if the device supports CAN-FD, i.e. if the channel may be connected with
+    /// [`Sender::connect_fd()`] and [`Receiver::connect_fd()`].
+    pub fn is_fd_capable(&self) -> bool {
+        self.features & sys::FEATURE_FD_CAPABLE != 0
+    }
+
+    /// Convert into the interface independent [`crate::DeviceInfo`], where `index` is the PCAN channel handle.
+    pub fn to_common(&self) -> crate::Result<crate::DeviceInfo> {
+        Ok(crate::DeviceInfo {
+            interface_name: self.interface_name()?,
+            is_ready: self.is_available(),
+            index: self.handle as u32,
+        })
+    }
 }
 
 /// Retrieve all PCAN devices connected the host
@@ -284,3 +734,69 @@ pub async fn list_devices() -> crate::Result<Vec<DeviceInfo>> {
         .unwrap()
         .map_err(|x| crate::Error::PCanOtherError(x.code, x.description()))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn btr_bitrate() {
+        assert_eq!(api::btr_bitrate(0x001C), 500000);
+        assert_eq!(api::btr_bitrate(0x031C), 125000);
+        assert_eq!(api::btr_bitrate(0x852B), 83333);
+    }
+
+    #[tokio::test]
+    async fn cancelled_recv_does_not_lose_messages() {
+        use std::time::Duration;
+
+        const COUNT: u32 = 2000;
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut queue = Queue::new(rx);
+        // emulates the receive thread, delivering messages in bursts
+        let thread = thread::spawn(move || {
+            for id in 0..COUNT {
+                let msg = Message::new_data(id & 0x7FF, false, &[]).unwrap();
+                tx.send(Ok((msg, Timestamp::from_micros(id as u64))))
+                    .unwrap();
+                if id % 100 == 0 {
+                    thread::sleep(Duration::from_micros(500));
+                }
+            }
+        });
+        let mut next = 0;
+        while next < COUNT {
+            tokio::select! {
+                result = queue.recv_with_timestamp() => {
+                    let (_, timestamp) = result.unwrap();
+                    assert_eq!(timestamp.micros, next as u64);
+                    next += 1;
+                }
+                _ = tokio::task::yield_now() => {}
+            }
+        }
+        thread.join().unwrap();
+        assert!(queue
+            .recv_with_timestamp()
+            .await
+            .unwrap_err()
+            .is_disconnected());
+    }
+
+    #[test]
+    fn acceptance_filter_range() {
+        assert_eq!(acceptance_code_mask(0x100, 0x100), (0x100, 0));
+        assert_eq!(acceptance_code_mask(0x100, 0x107), (0x100, 0x7));
+        assert_eq!(acceptance_code_mask(0x100, 0x1FF), (0x100, 0xFF));
+        // not aligned, widened to 0x000..=0x1FF
+        assert_eq!(acceptance_code_mask(0x0FF, 0x100), (0x000, 0x1FF));
+    }
+
+    #[test]
+    fn acceptance_filter_values() {
+        assert_eq!(api::acceptance_filter_value(0x100, 0x7), 0x100_0000_0007);
+        // clearing the filter resets both acceptance filters to let all IDs pass
+        assert_eq!(api::open_acceptance_filter(false), 0x0000_0000_0000_07FF);
+        assert_eq!(api::open_acceptance_filter(true), 0x0000_0000_1FFF_FFFF);
+    }
+}
@@ -30,7 +30,7 @@ impl Waiter {
             .map_err(|x| crate::Error::PCanInitFailed(x.code, x.description()))?;
         let eventfd = unsafe { libc::eventfd(0, 0) };
         if eventfd == -1 {
-            return Err(crate::Error::Io(std::io::Error::last_os_error()))?;
+            return Err(std::io::Error::last_os_error().into());
         }
 
         let cancel = Arc::new(AtomicBool::new(false));
@@ -61,7 +61,7 @@ impl Waiter {
         ];
         let err = unsafe { libc::poll(&mut polls as *mut libc::pollfd, polls.len() as u64, -1) };
         if err < 0 {
-            return Err(crate::Error::Io(std::io::Error::last_os_error()))?;
+            return Err(std::io::Error::last_os_error().into());
         }
         if polls[1].revents != 0 {
             // eventfd was flagged
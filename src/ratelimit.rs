@@ -0,0 +1,106 @@
+//! This module implements a [`crate::Sender`] wrapper limiting the rate of outgoing messages, to avoid flooding
+//! the bus or to respect the minimum separation time of an ECU.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::time::Instant;
+
+use crate::{CanId, Message, Result, Sender};
+
+/// Wraps a [`crate::Sender`] and enforces a minimum interval between two messages sent.
+///
+/// If a message is sent earlier, [`Sender::send()`] waits until the interval has passed. Optionally, a
+/// minimum interval between two messages with the same ID can be enforced with [`RateLimited::per_id()`].
+pub struct RateLimited<S> {
+    inner: S,
+    interval: Duration,
+    last_sent: Option<Instant>,
+    id_interval: Duration,
+    last_sent_by_id: HashMap<CanId, Instant>,
+}
+
+impl<S> RateLimited<S> {
+    /// Wrap `inner` and send at most one message per `interval`.
+    pub fn new(inner: S, interval: Duration) -> Self {
+        Self {
+            inner,
+            interval,
+            last_sent: None,
+            id_interval: Duration::ZERO,
+            last_sent_by_id: HashMap::new(),
+        }
+    }
+
+    /// In addition, send at most one message per `interval` for each ID.
+    pub fn per_id(mut self, interval: Duration) -> Self {
+        self.id_interval = interval;
+        self
+    }
+
+    /// Return the wrapped sender.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Returns the earliest time at which `id` may be sent.
+    fn next_send(&self, id: CanId) -> Option<Instant> {
+        let next = self.last_sent.map(|x| x + self.interval);
+        let next_by_id = self.last_sent_by_id.get(&id).map(|x| *x + self.id_interval);
+        next.max(next_by_id)
+    }
+}
+
+#[async_trait]
+impl<S: Sender + Send> Sender for RateLimited<S> {
+    async fn send(&mut self, msg: Message) -> Result<()> {
+        let id = msg.can_id();
+        if let Some(next) = self.next_send(id) {
+            tokio::time::sleep_until(next).await;
+        }
+        let result = self.inner.send(msg).await;
+        let now = Instant::now();
+        self.last_sent = Some(now);
+        if !self.id_interval.is_zero() {
+            self.last_sent_by_id.insert(id, now);
+        }
+        result
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        self.inner.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{loopback, Receiver};
+
+    #[tokio::test(start_paused = true)]
+    async fn spacing() {
+        let (tx, mut rx) = loopback::connect();
+        let mut tx =
+            RateLimited::new(tx, Duration::from_millis(10)).per_id(Duration::from_millis(50));
+        let a = Message::new_data(0x100, false, &[]).unwrap();
+        let b = Message::new_data(0x200, false, &[]).unwrap();
+
+        let start = Instant::now();
+        tx.send(a.clone()).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        tx.send(b.clone()).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_millis(10));
+        tx.send(a.clone()).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_millis(50));
+
+        tokio::time::advance(Duration::from_millis(100)).await;
+        let start = Instant::now();
+        tx.send(b.clone()).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        for msg in [a.clone(), b.clone(), a, b] {
+            assert_eq!(rx.recv().await.unwrap(), msg);
+        }
+    }
+}
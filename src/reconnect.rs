@@ -0,0 +1,182 @@
+//! This module implements a [`crate::Receiver`] wrapper which transparently reconnects after the
+//! connection to the device was lost.
+
+use std::future::Future;
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::{Message, Receiver, Result};
+
+/// Exponential backoff policy used by [`Reconnecting`] and the USR-CANET backend between two connection attempts.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    /// Delay before the first reconnection attempt
+    pub initial: Duration,
+    /// Upper bound for the delay, which doubles with every failed attempt
+    pub max: Duration,
+    /// Maximum number of consecutive failed attempts before giving up, or `None` to retry forever
+    pub max_retries: Option<usize>,
+}
+
+impl Backoff {
+    /// Run `f` until it succeeds, waiting for the backoff delay after each failed attempt.
+    ///
+    /// Returns the error of the last attempt once `max_retries` is exceeded.
+    pub async fn retry<F, Fut, T>(&self, mut f: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut delay = self.initial;
+        let mut retries = 0;
+        loop {
+            match f().await {
+                Ok(x) => return Ok(x),
+                Err(err) if self.max_retries.is_none_or(|max| retries < max) => {
+                    log::warn!("Reconnecting failed, retrying in {:?}: {}", delay, err);
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(self.max);
+                    retries += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(10),
+            max_retries: None,
+        }
+    }
+}
+
+/// Wraps a connection function and re-runs it to obtain a new [`crate::Receiver`] whenever the current
+/// receiver fails with an error for which [`crate::Error::is_disconnected()`] returns true.
+///
+/// The first connection is established upon the first call to `recv()`. Other errors are returned
+/// as is.
+///
+/// ```no_run
+/// # tokio_test::block_on(async {
+/// use async_can::reconnect::{Backoff, Reconnecting};
+/// use async_can::{usr_canet, Receiver};
+///
+/// let connect = || async { usr_canet::connect("192.168.1.10:1").await.map(|(_, rx)| rx) };
+/// let mut receiver = Reconnecting::new(connect, Backoff::default());
+/// let msg = receiver.recv().await;
+/// # });
+/// ```
+pub struct Reconnecting<F, R> {
+    connect: F,
+    backoff: Backoff,
+    inner: Option<R>,
+}
+
+impl<F, Fut, R> Reconnecting<F, R>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<R>>,
+{
+    pub fn new(connect: F, backoff: Backoff) -> Self {
+        Self {
+            connect,
+            backoff,
+            inner: None,
+        }
+    }
+
+    /// Returns true if a receiver is currently connected.
+    pub fn is_connected(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    async fn reconnect(&mut self) -> Result<R> {
+        self.backoff.retry(&mut self.connect).await
+    }
+}
+
+#[async_trait]
+impl<F, Fut, R> Receiver for Reconnecting<F, R>
+where
+    F: FnMut() -> Fut + Send,
+    Fut: Future<Output = Result<R>> + Send,
+    R: Receiver + Send,
+{
+    async fn recv(&mut self) -> Result<Message> {
+        loop {
+            let receiver = match &mut self.inner {
+                Some(receiver) => receiver,
+                None => {
+                    let receiver = self.reconnect().await?;
+                    self.inner.insert(receiver)
+                }
+            };
+            match receiver.recv().await {
+                Err(err) if err.is_disconnected() => {
+                    log::info!("Receiver disconnected, reconnecting: {}", err);
+                    self.inner = None;
+                }
+                x => return x,
+            }
+        }
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        match self.inner.take() {
+            Some(mut receiver) => receiver.shutdown().await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{loopback, Error, Sender};
+
+    #[tokio::test]
+    async fn reconnect_after_disconnect() {
+        let (mut tx1, rx1) = loopback::connect();
+        let (mut tx2, rx2) = loopback::connect();
+        let receivers = Arc::new(Mutex::new(vec![Ok(rx2), Err(Error::Disconnected), Ok(rx1)]));
+        let connect = move || {
+            let receiver = receivers.lock().unwrap().pop().unwrap();
+            async move { receiver }
+        };
+        let backoff = Backoff {
+            initial: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let mut rx = Reconnecting::new(connect, backoff);
+
+        tx1.send(Message::new_data(1, false, &[]).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(rx.recv().await.unwrap().id(), 1);
+        drop(tx1);
+        tx2.send(Message::new_data(2, false, &[]).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(rx.recv().await.unwrap().id(), 2);
+    }
+
+    #[tokio::test]
+    async fn give_up_after_max_retries() {
+        let connect = || async { Err::<loopback::Receiver, _>(Error::Disconnected) };
+        let backoff = Backoff {
+            initial: Duration::from_millis(1),
+            max: Duration::from_millis(1),
+            max_retries: Some(2),
+        };
+        let mut rx = Reconnecting::new(connect, backoff);
+        assert!(matches!(rx.recv().await, Err(Error::Disconnected)));
+        assert!(!rx.is_connected());
+    }
+}
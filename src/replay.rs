@@ -0,0 +1,233 @@
+//! This module implements replaying CAN frames recorded with `candump -L`.
+//!
+//! Each line of such a log file contains the timestamp in seconds, the interface name and the frame in
+//! candump format, for example `(1436509052.249713) vcan0 044#2A366C2BBA`.
+
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::fs::File;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader, Lines};
+use tokio::time::Instant;
+
+use crate::{Error, Message, Result, Timestamp};
+
+/// Defines how fast a [`FileReceiver`] replays the recorded frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timing {
+    /// Honor the recorded time between two frames.
+    Recorded,
+    /// Replay all frames as fast as possible.
+    AsFastAsPossible,
+}
+
+/// A line of a `candump -L` log file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogEntry {
+    pub timestamp: Timestamp,
+    pub interface: String,
+    pub msg: Message,
+}
+
+/// Formats the entry as a line of a `candump -L` log file, without the trailing newline.
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "({}.{:06}) {} {}",
+            self.timestamp.micros / 1_000_000,
+            self.timestamp.micros % 1_000_000,
+            self.interface,
+            self.msg
+        )
+    }
+}
+
+fn parse_timestamp(timestamp: &str) -> Option<Timestamp> {
+    let (secs, fraction) = timestamp.split_once('.')?;
+    if fraction.is_empty() || fraction.len() > 6 {
+        return None;
+    }
+    let scale = 10_u64.pow(6 - fraction.len() as u32);
+    let secs: u64 = secs.parse().ok()?;
+    let micros = fraction.parse::<u64>().ok()? * scale;
+    Some(Timestamp::from_micros(
+        secs.checked_mul(1_000_000)? + micros,
+    ))
+}
+
+/// Parse a single line of a `candump -L` log file.
+pub fn parse_line(line: &str) -> Result<LogEntry> {
+    let mut words = line.split_whitespace();
+    let (timestamp, interface, msg) = match (words.next(), words.next(), words.next(), words.next())
+    {
+        (Some(timestamp), Some(interface), Some(msg), None) => (timestamp, interface, msg),
+        _ => return Err(Error::InvalidFormat),
+    };
+    let timestamp = timestamp
+        .strip_prefix('(')
+        .and_then(|x| x.strip_suffix(')'))
+        .and_then(parse_timestamp)
+        .ok_or(Error::InvalidFormat)?;
+    let msg = msg.parse::<Message>()?;
+    Ok(LogEntry {
+        timestamp,
+        interface: interface.to_string(),
+        msg,
+    })
+}
+
+/// Replays the frames of a `candump -L` log file. Implements [`crate::Receiver`].
+///
+/// Once all frames are replayed, receiving fails with [`Error::Disconnected`]. Lines which cannot be parsed
+/// are reported as [`Error::Other`] including the line number, after which replaying may be continued.
+pub struct FileReceiver {
+    lines: Lines<Box<dyn AsyncBufRead + Send + Unpin>>,
+    line_number: usize,
+    timing: Timing,
+    start: Option<(Timestamp, Instant)>,
+    /// Entry which was read from the file but not returned yet
+    pending: Option<LogEntry>,
+}
+
+impl FileReceiver {
+    /// Open the log file at `path`.
+    pub async fn open<P: AsRef<Path>>(path: P, timing: Timing) -> Result<Self> {
+        let file = File::open(path).await?;
+        Ok(Self::from_reader(BufReader::new(file), timing))
+    }
+
+    /// Replay a log read from `reader`.
+    pub fn from_reader<R: AsyncBufRead + Send + Unpin + 'static>(
+        reader: R,
+        timing: Timing,
+    ) -> Self {
+        let reader: Box<dyn AsyncBufRead + Send + Unpin> = Box::new(reader);
+        Self {
+            lines: reader.lines(),
+            line_number: 0,
+            timing,
+            start: None,
+            pending: None,
+        }
+    }
+
+    /// Returns the next entry of the log file, including the interface name.
+    ///
+    /// This method is cancel-safe: if it is cancelled while waiting for the recorded time of an entry,
+    /// the entry is returned by the next call.
+    pub async fn recv_entry(&mut self) -> Result<LogEntry> {
+        if self.pending.is_none() {
+            let (line_number, entry) = loop {
+                let line = self.lines.next_line().await?.ok_or(Error::Disconnected)?;
+                self.line_number += 1;
+                if !line.trim().is_empty() {
+                    break (self.line_number, parse_line(&line));
+                }
+            };
+            let entry =
+                entry.map_err(|err| Error::Other(format!("Line {}: {}", line_number, err)))?;
+            self.pending = Some(entry);
+        }
+        let entry = self.pending.as_ref().unwrap();
+        if self.timing == Timing::Recorded {
+            let (first, start) = self
+                .start
+                .get_or_insert_with(|| (entry.timestamp.clone(), Instant::now()));
+            let delta = entry.timestamp.micros.saturating_sub(first.micros);
+            tokio::time::sleep_until(*start + Duration::from_micros(delta)).await;
+        }
+        Ok(self.pending.take().unwrap())
+    }
+
+    /// Receive a message together with the [`crate::Timestamp`] recorded in the log file.
+    pub async fn recv_with_timestamp(&mut self) -> Result<(Message, Timestamp)> {
+        self.recv_entry()
+            .await
+            .map(|entry| (entry.msg, entry.timestamp))
+    }
+}
+
+#[async_trait]
+impl crate::Receiver for FileReceiver {
+    async fn recv(&mut self) -> Result<Message> {
+        self.recv_with_timestamp().await.map(|(msg, _)| msg)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Receiver;
+
+    const LOG: &[u8] = b"(1436509052.249713) vcan0 044#2A366C2BBA\n\
+        \n\
+        (1436509052.449847) vcan0 12345678#R\n\
+        (1436509052.5) vcan0 123#XY\n\
+        (1436509052.6) vcan1 7FF#\n";
+
+    #[test]
+    fn parse_lines() {
+        let entry = parse_line("(1436509052.249713) vcan0 044#2A366C2BBA").unwrap();
+        assert_eq!(entry.timestamp, Timestamp::from_micros(1436509052249713));
+        assert_eq!(entry.interface, "vcan0");
+        assert_eq!(
+            entry.msg,
+            Message::new_data(0x44, false, &[0x2A, 0x36, 0x6C, 0x2B, 0xBA]).unwrap()
+        );
+        assert_eq!(
+            parse_timestamp("1.5"),
+            Some(Timestamp::from_micros(1_500_000))
+        );
+        assert_eq!(
+            entry.to_string(),
+            "(1436509052.249713) vcan0 044#2A366C2BBA"
+        );
+        assert!(parse_line("1.0 vcan0 123#").is_err());
+        assert!(parse_line("(1.0) vcan0").is_err());
+        assert!(parse_line("(1.0) vcan0 123# 11").is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn replay_recorded_timing() {
+        let mut rx = FileReceiver::from_reader(LOG, Timing::Recorded);
+        let start = Instant::now();
+        let (msg, _) = rx.recv_with_timestamp().await.unwrap();
+        assert_eq!(msg.id(), 0x44);
+        let (msg, timestamp) = rx.recv_with_timestamp().await.unwrap();
+        assert_eq!(msg, Message::new_remote(0x12345678, true, 0).unwrap());
+        assert_eq!(timestamp, Timestamp::from_micros(1436509052449847));
+        // the timer of tokio has a resolution of 1ms
+        assert!(start.elapsed() >= Duration::from_micros(200134));
+        assert!(start.elapsed() <= Duration::from_millis(202));
+
+        let err = rx.recv().await.unwrap_err();
+        assert!(matches!(err, Error::Other(x) if x == "Line 4: Invalid frame format"));
+        let entry = rx.recv_entry().await.unwrap();
+        assert_eq!(entry.interface, "vcan1");
+        assert!(rx.recv().await.unwrap_err().is_disconnected());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cancel_recorded_timing() {
+        use crate::ext::ReceiverExt;
+
+        let mut rx = FileReceiver::from_reader(LOG, Timing::Recorded);
+        rx.recv().await.unwrap();
+        let timeout = Duration::from_millis(100);
+        assert_eq!(rx.recv_timeout(timeout).await.unwrap(), None);
+        let (msg, _) = rx.recv_with_timestamp().await.unwrap();
+        assert_eq!(msg, Message::new_remote(0x12345678, true, 0).unwrap());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn replay_as_fast_as_possible() {
+        let mut rx = FileReceiver::from_reader(LOG, Timing::AsFastAsPossible);
+        let start = Instant::now();
+        rx.recv().await.unwrap();
+        rx.recv().await.unwrap();
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+}
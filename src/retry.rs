@@ -0,0 +1,139 @@
+//! This module implements a [`crate::Sender`] wrapper retrying sends which failed with a transient error.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::{BusError, Error, Message, Result};
+
+/// Returns true for errors which are considered transient by default:
+///
+///  * [`Error::TransmitQueueFull`]
+///  * [`Error::BusError`] with [`BusError::LightWarning`] or [`BusError::HeavyWarning`]
+pub fn is_retryable(err: &Error) -> bool {
+    matches!(
+        err,
+        Error::TransmitQueueFull
+            | Error::BusError(BusError::LightWarning)
+            | Error::BusError(BusError::HeavyWarning)
+    )
+}
+
+/// Wraps a [`crate::Sender`] and retries sending a message if it failed with a retryable error.
+///
+/// Between two attempts, the sender waits for a backoff duration which doubles with every retry.
+/// Errors which are not retryable, such as [`Error::IdTooLong`] or [`Error::DataTooLong`], are
+/// returned immediately. Which errors are retried defaults to [`is_retryable()`] and may be changed with
+/// [`RetryingSender::retry_on()`].
+pub struct RetryingSender<S> {
+    inner: S,
+    max_retries: usize,
+    backoff: Duration,
+    retry_on: fn(&Error) -> bool,
+}
+
+impl<S> RetryingSender<S> {
+    /// Wrap `inner` and retry up to `max_retries` times, waiting for `backoff` before the first retry.
+    pub fn new(inner: S, max_retries: usize, backoff: Duration) -> Self {
+        Self {
+            inner,
+            max_retries,
+            backoff,
+            retry_on: is_retryable,
+        }
+    }
+
+    /// Replace the function deciding whether an error is retryable.
+    pub fn retry_on(mut self, retry_on: fn(&Error) -> bool) -> Self {
+        self.retry_on = retry_on;
+        self
+    }
+
+    /// Return the wrapped sender.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<S: crate::Sender + Send> crate::Sender for RetryingSender<S> {
+    async fn send(&mut self, msg: Message) -> Result<()> {
+        let mut backoff = self.backoff;
+        let mut retries = 0;
+        loop {
+            match self.inner.send(msg.clone()).await {
+                Err(err) if retries < self.max_retries && (self.retry_on)(&err) => {
+                    log::debug!("Retrying send after error: {}", err);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    retries += 1;
+                }
+                x => return x,
+            }
+        }
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        self.inner.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Sender;
+
+    struct FailingSender {
+        failures: usize,
+        error: fn() -> Error,
+        attempts: usize,
+    }
+
+    #[async_trait]
+    impl Sender for FailingSender {
+        async fn send(&mut self, _msg: Message) -> Result<()> {
+            self.attempts += 1;
+            if self.attempts <= self.failures {
+                Err((self.error)())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn sender(failures: usize, error: fn() -> Error) -> RetryingSender<FailingSender> {
+        let inner = FailingSender {
+            failures,
+            error,
+            attempts: 0,
+        };
+        RetryingSender::new(inner, 3, Duration::from_millis(1))
+    }
+
+    #[tokio::test]
+    async fn retry_transient_errors() {
+        let msg = Message::new_data(0x100, false, &[1, 2]).unwrap();
+
+        let mut tx = sender(3, || Error::TransmitQueueFull);
+        tx.send(msg.clone()).await.unwrap();
+        assert_eq!(tx.into_inner().attempts, 4);
+
+        let mut tx = sender(4, || Error::BusError(BusError::LightWarning));
+        assert!(matches!(
+            tx.send(msg.clone()).await,
+            Err(Error::BusError(BusError::LightWarning))
+        ));
+        assert_eq!(tx.into_inner().attempts, 4);
+
+        let mut tx = sender(1, || Error::DataTooLong);
+        assert!(matches!(
+            tx.send(msg.clone()).await,
+            Err(Error::DataTooLong)
+        ));
+        assert_eq!(tx.into_inner().attempts, 1);
+
+        let mut tx = sender(1, || Error::DataTooLong).retry_on(|_| true);
+        tx.send(msg).await.unwrap();
+        assert_eq!(tx.into_inner().attempts, 2);
+    }
+}
@@ -0,0 +1,138 @@
+//! This module implements packing and unpacking of signals in the payload of CAN frames.
+//!
+//! The bit numbering follows the DBC conventions used by Vector tools: bit `k` is bit `k % 8` of byte `k / 8`,
+//! where bit 0 is the least significant bit of a byte.
+//!
+//!  * For little endian (Intel, `@1` in DBC) signals, `start_bit` is the position of the least significant bit
+//!    and the signal extends towards higher bit positions.
+//!  * For big endian (Motorola, `@0` in DBC) signals, `start_bit` is the position of the most significant bit.
+//!    The signal extends towards the least significant bit of that byte and continues at the most significant
+//!    bit of the following byte.
+//!
+//! All functions panic if `len` is not within `1..=64` or if the signal does not fit into `data`.
+
+/// Returns the bit positions of the signal, ordered from the least to the most significant bit.
+fn bit_positions(start_bit: usize, len: usize, little_endian: bool) -> Vec<usize> {
+    assert!(
+        (1..=64).contains(&len),
+        "Signal length must be within 1..=64"
+    );
+    if little_endian {
+        return (start_bit..start_bit + len).collect();
+    }
+    let mut ret = Vec::with_capacity(len);
+    let mut pos = start_bit;
+    for _ in 0..len {
+        ret.push(pos);
+        pos = if pos.is_multiple_of(8) {
+            pos + 15
+        } else {
+            pos - 1
+        };
+    }
+    ret.reverse();
+    ret
+}
+
+/// Returns true if the signal fits into `len` bytes of data.
+pub(crate) fn fits(data_len: usize, start_bit: usize, len: usize, little_endian: bool) -> bool {
+    bit_positions(start_bit, len, little_endian)
+        .iter()
+        .all(|pos| *pos < data_len * 8)
+}
+
+/// Extract an unsigned signal from `data`.
+pub fn extract_unsigned(data: &[u8], start_bit: usize, len: usize, little_endian: bool) -> u64 {
+    bit_positions(start_bit, len, little_endian)
+        .iter()
+        .enumerate()
+        .fold(0, |value, (k, pos)| {
+            let bit = (data[pos / 8] >> (pos % 8)) & 1;
+            value | ((bit as u64) << k)
+        })
+}
+
+/// Extract a two's complement signed signal from `data`.
+pub fn extract_signed(data: &[u8], start_bit: usize, len: usize, little_endian: bool) -> i64 {
+    let value = extract_unsigned(data, start_bit, len, little_endian);
+    // sign-extend by shifting the sign bit into the most significant position
+    ((value << (64 - len)) as i64) >> (64 - len)
+}
+
+/// Insert an unsigned signal into `data`. Bits of `value` which do not fit into `len` bits are ignored.
+pub fn insert_unsigned(
+    data: &mut [u8],
+    start_bit: usize,
+    len: usize,
+    little_endian: bool,
+    value: u64,
+) {
+    for (k, pos) in bit_positions(start_bit, len, little_endian)
+        .into_iter()
+        .enumerate()
+    {
+        let mask = 1 << (pos % 8);
+        if (value >> k) & 1 != 0 {
+            data[pos / 8] |= mask;
+        } else {
+            data[pos / 8] &= !mask;
+        }
+    }
+}
+
+/// Insert a signed signal into `data` in two's complement. Bits of `value` which do not fit into `len` bits
+/// are ignored.
+pub fn insert_signed(
+    data: &mut [u8],
+    start_bit: usize,
+    len: usize,
+    little_endian: bool,
+    value: i64,
+) {
+    insert_unsigned(data, start_bit, len, little_endian, value as u64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn little_endian() {
+        let data = [0x34, 0x12, 0xF0, 0, 0, 0, 0, 0];
+        assert_eq!(extract_unsigned(&data, 0, 16, true), 0x1234);
+        assert_eq!(extract_unsigned(&data, 4, 8, true), 0x23);
+        assert_eq!(extract_unsigned(&data, 20, 4, true), 0xF);
+        assert_eq!(extract_signed(&data, 20, 4, true), -1);
+        assert_eq!(extract_signed(&data, 0, 16, true), 0x1234);
+
+        let mut buf = [0_u8; 8];
+        insert_unsigned(&mut buf, 0, 16, true, 0x1234);
+        insert_signed(&mut buf, 20, 4, true, -1);
+        assert_eq!(buf, data);
+    }
+
+    #[test]
+    fn big_endian() {
+        // 16-bit signal with its MSB at bit 7 occupies the first two bytes
+        let data = [0x12, 0x34, 0, 0, 0, 0, 0, 0];
+        assert_eq!(extract_unsigned(&data, 7, 16, false), 0x1234);
+        // 12-bit signal starting at bit 3 spans the lower nibble of byte 0 and all of byte 1
+        assert_eq!(extract_unsigned(&data, 3, 12, false), 0x234);
+        assert_eq!(extract_signed(&[0x80, 0x00], 7, 16, false), -32768);
+
+        let mut buf = [0xFF_u8; 8];
+        insert_unsigned(&mut buf, 3, 12, false, 0x234);
+        assert_eq!(buf[..2], [0xF2, 0x34]);
+        insert_signed(&mut buf, 7, 16, false, -2);
+        assert_eq!(buf[..2], [0xFF, 0xFE]);
+    }
+
+    #[test]
+    fn full_width() {
+        let data = 0x0123456789ABCDEF_u64.to_le_bytes();
+        assert_eq!(extract_unsigned(&data, 0, 64, true), 0x0123456789ABCDEF);
+        let data = 0x0123456789ABCDEF_u64.to_be_bytes();
+        assert_eq!(extract_unsigned(&data, 7, 64, false), 0x0123456789ABCDEF);
+        assert_eq!(extract_signed(&[0xFF; 8], 0, 64, true), -1);
+    }
+}
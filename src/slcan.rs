@@ -0,0 +1,225 @@
+//! This module implements support for adapters speaking the ASCII slcan (serial line CAN) protocol, such as
+//! the CANable or other LAWICEL compatible devices.
+//!
+//! The adapter is connected with a (virtual) serial port. Each CAN frame is encoded as an ASCII record
+//! terminated with `\r`, for example `t1232ABCD\r` for a data frame with standard ID `0x123` and two data bytes.
+//! CAN-FD is not supported.
+
+use crate::{decode_hex, trace, CanFrameError, Message, CAN_MAX_DLC};
+use async_trait::async_trait;
+use tokio::io::{split, AsyncBufReadExt, AsyncWriteExt, BufReader, ReadHalf, WriteHalf};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+/// Baudrate of the serial port. Most adapters ignore it as they are connected via USB.
+const SERIAL_BAUDRATE: u32 = 115200;
+
+/// A sender for slcan adapters. Implements [`crate::Sender`].
+///
+/// Contains the write half of the serial port.
+pub struct Sender {
+    port: WriteHalf<SerialStream>,
+}
+
+/// A receiver for slcan adapters. Implements [`crate::Receiver`].
+///
+/// Contains the read half of the serial port.
+pub struct Receiver {
+    port: BufReader<ReadHalf<SerialStream>>,
+    /// The record being read, kept across calls such that receiving is cancel-safe
+    buf: Vec<u8>,
+    dropped_malformed: u64,
+}
+
+fn bitrate_command(bitrate: u32) -> crate::Result<&'static [u8]> {
+    let ret: &[u8] = match bitrate {
+        10000 => b"S0\r",
+        20000 => b"S1\r",
+        50000 => b"S2\r",
+        100000 => b"S3\r",
+        125000 => b"S4\r",
+        250000 => b"S5\r",
+        500000 => b"S6\r",
+        800000 => b"S7\r",
+        1000000 => b"S8\r",
+        _ => return Err(crate::Error::InvalidBitRate),
+    };
+    Ok(ret)
+}
+
+/// Open the serial port at `path`, e.g. `/dev/ttyACM0` or `COM3`, configure the adapter to the
+/// given bitrate and open the CAN channel.
+pub async fn connect(path: &str, bitrate: u32) -> crate::Result<(Sender, Receiver)> {
+    let bitrate = bitrate_command(bitrate)?;
+    let mut port = tokio_serial::new(path, SERIAL_BAUDRATE)
+        .open_native_async()
+        .map_err(|err| crate::Error::Other(format!("Cannot open `{}`: {}", path, err)))?;
+    // close the channel in case it's still open, otherwise the bitrate cannot be changed
+    port.write_all(b"C\r").await?;
+    port.write_all(bitrate).await?;
+    port.write_all(b"O\r").await?;
+    let (read, write) = split(port);
+    let sender = Sender { port: write };
+    let receiver = Receiver {
+        port: BufReader::new(read),
+        buf: Vec::new(),
+        dropped_malformed: 0,
+    };
+    Ok((sender, receiver))
+}
+
+fn encode(msg: &Message) -> crate::Result<Vec<u8>> {
+    let (tag, data) = match msg {
+        Message::Data(frame) => (if msg.ext_id() { 'T' } else { 't' }, frame.data()),
+        Message::Remote(_) => (if msg.ext_id() { 'R' } else { 'r' }, &[][..]),
+        Message::Fd(_) => {
+            return Err(crate::Error::NotSupported(
+                "slcan does not support CAN-FD".to_string(),
+            ))
+        }
+    };
+    let mut ret = if msg.ext_id() {
+        format!("{}{:08X}{:X}", tag, msg.id(), msg.dlc())
+    } else {
+        format!("{}{:03X}{:X}", tag, msg.id(), msg.dlc())
+    };
+    for x in data {
+        ret.push_str(&format!("{:02X}", x));
+    }
+    ret.push('\r');
+    Ok(ret.into_bytes())
+}
+
+fn parse_hex(record: &[u8]) -> Option<u32> {
+    let record = std::str::from_utf8(record).ok()?;
+    u32::from_str_radix(record, 16).ok()
+}
+
+/// Decode a record without the trailing `\r`. Returns `Ok(None)` if the record is not a frame,
+/// such as the acknowledgement of a command.
+fn decode(record: &[u8]) -> Result<Option<Message>, CanFrameError> {
+    let (ext_id, rtr) = match record.first() {
+        Some(b't') => (false, false),
+        Some(b'T') => (true, false),
+        Some(b'r') => (false, true),
+        Some(b'R') => (true, true),
+        _ => return Ok(None),
+    };
+    let id_len = if ext_id { 8 } else { 3 };
+    let id = record
+        .get(1..1 + id_len)
+        .and_then(parse_hex)
+        .ok_or(CanFrameError::InvalidFormat)?;
+    let dlc = record
+        .get(1 + id_len..2 + id_len)
+        .and_then(parse_hex)
+        .ok_or(CanFrameError::InvalidFormat)? as usize;
+    if dlc > CAN_MAX_DLC {
+        return Err(CanFrameError::DataTooLong);
+    }
+    if rtr {
+        return Message::new_remote(id, ext_id, dlc as u8).map(Some);
+    }
+    // a timestamp may follow the data
+    let data = record
+        .get(2 + id_len..2 + id_len + 2 * dlc)
+        .ok_or(CanFrameError::InvalidFormat)?;
+    let data = decode_hex(data)?;
+    Message::new_data(id, ext_id, &data).map(Some)
+}
+
+#[async_trait]
+impl crate::Sender for Sender {
+    async fn send(&mut self, msg: Message) -> crate::Result<()> {
+        let record = encode(&msg)?;
+        trace::send("slcan", &msg);
+        self.port.write_all(&record).await?;
+        Ok(())
+    }
+}
+
+impl Receiver {
+    /// Returns the number of received frames which were dropped because they could not be decoded
+    /// into a [`crate::Message`].
+    pub fn dropped_malformed_count(&self) -> u64 {
+        self.dropped_malformed
+    }
+}
+
+#[async_trait]
+impl crate::Receiver for Receiver {
+    /// This is cancel-safe, a partially read record is kept and completed by the next call.
+    async fn recv(&mut self) -> crate::Result<Message> {
+        loop {
+            if self.port.read_until(b'\r', &mut self.buf).await? == 0 {
+                return Err(crate::Error::Disconnected);
+            }
+            let buf = std::mem::take(&mut self.buf);
+            // errors are reported with BEL instead of a record
+            let start = buf.iter().take_while(|x| **x == 0x07).count();
+            let record = buf[start..].strip_suffix(b"\r").unwrap_or(&buf[start..]);
+            match decode(record) {
+                Ok(Some(msg)) => {
+                    trace::recv("slcan", &msg);
+                    return Ok(msg);
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    self.dropped_malformed += 1;
+                    log::warn!(
+                        "Dropping malformed slcan record ({:?}): {:?}",
+                        err,
+                        String::from_utf8_lossy(record)
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode_decode() {
+        let msgs = [
+            Message::new_data(0x123, false, &[0xAB, 0xCD]).unwrap(),
+            Message::new_data(0x1ABCDEF, true, &[]).unwrap(),
+            Message::new_remote(0x7FF, false, 3).unwrap(),
+            Message::new_remote(0x12345, true, 8).unwrap(),
+        ];
+        let records: [&[u8]; 4] = [b"t1232ABCD\r", b"T01ABCDEF0\r", b"r7FF3\r", b"R000123458\r"];
+        for (msg, record) in msgs.iter().zip(records) {
+            assert_eq!(encode(msg).unwrap(), record);
+            let record = &record[..record.len() - 1];
+            assert_eq!(decode(record).unwrap().as_ref(), Some(msg));
+        }
+        let fd = Message::new_fd(0x123, false, false, &[]).unwrap();
+        assert!(encode(&fd).is_err());
+    }
+
+    #[test]
+    fn decode_records() {
+        // with trailing timestamp
+        assert_eq!(
+            decode(b"t12310A1B2").unwrap(),
+            Some(Message::new_data(0x123, false, &[0x0A]).unwrap())
+        );
+        // acknowledgements of commands
+        assert_eq!(decode(b"").unwrap(), None);
+        assert_eq!(decode(b"z").unwrap(), None);
+        assert!(matches!(decode(b"t1239"), Err(CanFrameError::DataTooLong)));
+        assert!(matches!(
+            decode(b"t1232AB"),
+            Err(CanFrameError::InvalidFormat)
+        ));
+        assert!(matches!(
+            decode(b"t12X0"),
+            Err(CanFrameError::InvalidFormat)
+        ));
+        assert!(matches!(
+            decode(b"t1231XY"),
+            Err(CanFrameError::InvalidFormat)
+        ));
+    }
+}
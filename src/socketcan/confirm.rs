@@ -0,0 +1,179 @@
+//! A sender which waits until frames were actually transmitted.
+
+use std::ffi::c_void;
+use std::io::{self, ErrorKind};
+use std::mem::size_of_val;
+use std::os::raw::c_uint;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ptr;
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+
+use super::{set_socket_option, sys, CanFilter, CanSocket};
+use crate::filter::Filter;
+use crate::{Message, Result, Timestamp};
+
+/// A sender whose [`ConfirmedSender::send_confirmed()`] completes once the frame was transmitted on the bus,
+/// rather than once it was queued in the kernel.
+///
+/// This enables `CAN_RAW_RECV_OWN_MSGS` on a dedicated socket, such that the kernel echoes each frame once
+/// the driver reports the transmission as completed. Waiting for the echo adds the full transmit latency,
+/// i.e. the time the frame spends in the queues of the kernel and the controller as well as the arbitration
+/// and transmission on the bus, plus a round-trip through the receive path. Only opt in if this pacing is
+/// actually needed, e.g. for bootloaders.
+///
+/// The socket also requests TX timestamps with `SO_TIMESTAMPING`, which are returned by
+/// [`ConfirmedSender::send_confirmed()`].
+///
+/// Note that virtual interfaces such as `vcan` echo frames immediately.
+pub struct ConfirmedSender {
+    socket: CanSocket,
+    /// ID and ID type the receive filters of the socket are currently set to
+    filter_id: Option<(u32, bool)>,
+    /// Number of frames sent with `filter_id` whose echo was not received yet, which is non-zero if
+    /// a previous send was cancelled
+    unconfirmed: usize,
+}
+
+impl ConfirmedSender {
+    /// Bind to the CAN socket with the given interface name
+    pub fn bind<T: AsRef<str>>(ifname: T) -> io::Result<Self> {
+        Self::new(CanSocket::bind(ifname)?)
+    }
+
+    /// Bind to the CAN socket with the given interface name and enable CAN-FD frames, see
+    /// [`CanSocket::bind_fd()`].
+    pub fn bind_fd<T: AsRef<str>>(ifname: T) -> io::Result<Self> {
+        Self::new(CanSocket::bind_fd(ifname)?)
+    }
+
+    fn new(socket: CanSocket) -> io::Result<Self> {
+        socket.set_recv_own_msgs(true)?;
+        // error frames would abort waiting for the echo, even though the frame may still be transmitted
+        set_socket_option(
+            socket.as_raw_fd(),
+            sys::SOL_CAN_RAW,
+            sys::CAN_RAW_ERR_FILTER,
+            &0_u32.to_ne_bytes(),
+        )?;
+        let flags: c_uint = libc::SOF_TIMESTAMPING_TX_HARDWARE
+            | libc::SOF_TIMESTAMPING_TX_SOFTWARE
+            | libc::SOF_TIMESTAMPING_SOFTWARE
+            | libc::SOF_TIMESTAMPING_RAW_HARDWARE
+            | libc::SOF_TIMESTAMPING_OPT_TSONLY;
+        set_socket_option(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_TIMESTAMPING,
+            &flags.to_ne_bytes(),
+        )?;
+        Ok(Self {
+            socket,
+            filter_id: None,
+            unconfirmed: 0,
+        })
+    }
+
+    /// Send a message and wait until it was transmitted. Returns the TX timestamp of the frame, in
+    /// microseconds since the Unix epoch.
+    ///
+    /// The hardware TX timestamp is returned if the driver supports it, otherwise the software TX timestamp
+    /// taken when the driver accepted the frame. If the driver reports neither, the time the echoed frame was
+    /// received is returned.
+    ///
+    /// If the frame is never transmitted, e.g. because it is not acknowledged or the controller is bus-off,
+    /// this waits forever. Use a timeout such as [`tokio::time::timeout()`] to bound the wait. This method
+    /// is cancel-safe: the echo of a cancelled frame is not mistaken for the echo of the next one.
+    pub async fn send_confirmed(&mut self, msg: Message) -> Result<Timestamp> {
+        // only receive frames with the ID of the message, others would just have to be discarded.
+        // the filters are only replaced if the ID changes, as consecutive frames usually share it.
+        let id = (msg.id(), msg.ext_id());
+        if self.filter_id != Some(id) {
+            let filter = Filter::new().exact(msg.id());
+            let filter = if msg.ext_id() {
+                filter.extended_only()
+            } else {
+                filter.standard_only()
+            };
+            let filters: Vec<CanFilter> = filter.compile().into_iter().map(Into::into).collect();
+            self.socket.set_filters(&filters)?;
+            // echoes of frames with the previous ID are skipped below
+            self.filter_id = Some(id);
+            self.unconfirmed = 0;
+        }
+
+        self.socket.send(msg).await?;
+        self.unconfirmed += 1;
+        // frames are echoed in the order they were sent, so the last echo is that of `msg`
+        let mut echo_timestamp = None;
+        while self.unconfirmed > 0 {
+            let (received, meta) = self.socket.recv_full().await?;
+            if meta.confirmed && (received.id(), received.ext_id()) == id {
+                self.unconfirmed -= 1;
+                echo_timestamp = meta.timestamp;
+            }
+        }
+
+        let timestamp = read_tx_timestamp(self.socket.as_raw_fd())?
+            .or(echo_timestamp)
+            .or_else(|| Timestamp::from_system_time(SystemTime::now()))
+            .unwrap_or(Timestamp { micros: 0 });
+        Ok(timestamp)
+    }
+
+    pub fn into_inner(self) -> CanSocket {
+        self.socket
+    }
+}
+
+/// Reads all TX timestamps queued on the error queue of the socket and returns the last one.
+fn read_tx_timestamp(fd: RawFd) -> io::Result<Option<Timestamp>> {
+    let mut last = None;
+    loop {
+        // large enough for a `struct scm_timestamping` and the queued error, u64 for alignment
+        let mut control = [0_u64; 16];
+        let mut hdr: libc::msghdr = unsafe { std::mem::zeroed() };
+        hdr.msg_control = control.as_mut_ptr() as *mut c_void;
+        hdr.msg_controllen = size_of_val(&control);
+
+        let size = unsafe { libc::recvmsg(fd, &mut hdr, libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT) };
+        if size < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == ErrorKind::WouldBlock {
+                return Ok(last);
+            }
+            return Err(err);
+        }
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&hdr);
+            while !cmsg.is_null() {
+                if ((*cmsg).cmsg_level, (*cmsg).cmsg_type)
+                    == (libc::SOL_SOCKET, libc::SCM_TIMESTAMPING)
+                {
+                    // software, deprecated and raw hardware timestamp
+                    let ts =
+                        ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const [libc::timespec; 3]);
+                    let ts = if ts[2].tv_sec != 0 { ts[2] } else { ts[0] };
+                    if ts.tv_sec != 0 {
+                        last = Some(Timestamp {
+                            micros: ts.tv_sec as u64 * 1_000_000 + ts.tv_nsec as u64 / 1000,
+                        });
+                    }
+                }
+                cmsg = libc::CMSG_NXTHDR(&hdr, cmsg);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl crate::Sender for ConfirmedSender {
+    async fn send(&mut self, msg: Message) -> Result<()> {
+        self.send_confirmed(msg).await.map(|_| ())
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        Ok(self.socket.close()?)
+    }
+}
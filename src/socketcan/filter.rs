@@ -0,0 +1,211 @@
+//! Receive filter configuration for [`super::CanSocket`]
+
+use std::os::raw::c_int;
+
+use super::sys;
+
+/// A receive filter as understood by the kernel.
+///
+/// A received frame matches the filter if `received_id & mask == id & mask`. If the filter is
+/// inverted, see [`CanFilter::invert()`], a frame matches if this condition does not hold.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CanFilter {
+    pub id: u32,
+    pub mask: u32,
+}
+
+impl CanFilter {
+    pub fn new(id: u32, mask: u32) -> Self {
+        Self { id, mask }
+    }
+
+    /// Invert the filter by setting the `CAN_INV_FILTER` flag, i.e. only frames that do *not*
+    /// match `id` and `mask` are received.
+    pub fn invert(mut self) -> Self {
+        self.id |= sys::CAN_INV_FILTER;
+        self
+    }
+
+    pub fn is_inverted(&self) -> bool {
+        self.id & sys::CAN_INV_FILTER != 0
+    }
+}
+
+/// Convert an `(id, mask)` pair as produced by [`crate::filter::Filter::compile()`].
+impl From<(u32, u32)> for CanFilter {
+    fn from((id, mask): (u32, u32)) -> Self {
+        Self::new(id, mask)
+    }
+}
+
+/// A single `setsockopt()` call on the `SOL_CAN_RAW` level.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SocketOption {
+    pub(crate) name: c_int,
+    pub(crate) label: &'static str,
+    pub(crate) value: Vec<u8>,
+}
+
+impl SocketOption {
+    fn flag(name: c_int, label: &'static str, enable: bool) -> Self {
+        Self {
+            name,
+            label,
+            value: (enable as c_int).to_ne_bytes().to_vec(),
+        }
+    }
+}
+
+/// Collects the receive filters and related socket options, which are then applied to a
+/// socket at once using [`super::CanSocket::apply_filters()`].
+///
+/// Options which are not explicitly configured are left untouched on the socket.
+/// The options are applied in the following order:
+///
+///  1. `CAN_RAW_LOOPBACK`
+///  2. `CAN_RAW_RECV_OWN_MSGS`
+///  3. `CAN_RAW_JOIN_FILTERS`
+///  4. `CAN_RAW_ERR_FILTER`
+///  5. `CAN_RAW_FILTER`
+///
+/// The ID filters are installed last, such that the socket only starts filtering once the remaining
+/// options are in place.
+#[derive(Debug, Clone, Default)]
+pub struct FilterConfig {
+    filters: Option<Vec<CanFilter>>,
+    error_mask: Option<u32>,
+    join_filters: Option<bool>,
+    loopback: Option<bool>,
+    recv_own_msgs: Option<bool>,
+}
+
+impl FilterConfig {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Add an ID filter. Once at least one filter is added, only matching frames are received.
+    pub fn filter(mut self, filter: CanFilter) -> Self {
+        self.filters.get_or_insert_with(Vec::new).push(filter);
+        self
+    }
+
+    /// Replace the ID filters. An empty list causes the socket to not receive any frames.
+    pub fn filters(mut self, filters: Vec<CanFilter>) -> Self {
+        self.filters = Some(filters);
+        self
+    }
+
+    /// Set the mask of error classes which should be received as error frames.
+    pub fn error_mask(mut self, mask: u32) -> Self {
+        self.error_mask = Some(mask);
+        self
+    }
+
+    /// If enabled, a frame must match all filters instead of any of them to be received.
+    pub fn join_filters(mut self, join: bool) -> Self {
+        self.join_filters = Some(join);
+        self
+    }
+
+    /// Enable or disable the local loopback of sent frames to other sockets on the same interface.
+    pub fn loopback(mut self, enable: bool) -> Self {
+        self.loopback = Some(enable);
+        self
+    }
+
+    /// Enable or disable receiving the frames sent on this socket.
+    pub fn recv_own_msgs(mut self, enable: bool) -> Self {
+        self.recv_own_msgs = Some(enable);
+        self
+    }
+
+    pub(crate) fn options(&self) -> Vec<SocketOption> {
+        let mut ret = Vec::new();
+        if let Some(enable) = self.loopback {
+            ret.push(SocketOption::flag(
+                sys::CAN_RAW_LOOPBACK,
+                "CAN_RAW_LOOPBACK",
+                enable,
+            ));
+        }
+        if let Some(enable) = self.recv_own_msgs {
+            ret.push(SocketOption::flag(
+                sys::CAN_RAW_RECV_OWN_MSGS,
+                "CAN_RAW_RECV_OWN_MSGS",
+                enable,
+            ));
+        }
+        if let Some(join) = self.join_filters {
+            ret.push(SocketOption::flag(
+                sys::CAN_RAW_JOIN_FILTERS,
+                "CAN_RAW_JOIN_FILTERS",
+                join,
+            ));
+        }
+        if let Some(mask) = self.error_mask {
+            ret.push(SocketOption {
+                name: sys::CAN_RAW_ERR_FILTER,
+                label: "CAN_RAW_ERR_FILTER",
+                value: mask.to_ne_bytes().to_vec(),
+            });
+        }
+        if let Some(filters) = &self.filters {
+            let value = filters
+                .iter()
+                .flat_map(|x| {
+                    let mut bytes = x.id.to_ne_bytes().to_vec();
+                    bytes.extend_from_slice(&x.mask.to_ne_bytes());
+                    bytes
+                })
+                .collect();
+            ret.push(SocketOption {
+                name: sys::CAN_RAW_FILTER,
+                label: "CAN_RAW_FILTER",
+                value,
+            });
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn option_sequence() {
+        let config = FilterConfig::new()
+            .filter(CanFilter::new(0x100, 0x700))
+            .filter(CanFilter::new(0x200, 0x7FF))
+            .error_mask(0x1FF)
+            .join_filters(false)
+            .recv_own_msgs(true)
+            .loopback(true);
+        let options = config.options();
+        let names: Vec<_> = options.iter().map(|x| x.name).collect();
+        assert_eq!(
+            names,
+            vec![
+                sys::CAN_RAW_LOOPBACK,
+                sys::CAN_RAW_RECV_OWN_MSGS,
+                sys::CAN_RAW_JOIN_FILTERS,
+                sys::CAN_RAW_ERR_FILTER,
+                sys::CAN_RAW_FILTER,
+            ]
+        );
+        assert_eq!(options[4].value.len(), 16);
+        assert_eq!(options[4].value[0..4], 0x100_u32.to_ne_bytes());
+
+        let filter = CanFilter::new(0x100, 0x700).invert();
+        assert!(filter.is_inverted());
+        assert!(!CanFilter::new(0x100, 0x700).is_inverted());
+        let options = FilterConfig::new().filter(filter).options();
+        assert_eq!(options[0].value[0..4], 0x20000100_u32.to_ne_bytes());
+
+        let options = FilterConfig::new().filters(vec![]).options();
+        assert_eq!(options.len(), 1);
+        assert!(options[0].value.is_empty());
+    }
+}
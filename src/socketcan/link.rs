@@ -0,0 +1,399 @@
+//! Configuration and state of CAN specific link attributes, such as the bitrate, by means of rtnetlink.
+//!
+//! The CAN attributes are nested into `IFLA_LINKINFO` / `IFLA_INFO_DATA` and are not supported by
+//! `netlink-packet-route`, hence they are encoded here.
+
+use std::io;
+
+use futures::{StreamExt, TryStreamExt};
+use rtnetlink::packet::nlas::link::{Info, InfoData, InfoKind, Nla};
+use rtnetlink::packet::{
+    LinkMessage, NetlinkMessage, NetlinkPayload, RtnlMessage, IFF_UP, NLM_F_ACK, NLM_F_REQUEST,
+};
+
+use super::get_interface_index_by_name;
+use crate::{BusState, Error, Result};
+
+const IFLA_CAN_BITTIMING: u16 = 1;
+const IFLA_CAN_STATE: u16 = 4;
+const IFLA_CAN_CTRLMODE: u16 = 5;
+const IFLA_CAN_RESTART_MS: u16 = 6;
+const IFLA_CAN_RESTART: u16 = 7;
+const IFLA_CAN_BERR_COUNTER: u16 = 8;
+
+/// Mask of the type of a netlink attribute without the `NLA_F_NESTED` and `NLA_F_NET_BYTEORDER` flags
+const NLA_TYPE_MASK: u16 = 0x3FFF;
+
+/// Size of `struct can_bittiming`, which consists of 8 `u32` fields starting with the bitrate
+const CAN_BITTIMING_LEN: usize = 32;
+
+/// Encode a netlink attribute, including the padding to a multiple of 4 bytes.
+fn encode_nla(kind: u16, value: &[u8]) -> Vec<u8> {
+    let len = 4 + value.len();
+    let mut ret = Vec::with_capacity((len + 3) & !3);
+    ret.extend_from_slice(&(len as u16).to_ne_bytes());
+    ret.extend_from_slice(&kind.to_ne_bytes());
+    ret.extend_from_slice(value);
+    ret.resize((len + 3) & !3, 0);
+    ret
+}
+
+/// Decode a sequence of netlink attributes into their type and value.
+fn decode_nlas(mut buf: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut ret = Vec::new();
+    while buf.len() >= 4 {
+        let len = u16::from_ne_bytes([buf[0], buf[1]]) as usize;
+        let kind = u16::from_ne_bytes([buf[2], buf[3]]) & NLA_TYPE_MASK;
+        if len < 4 || len > buf.len() {
+            break;
+        }
+        ret.push((kind, &buf[4..len]));
+        buf = &buf[((len + 3) & !3).min(buf.len())..];
+    }
+    ret
+}
+
+/// Convert the `enum can_state` reported by the kernel.
+fn bus_state_from_raw(state: u32) -> Option<BusState> {
+    match state {
+        0 => Some(BusState::ErrorActive),
+        1 => Some(BusState::ErrorWarning),
+        2 => Some(BusState::ErrorPassive),
+        3 => Some(BusState::BusOff),
+        4 => Some(BusState::Stopped),
+        5 => Some(BusState::Sleeping),
+        _ => None,
+    }
+}
+
+/// Control modes of a CAN controller, see [`set_ctrl_modes()`].
+///
+/// Modes which are `None` are left unchanged.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ControlModes {
+    /// Frames sent are also received by the controller itself
+    pub loopback: Option<bool>,
+    /// The controller only listens to the bus and never acknowledges or transmits frames
+    pub listen_only: Option<bool>,
+    /// The bus is sampled three times per bit
+    pub triple_sampling: Option<bool>,
+    /// Frames are not retransmitted if the transmission failed
+    pub one_shot: Option<bool>,
+    /// Bus errors are reported as error frames
+    pub berr_reporting: Option<bool>,
+    /// CAN-FD is enabled
+    pub fd: Option<bool>,
+    /// Frames are considered transmitted successfully even if they are not acknowledged
+    pub presume_ack: Option<bool>,
+    /// CAN-FD frames use the non-ISO CRC
+    pub fd_non_iso: Option<bool>,
+}
+
+impl ControlModes {
+    /// Encode the modes as `struct can_ctrlmode`, i.e. the mask of the modes to change followed by
+    /// their flags.
+    fn encode(&self) -> [u8; 8] {
+        let modes = [
+            self.loopback,
+            self.listen_only,
+            self.triple_sampling,
+            self.one_shot,
+            self.berr_reporting,
+            self.fd,
+            self.presume_ack,
+            self.fd_non_iso,
+        ];
+        let (mut mask, mut flags) = (0_u32, 0_u32);
+        for (k, mode) in modes.iter().enumerate() {
+            if let Some(enabled) = mode {
+                mask |= 1 << k;
+                if *enabled {
+                    flags |= 1 << k;
+                }
+            }
+        }
+        let mut ret = [0_u8; 8];
+        ret[..4].copy_from_slice(&mask.to_ne_bytes());
+        ret[4..].copy_from_slice(&flags.to_ne_bytes());
+        ret
+    }
+
+    /// Decode the modes reported by the kernel as `struct can_ctrlmode`, in which only the flags are set.
+    fn decode(value: &[u8]) -> Option<Self> {
+        let flags = value.get(4..8)?;
+        let flags = u32::from_ne_bytes([flags[0], flags[1], flags[2], flags[3]]);
+        let mode = |k: u32| Some(flags & (1 << k) != 0);
+        Some(Self {
+            loopback: mode(0),
+            listen_only: mode(1),
+            triple_sampling: mode(2),
+            one_shot: mode(3),
+            berr_reporting: mode(4),
+            fd: mode(5),
+            presume_ack: mode(6),
+            fd_non_iso: mode(7),
+        })
+    }
+}
+
+fn netlink_error(err: rtnetlink::Error) -> Error {
+    Error::Other(format!("{}", err))
+}
+
+/// Retrieve the link message of the given interface.
+async fn get_link(interface: &str) -> Result<LinkMessage> {
+    let index = get_interface_index_by_name(interface).await?;
+    let (con, handle, _) = rtnetlink::new_connection()?;
+    tokio::spawn(con);
+    handle
+        .link()
+        .get()
+        .match_index(index)
+        .execute()
+        .try_next()
+        .await
+        .map_err(netlink_error)?
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Interface `{}` not found", interface),
+            )
+            .into()
+        })
+}
+
+/// Retrieve the value of a CAN attribute of the given interface.
+async fn get_can_attribute(interface: &str, kind: u16) -> Result<Vec<u8>> {
+    let link = get_link(interface).await?;
+    let infos = link.nlas.into_iter().find_map(|nla| match nla {
+        Nla::Info(infos) => Some(infos),
+        _ => None,
+    });
+    let data = infos.into_iter().flatten().find_map(|info| match info {
+        Info::Data(InfoData::Other(data)) => Some(data),
+        _ => None,
+    });
+    data.as_deref()
+        .and_then(|data| {
+            decode_nlas(data)
+                .into_iter()
+                .find(|(x, _)| *x == kind)
+                .map(|(_, value)| value.to_vec())
+        })
+        .ok_or_else(|| {
+            Error::Other(format!(
+                "Interface `{}` does not report the CAN attribute {}",
+                interface, kind
+            ))
+        })
+}
+
+/// Fail with a descriptive error if the given interface is up.
+async fn ensure_down(interface: &str, action: &str) -> Result<()> {
+    let link = get_link(interface).await?;
+    if link.header.flags & IFF_UP != 0 {
+        return Err(Error::Other(format!(
+            "Interface `{}` must be down to {}",
+            interface, action
+        )));
+    }
+    Ok(())
+}
+
+/// Apply the given encoded CAN attributes to the interface.
+///
+/// This requires a `RTM_NEWLINK` request as `RTM_SETLINK` ignores the `IFLA_LINKINFO` attribute.
+async fn set_can_attributes(interface: &str, attributes: Vec<u8>) -> Result<()> {
+    let index = get_interface_index_by_name(interface).await?;
+    let (con, mut handle, _) = rtnetlink::new_connection()?;
+    tokio::spawn(con);
+    let mut message = LinkMessage::default();
+    message.header.index = index;
+    message.nlas.push(Nla::Info(vec![
+        Info::Kind(InfoKind::Other("can".to_string())),
+        Info::Data(InfoData::Other(attributes)),
+    ]));
+    let mut request = NetlinkMessage::from(RtnlMessage::NewLink(message));
+    request.header.flags = NLM_F_REQUEST | NLM_F_ACK;
+    let mut response = handle.request(request).map_err(netlink_error)?;
+    while let Some(msg) = response.next().await {
+        if let NetlinkPayload::Error(err) = msg.payload {
+            return Err(io::Error::from_raw_os_error(-err.code).into());
+        }
+    }
+    Ok(())
+}
+
+/// Set the bitrate of the given CAN interface. The bit timing parameters are calculated by the kernel.
+///
+/// This is like calling
+/// ```sh
+/// ip link set can0 type can bitrate 500000
+/// ```
+///
+/// The interface must be down, otherwise an [`Error::Other`] is returned.
+///
+/// Note, that this requires the capability `CAP_NET_ADMIN`
+pub async fn set_bitrate(interface: &str, bitrate: u32) -> Result<()> {
+    if bitrate == 0 {
+        return Err(Error::InvalidBitRate);
+    }
+    ensure_down(interface, "change the bitrate").await?;
+    let mut bittiming = [0_u8; CAN_BITTIMING_LEN];
+    bittiming[..4].copy_from_slice(&bitrate.to_ne_bytes());
+    set_can_attributes(interface, encode_nla(IFLA_CAN_BITTIMING, &bittiming)).await
+}
+
+/// Enable or disable control modes of the CAN controller of the given interface, such as listen-only mode.
+///
+/// This is like calling
+/// ```sh
+/// ip link set can0 type can listen-only on
+/// ```
+///
+/// The interface must be down, otherwise an [`Error::Other`] is returned. If the driver does not support
+/// one of the requested modes, the kernel rejects the request with `EOPNOTSUPP`.
+///
+/// Note, that this requires the capability `CAP_NET_ADMIN`
+pub async fn set_ctrl_modes(interface: &str, modes: ControlModes) -> Result<()> {
+    ensure_down(interface, "change the control modes").await?;
+    set_can_attributes(interface, encode_nla(IFLA_CAN_CTRLMODE, &modes.encode())).await
+}
+
+/// Return the control modes of the CAN controller of the given interface, with all modes set to `Some`.
+///
+/// This is like calling
+/// ```sh
+/// ip -details link show can0
+/// ```
+pub async fn get_ctrl_modes(interface: &str) -> Result<ControlModes> {
+    let value = get_can_attribute(interface, IFLA_CAN_CTRLMODE).await?;
+    ControlModes::decode(&value)
+        .ok_or_else(|| Error::Other(format!("Invalid CAN control modes: {:02x?}", value)))
+}
+
+/// Set the delay after which the CAN controller is restarted automatically once it went bus-off.
+/// A delay of 0 disables the automatic restart.
+///
+/// This is like calling
+/// ```sh
+/// ip link set can0 type can restart-ms 100
+/// ```
+///
+/// The interface must be down, otherwise an [`Error::Other`] is returned.
+///
+/// Note, that this requires the capability `CAP_NET_ADMIN`
+pub async fn set_restart_ms(interface: &str, ms: u32) -> Result<()> {
+    ensure_down(interface, "change the restart delay").await?;
+    set_can_attributes(
+        interface,
+        encode_nla(IFLA_CAN_RESTART_MS, &ms.to_ne_bytes()),
+    )
+    .await
+}
+
+/// Manually restart the CAN controller of the given interface after it went bus-off.
+///
+/// This is like calling
+/// ```sh
+/// ip link set can0 type can restart
+/// ```
+///
+/// The kernel rejects the request with `EBUSY` if the controller is not in the bus-off state.
+///
+/// Note, that this requires the capability `CAP_NET_ADMIN`
+pub async fn restart(interface: &str) -> Result<()> {
+    set_can_attributes(
+        interface,
+        encode_nla(IFLA_CAN_RESTART, &1_u32.to_ne_bytes()),
+    )
+    .await
+}
+
+/// Return the state of the CAN controller of the given interface.
+///
+/// This is like calling
+/// ```sh
+/// ip -details link show can0
+/// ```
+///
+/// Virtual interfaces, such as `vcan`, do not have a bus state and an [`Error::Other`] is returned.
+pub async fn get_bus_state(interface: &str) -> Result<BusState> {
+    let value = get_can_attribute(interface, IFLA_CAN_STATE).await?;
+    value
+        .get(..4)
+        .and_then(|x| bus_state_from_raw(u32::from_ne_bytes([x[0], x[1], x[2], x[3]])))
+        .ok_or_else(|| Error::Other(format!("Invalid CAN state: {:02x?}", value)))
+}
+
+/// Return the transmit and receive error counters of the CAN controller of the given interface.
+///
+/// Note that not all drivers report the error counters.
+pub async fn get_error_counters(interface: &str) -> Result<(u16, u16)> {
+    let value = get_can_attribute(interface, IFLA_CAN_BERR_COUNTER).await?;
+    match value[..] {
+        [tx0, tx1, rx0, rx1, ..] => Ok((
+            u16::from_ne_bytes([tx0, tx1]),
+            u16::from_ne_bytes([rx0, rx1]),
+        )),
+        _ => Err(Error::Other(format!(
+            "Invalid CAN error counters: {:02x?}",
+            value
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nla_padding() {
+        let nla = encode_nla(7, &[1]);
+        assert_eq!(nla.len(), 8);
+        assert_eq!(nla[..2], 5_u16.to_ne_bytes());
+        assert_eq!(nla[2..4], 7_u16.to_ne_bytes());
+        assert_eq!(nla[4..], [1, 0, 0, 0]);
+        assert_eq!(encode_nla(1, &[0; 32]).len(), 36);
+    }
+
+    #[test]
+    fn encode_ctrl_modes() {
+        let modes = ControlModes {
+            listen_only: Some(true),
+            one_shot: Some(false),
+            ..Default::default()
+        };
+        let encoded = modes.encode();
+        assert_eq!(encoded[..4], 0x0A_u32.to_ne_bytes());
+        assert_eq!(encoded[4..], 0x02_u32.to_ne_bytes());
+        assert_eq!(ControlModes::default().encode(), [0; 8]);
+    }
+
+    #[test]
+    fn decode_ctrl_modes() {
+        let mut value = [0_u8; 8];
+        value[4..].copy_from_slice(&0x28_u32.to_ne_bytes());
+        let modes = ControlModes::decode(&value).unwrap();
+        assert_eq!(modes.one_shot, Some(true));
+        assert_eq!(modes.fd, Some(true));
+        assert_eq!(modes.loopback, Some(false));
+        assert!(ControlModes::decode(&value[..4]).is_none());
+    }
+
+    #[test]
+    fn decode_attributes() {
+        let mut buf = encode_nla(IFLA_CAN_STATE, &2_u32.to_ne_bytes());
+        buf.extend(encode_nla(0x8000 | 3, &[1]));
+        buf.extend(encode_nla(IFLA_CAN_BERR_COUNTER, &[1, 0, 2, 0]));
+        let nlas = decode_nlas(&buf);
+        assert_eq!(nlas.len(), 3);
+        assert_eq!(nlas[0], (IFLA_CAN_STATE, &2_u32.to_ne_bytes()[..]));
+        assert_eq!(nlas[1], (3, &[1][..]));
+        assert_eq!(nlas[2].0, IFLA_CAN_BERR_COUNTER);
+        assert_eq!(bus_state_from_raw(2), Some(BusState::ErrorPassive));
+        assert_eq!(bus_state_from_raw(6), None);
+        // truncated attributes are ignored
+        assert_eq!(decode_nlas(&buf[..6]).len(), 0);
+    }
+}
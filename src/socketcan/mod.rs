@@ -1,11 +1,16 @@
 //! Implements an async interface to the Linux SocketCAN
 
-use std::ffi::{c_void, CString};
+use std::convert::TryFrom;
+use std::ffi::{c_void, CStr, CString};
 use std::io::{self, ErrorKind};
-use std::mem::{size_of, MaybeUninit};
+use std::mem::{size_of, size_of_val, MaybeUninit};
 use std::os::raw::{c_int, c_short};
-use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
 use std::task::{Context, Poll};
+use std::time::SystemTime;
 
 use futures::future::poll_fn;
 use futures::{ready, TryStreamExt};
@@ -16,46 +21,146 @@ use mio::unix::SourceFd;
 use rtnetlink::packet::nlas::link::{Info, InfoKind, Nla, State};
 use tokio::io::unix::AsyncFd;
 
-use crate::socketcan::sys::{CanFrame, CanSocketAddr, AF_CAN};
-use crate::Message;
+use crate::socketcan::sys::{CanFdFrame, CanSocketAddr, Frame, AF_CAN, CANFD_MTU, CAN_MTU};
+use crate::{trace, Message, Timestamp};
 use crate::{DeviceInfo, Result};
 use mio::{Interest, Registry, Token};
 
 use async_trait::async_trait;
 
+mod confirm;
+mod filter;
+mod link;
 mod sys;
 
+pub use crate::BusState;
+pub use confirm::ConfirmedSender;
+pub use filter::{CanFilter, FilterConfig};
+pub use link::{
+    get_bus_state, get_ctrl_modes, get_error_counters, restart, set_bitrate, set_ctrl_modes,
+    set_restart_ms, ControlModes,
+};
+pub use sys::RawCanFrame;
+
+/// Metadata of a received frame as reported by the kernel, see [`CanSocket::recv_full()`].
+#[derive(Debug, Clone)]
+pub struct RecvMeta {
+    /// Index of the interface the frame was received on
+    pub iface: Option<u32>,
+    /// Time the frame was received by the kernel, in microseconds since the Unix epoch
+    pub timestamp: Option<Timestamp>,
+    /// Total number of frames dropped by the kernel since the socket was opened, e.g. because
+    /// the receive buffer was full
+    pub dropped: u64,
+    /// True if the frame was sent by this socket, which is only reported if `CAN_RAW_RECV_OWN_MSGS` is enabled,
+    /// see [`ConfirmedSender`]
+    pub confirmed: bool,
+}
+
 /// A type that connects to CAN socket
 pub struct CanSocket {
-    inner: AsyncFd<RawFd>,
+    /// `None` once the socket was closed with [`CanSocket::close()`]
+    inner: Option<AsyncFd<OwnedFd>>,
+    dropped_malformed: AtomicU64,
+    fd_frames: bool,
+    /// Error encountered by [`CanSocket::recv_many()`] after it already read some frames, returned
+    /// by the next receive call
+    deferred_error: Mutex<Option<io::Error>>,
+    /// Set once one-shot mode was found or made enabled on the interface, see [`CanSocket::send_once()`]
+    one_shot: AtomicBool,
 }
 
 impl Drop for CanSocket {
     fn drop(&mut self) {
-        unsafe {
-            libc::close(self.as_raw_fd());
-        }
+        let _ = self.close();
     }
 }
 
+/// Returns -1 if the socket was closed.
 impl AsRawFd for CanSocket {
     fn as_raw_fd(&self) -> RawFd {
-        self.inner.get_ref().as_raw_fd()
+        self.inner.as_ref().map_or(-1, |x| x.get_ref().as_raw_fd())
     }
 }
 
 impl CanSocket {
     /// Bind to the CAN socket with the given interface name
     pub fn bind<T: AsRef<str>>(ifname: T) -> io::Result<Self> {
-        let name = CString::new(ifname.as_ref()).unwrap();
-        let ifindex = unsafe { libc::if_nametoindex(name.as_ptr()) };
-        if ifindex == 0 {
-            return Err(io::Error::last_os_error());
+        Self::bind_with(ifname.as_ref(), false)
+    }
+
+    /// Bind to the CAN socket with the given interface name and enable CAN-FD frames.
+    ///
+    /// Received CAN-FD frames are returned as [`crate::Message::Fd`] while classic frames
+    /// are still returned as before. Fails if the interface is not CAN-FD capable, i.e.
+    /// if its MTU is not set to that of a CAN-FD frame.
+    pub fn bind_fd<T: AsRef<str>>(ifname: T) -> io::Result<Self> {
+        Self::bind_with(ifname.as_ref(), true)
+    }
+
+    /// Bind to the CAN socket with the given interface index, as reported by [`list_devices()`].
+    ///
+    /// An index of 0 binds the socket to all CAN interfaces. Use [`CanSocket::recv_with_iface()`] to tell
+    /// which interface a frame was received on. Sending requires the socket to be bound to a single
+    /// interface.
+    pub fn bind_index(ifindex: u32) -> io::Result<Self> {
+        Self::open(ifindex)
+    }
+
+    /// Bind to the CAN socket with the given interface name and enable CAN-FD frames if the interface
+    /// supports them, i.e. if its MTU is set to that of a CAN-FD frame.
+    ///
+    /// Received messages are returned as classic or CAN-FD messages accordingly. Use
+    /// [`CanSocket::is_fd()`] to check whether CAN-FD frames were enabled.
+    pub fn bind_auto<T: AsRef<str>>(ifname: T) -> io::Result<Self> {
+        let ifname = ifname.as_ref();
+        let name = CString::new(ifname).unwrap();
+        let mut socket = Self::open(name_to_index(&name)?)?;
+        if interface_mtu(socket.as_raw_fd(), &name)? == CANFD_MTU {
+            socket.enable_fd_frames()?;
         }
+        Ok(socket)
+    }
+
+    fn bind_with(ifname: &str, fd_frames: bool) -> io::Result<Self> {
+        let name = CString::new(ifname).unwrap();
+        let mut socket = Self::open(name_to_index(&name)?)?;
+        if fd_frames {
+            if interface_mtu(socket.as_raw_fd(), &name)? != CANFD_MTU {
+                return Err(io::Error::new(
+                    ErrorKind::Unsupported,
+                    format!("Interface `{}` does not support CAN-FD", ifname),
+                ));
+            }
+            socket.enable_fd_frames()?;
+        }
+        Ok(socket)
+    }
+
+    fn enable_fd_frames(&mut self) -> io::Result<()> {
+        let on = (1 as c_int).to_ne_bytes();
+        set_socket_option(
+            self.as_raw_fd(),
+            sys::SOL_CAN_RAW,
+            sys::CAN_RAW_FD_FRAMES,
+            &on,
+        )?;
+        self.fd_frames = true;
+        Ok(())
+    }
+
+    /// Returns true if the socket sends and receives CAN-FD frames.
+    pub fn is_fd(&self) -> bool {
+        self.fd_frames
+    }
+
+    fn open(ifindex: u32) -> io::Result<Self> {
         let fd = unsafe { libc::socket(libc::PF_CAN, libc::SOCK_RAW, sys::CAN_RAW as c_int) };
         if fd == -1 {
             return Err(io::Error::last_os_error());
         }
+        // closes the socket if any of the following steps fails
+        let owned = unsafe { OwnedFd::from_raw_fd(fd) };
 
         let addr = CanSocketAddr {
             _af_can: AF_CAN as c_short,
@@ -81,35 +186,308 @@ impl CanSocket {
             return Err(io::Error::last_os_error());
         }
 
-        let inner = AsyncFd::new(fd)?;
-        Ok(Self { inner })
+        // report receive timestamps and dropped frames as ancillary data, see `recv_full()`
+        let on = (1 as c_int).to_ne_bytes();
+        set_socket_option(fd, libc::SOL_SOCKET, libc::SO_TIMESTAMP, &on)?;
+        set_socket_option(fd, libc::SOL_SOCKET, libc::SO_RXQ_OVFL, &on)?;
+
+        // deliver the error frames which are reported as `BusError`, see `recv()`
+        let err_mask = sys::CAN_ERR_CRTL | sys::CAN_ERR_BUSOFF;
+        set_socket_option(
+            fd,
+            sys::SOL_CAN_RAW,
+            sys::CAN_RAW_ERR_FILTER,
+            &err_mask.to_ne_bytes(),
+        )?;
+
+        let inner = AsyncFd::new(owned)?;
+        Ok(Self {
+            inner: Some(inner),
+            dropped_malformed: AtomicU64::new(0),
+            fd_frames: false,
+            deferred_error: Mutex::new(None),
+            one_shot: AtomicBool::new(false),
+        })
     }
 
     /// Try to receive a [`crate::Message`] from the CAN bus
+    ///
+    /// Error frames reporting a bus warning, error passive or bus-off state are returned as an error
+    /// wrapping a [`crate::BusError`], which converts to [`crate::Error::BusError`].
     pub async fn recv(&self) -> io::Result<Message> {
-        poll_fn(|cx| self.poll_read(cx)).await
+        let (msg, _) = poll_fn(|cx| self.poll_read(cx, read_from_fd)).await?;
+        Ok(msg)
     }
 
-    fn poll_read(&self, cx: &mut Context) -> Poll<io::Result<Message>> {
-        loop {
-            let mut guard = ready!(self.inner.poll_read_ready(cx))?;
-            match guard.try_io(|fd| read_from_fd(fd.as_raw_fd())) {
+    /// Try to receive a [`crate::Message`] from the CAN bus together with the [`RecvMeta`] data
+    /// reported by the kernel.
+    ///
+    /// All metadata is retrieved using a single `recvmsg()` call.
+    pub async fn recv_full(&self) -> io::Result<(Message, RecvMeta)> {
+        poll_fn(|cx| self.poll_read(cx, recvmsg_from_fd)).await
+    }
+
+    /// Try to receive a [`crate::Message`] from the CAN bus together with the index of the interface it
+    /// was received on, which is useful for sockets bound to all interfaces with [`CanSocket::bind_index()`].
+    pub async fn recv_with_iface(&self) -> io::Result<(Message, u32)> {
+        let (msg, meta) = self.recv_full().await?;
+        // the kernel always reports the address of the interface for raw CAN sockets
+        Ok((msg, meta.iface.unwrap_or(0)))
+    }
+
+    /// Try to receive a [`crate::Message`] from the CAN bus together with the time it was received
+    /// by the kernel, in microseconds since the Unix epoch.
+    ///
+    /// In the unlikely case that the kernel does not report a timestamp, the current time is used instead.
+    pub async fn recv_with_timestamp(&self) -> io::Result<(Message, Timestamp)> {
+        let (msg, meta) = self.recv_full().await?;
+        let timestamp = meta
+            .timestamp
+            .or_else(|| Timestamp::from_system_time(SystemTime::now()))
+            .unwrap_or(Timestamp { micros: 0 });
+        Ok((msg, timestamp))
+    }
+
+    /// Receive the next frame into `frame`, overwriting its previous contents.
+    ///
+    /// Unlike [`CanSocket::recv()`], this does not allocate and returns error frames and frames which cannot
+    /// be represented as [`crate::Message`] as they are. Use `Message::try_from()` to convert the frame.
+    pub async fn recv_into(&self, frame: &mut RawCanFrame) -> io::Result<()> {
+        if let Some(err) = self.deferred_error.lock().unwrap().take() {
+            return Err(err);
+        }
+        poll_fn(|cx| loop {
+            let mut guard = ready!(self.async_fd()?.poll_read_ready(cx))?;
+            match guard.try_io(|fd| read_raw_from_fd(fd.as_raw_fd(), frame)) {
                 Ok(result) => return Poll::Ready(result),
                 Err(_would_block) => continue,
             }
+        })
+        .await
+    }
+
+    /// Receive up to `max` messages into `buf`, returning the number of messages received.
+    ///
+    /// Waits for the first message, then reads all further messages which are already queued in the socket
+    /// without waiting. If an error occurs after some messages were read, these messages are returned and the
+    /// error is returned by the next receive call.
+    pub async fn recv_many(&self, buf: &mut Vec<Message>, max: usize) -> io::Result<usize> {
+        if max == 0 {
+            return Ok(0);
+        }
+        buf.push(self.recv().await?);
+        let mut count = 1;
+        while count < max {
+            match read_from_fd(self.as_raw_fd()) {
+                Ok((frame, meta)) => match self.decode_message(frame, meta) {
+                    Some(Ok((msg, _))) => {
+                        buf.push(msg);
+                        count += 1;
+                    }
+                    Some(Err(err)) => {
+                        *self.deferred_error.lock().unwrap() = Some(err);
+                        break;
+                    }
+                    None => {}
+                },
+                // readiness is cleared by the next call to `poll_read()`
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    *self.deferred_error.lock().unwrap() = Some(err);
+                    break;
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    fn poll_read<T>(
+        &self,
+        cx: &mut Context,
+        read: fn(RawFd) -> io::Result<(Frame, T)>,
+    ) -> Poll<io::Result<(Message, T)>> {
+        if let Some(err) = self.deferred_error.lock().unwrap().take() {
+            return Poll::Ready(Err(err));
+        }
+        loop {
+            let mut guard = ready!(self.async_fd()?.poll_read_ready(cx))?;
+            match guard.try_io(|fd| read(fd.as_raw_fd())) {
+                Ok(Ok((frame, meta))) => {
+                    if let Some(result) = self.decode_message(frame, meta) {
+                        return Poll::Ready(result);
+                    }
+                }
+                Ok(Err(err)) => return Poll::Ready(Err(err)),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Convert a received frame to a message. Returns `None` if the frame is skipped.
+    fn decode_message<T>(&self, frame: Frame, meta: T) -> Option<io::Result<(Message, T)>> {
+        if frame.is_error_frame() {
+            match frame.bus_error() {
+                Some(err) => {
+                    trace::bus_error("socketcan", &err);
+                    return Some(Err(io::Error::other(err)));
+                }
+                None => log::debug!("Ignoring CAN error frame: {:?}", frame),
+            }
+            return None;
+        }
+        match Message::try_from(&frame) {
+            Ok(msg) => {
+                trace::recv("socketcan", &msg);
+                Some(Ok((msg, meta)))
+            }
+            Err(err) => {
+                self.dropped_malformed.fetch_add(1, Ordering::Relaxed);
+                log::warn!("Dropping malformed CAN frame ({:?}): {:?}", err, frame);
+                None
+            }
         }
     }
 
+    /// Returns the number of received frames which were dropped because they could not be decoded
+    /// into a [`crate::Message`].
+    pub fn dropped_malformed_count(&self) -> u64 {
+        self.dropped_malformed.load(Ordering::Relaxed)
+    }
+
     /// Try to send a [`crate::Message`] to the CAN bus
+    ///
+    /// Sending a [`crate::Message::Fd`] requires the socket to be bound with [`CanSocket::bind_fd()`].
     pub async fn send(&self, msg: Message) -> io::Result<()> {
-        let frame: CanFrame = CanFrame::from(msg);
-        let ret = poll_fn(|cx| self.poll_write(cx, &frame)).await;
-        Ok(ret?)
+        if matches!(msg, Message::Fd(_)) && !self.fd_frames {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "CAN-FD frames are not enabled on this socket",
+            ));
+        }
+        trace::send("socketcan", &msg);
+        let frame = Frame::from(msg);
+        poll_fn(|cx| self.poll_write(cx, &frame)).await
+    }
+
+    /// Send a message which is transmitted only once, i.e. it is not retransmitted if arbitration is lost
+    /// or the frame is not acknowledged.
+    ///
+    /// SocketCAN supports this only for a whole interface, hence one-shot mode must be enabled with
+    /// [`CanSocket::enable_one_shot()`] or [`set_ctrl_modes()`] beforehand. Otherwise, this fails with
+    /// [`crate::Error::NotSupported`], as it does for sockets bound to all interfaces. The control modes
+    /// are queried with netlink until one-shot mode is found enabled.
+    pub async fn send_once(&self, msg: Message) -> Result<()> {
+        if !self.one_shot.load(Ordering::Relaxed) {
+            let interface = self.single_interface_name()?;
+            if get_ctrl_modes(&interface).await?.one_shot != Some(true) {
+                return Err(crate::Error::NotSupported(format!(
+                    "One-shot mode is not enabled on `{}`",
+                    interface
+                )));
+            }
+            self.one_shot.store(true, Ordering::Relaxed);
+        }
+        Ok(self.send(msg).await?)
+    }
+
+    /// Enable one-shot mode on the interface the socket is bound to, such that frames are not retransmitted,
+    /// see [`CanSocket::send_once()`].
+    ///
+    /// The interface is brought down to change the control mode and brought up again afterwards.
+    ///
+    /// Note, that this requires the capability `CAP_NET_ADMIN`
+    pub async fn enable_one_shot(&self) -> Result<()> {
+        let interface = self.single_interface_name()?;
+        set_interface_down(&interface).await?;
+        let modes = ControlModes {
+            one_shot: Some(true),
+            ..Default::default()
+        };
+        let result = set_ctrl_modes(&interface, modes).await;
+        set_interface_up(&interface).await?;
+        result?;
+        self.one_shot.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns the name of the interface the socket is bound to, or fails with [`crate::Error::NotSupported`]
+    /// if it is bound to all interfaces.
+    fn single_interface_name(&self) -> Result<String> {
+        if self.interface_index()? == 0 {
+            return Err(crate::Error::NotSupported(
+                "One-shot mode requires a socket bound to a single interface".to_string(),
+            ));
+        }
+        Ok(self.interface_name()?)
+    }
+
+    /// Send a raw frame, which may be any frame accepted by the kernel, e.g. with reserved bits set.
+    ///
+    /// Sending a CAN-FD frame requires the socket to be bound with [`CanSocket::bind_fd()`].
+    pub async fn send_raw(&self, frame: &RawCanFrame) -> io::Result<()> {
+        if frame.is_fd() && !self.fd_frames {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "CAN-FD frames are not enabled on this socket",
+            ));
+        }
+        let frame = Frame::from(frame);
+        poll_fn(|cx| self.poll_write(cx, &frame)).await
+    }
+
+    /// Queue the given messages for transmission with a single `sendmmsg()` call, without waiting.
+    ///
+    /// Returns the number of messages which were queued, which may be less than `msgs.len()` if the transmit
+    /// queue of the socket is full. In that case, the remaining messages should be sent again later.
+    pub fn send_batch(&self, msgs: &[Message]) -> io::Result<usize> {
+        if msgs.iter().any(|x| matches!(x, Message::Fd(_))) && !self.fd_frames {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "CAN-FD frames are not enabled on this socket",
+            ));
+        }
+        if msgs.is_empty() {
+            return Ok(0);
+        }
+        msgs.iter().for_each(|msg| trace::send("socketcan", msg));
+        let frames: Vec<Frame> = msgs.iter().cloned().map(Frame::from).collect();
+        let mut iovs: Vec<libc::iovec> = frames
+            .iter()
+            .map(|frame| libc::iovec {
+                iov_base: frame.as_ptr() as *mut c_void,
+                iov_len: frame.mtu(),
+            })
+            .collect();
+        let mut hdrs: Vec<libc::mmsghdr> = iovs
+            .iter_mut()
+            .map(|iov| {
+                let mut hdr: libc::mmsghdr = unsafe { std::mem::zeroed() };
+                hdr.msg_hdr.msg_iov = iov;
+                hdr.msg_hdr.msg_iovlen = 1;
+                hdr
+            })
+            .collect();
+        let sent = unsafe {
+            libc::sendmmsg(
+                self.as_raw_fd(),
+                hdrs.as_mut_ptr(),
+                hdrs.len() as _,
+                libc::MSG_DONTWAIT,
+            )
+        };
+        if sent < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == ErrorKind::WouldBlock || err.raw_os_error() == Some(libc::ENOBUFS) {
+                return Ok(0);
+            }
+            return Err(err);
+        }
+        Ok(sent as usize)
     }
 
-    fn poll_write(&self, cx: &mut Context<'_>, frame: &CanFrame) -> Poll<io::Result<()>> {
+    fn poll_write(&self, cx: &mut Context<'_>, frame: &Frame) -> Poll<io::Result<()>> {
         loop {
-            let mut guard = ready!(self.inner.poll_write_ready(cx))?;
+            let mut guard = ready!(self.async_fd()?.poll_write_ready(cx))?;
             match guard.try_io(|fd| write_to_fd(fd.as_raw_fd(), frame)) {
                 Ok(result) => return Poll::Ready(result),
                 Err(_would_block) => continue,
@@ -117,21 +495,130 @@ impl CanSocket {
         }
     }
 
+    /// Apply the receive filters and socket options collected in the given [`FilterConfig`].
+    ///
+    /// In case one of the options cannot be set, the returned error names the failing option.
+    /// The options preceding it remain applied.
+    pub fn apply_filters(&self, config: &FilterConfig) -> io::Result<()> {
+        for option in config.options() {
+            set_socket_option(
+                self.as_raw_fd(),
+                sys::SOL_CAN_RAW,
+                option.name,
+                &option.value,
+            )
+            .map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!("Setting `{}` failed: {}", option.label, err),
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Install the given ID filters with `CAN_RAW_FILTER`, replacing any previously installed filters.
+    ///
+    /// A frame is received if it matches any of the filters. An empty slice causes the socket to not
+    /// receive any frames. This is a shorthand for [`CanSocket::apply_filters()`] with only the ID
+    /// filters configured.
+    pub fn set_filters(&self, filters: &[CanFilter]) -> io::Result<()> {
+        self.apply_filters(&FilterConfig::new().filters(filters.to_vec()))
+    }
+
+    /// Enable or disable receiving the frames sent by this socket with `CAN_RAW_RECV_OWN_MSGS`.
+    ///
+    /// This is disabled by default. Received own frames are reported with [`RecvMeta::confirmed`] set, see
+    /// [`CanSocket::recv_full()`]. Requires loopback to be enabled, see [`CanSocket::set_loopback()`].
+    pub fn set_recv_own_msgs(&self, enabled: bool) -> io::Result<()> {
+        let value = (enabled as c_int).to_ne_bytes();
+        set_socket_option(
+            self.as_raw_fd(),
+            sys::SOL_CAN_RAW,
+            sys::CAN_RAW_RECV_OWN_MSGS,
+            &value,
+        )
+    }
+
+    /// Enable or disable delivering the frames sent by this socket to other sockets on the same host with
+    /// `CAN_RAW_LOOPBACK`. This is enabled by default.
+    pub fn set_loopback(&self, enabled: bool) -> io::Result<()> {
+        let value = (enabled as c_int).to_ne_bytes();
+        set_socket_option(
+            self.as_raw_fd(),
+            sys::SOL_CAN_RAW,
+            sys::CAN_RAW_LOOPBACK,
+            &value,
+        )
+    }
+
+    /// Returns the name of the interface the socket is bound to.
+    pub fn interface_name(&self) -> io::Result<String> {
+        let index = self.interface_index()?;
+        let mut name = [0 as libc::c_char; libc::IF_NAMESIZE];
+        if unsafe { libc::if_indextoname(index, name.as_mut_ptr()) }.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let name = unsafe { CStr::from_ptr(name.as_ptr()) };
+        Ok(name.to_string_lossy().into_owned())
+    }
+
+    /// Returns the index of the interface the socket is bound to, which is 0 for all interfaces.
+    fn interface_index(&self) -> io::Result<u32> {
+        let mut addr = MaybeUninit::<CanSocketAddr>::zeroed();
+        let mut len = size_of::<CanSocketAddr>() as libc::socklen_t;
+        let ok = unsafe {
+            libc::getsockname(
+                self.as_raw_fd(),
+                addr.as_mut_ptr() as *mut sockaddr,
+                &mut len,
+            )
+        };
+        if ok != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { addr.assume_init() }.if_index as u32)
+    }
+
+    /// Close the socket explicitly instead of when it is dropped, and report if closing failed.
+    ///
+    /// Sending and receiving on a closed socket fails with [`ErrorKind::NotConnected`]. Sockets obtained
+    /// with [`CanSocket::try_clone()`] remain open.
+    pub fn close(&mut self) -> io::Result<()> {
+        if let Some(inner) = self.inner.take() {
+            let fd = inner.into_inner().into_raw_fd();
+            if unsafe { libc::close(fd) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    fn async_fd(&self) -> io::Result<&AsyncFd<OwnedFd>> {
+        self.inner
+            .as_ref()
+            .ok_or_else(|| io::Error::from(ErrorKind::NotConnected))
+    }
+
     pub fn try_clone(&self) -> io::Result<Self> {
         let new_fd = unsafe { libc::dup(self.as_raw_fd()) };
         if new_fd < 0 {
             return Err(io::Error::last_os_error());
         }
+        let new_fd = unsafe { OwnedFd::from_raw_fd(new_fd) };
         Ok(Self {
-            inner: AsyncFd::new(new_fd)?,
+            inner: Some(AsyncFd::new(new_fd)?),
+            dropped_malformed: AtomicU64::new(0),
+            fd_frames: self.fd_frames,
+            deferred_error: Mutex::new(None),
+            one_shot: AtomicBool::new(self.one_shot.load(Ordering::Relaxed)),
         })
     }
 }
 
-fn write_to_fd(fd: RawFd, frame: &CanFrame) -> io::Result<()> {
-    let frame = frame as *const CanFrame as *const c_void;
-    let written = unsafe { libc::write(fd, frame, size_of::<CanFrame>()) };
-    if written as usize != size_of::<CanFrame>() {
+fn write_to_fd(fd: RawFd, frame: &Frame) -> io::Result<()> {
+    let written = unsafe { libc::write(fd, frame.as_ptr() as *const c_void, frame.mtu()) };
+    if written as usize != frame.mtu() {
         Err(io::Error::last_os_error())
     } else {
         // successfully sent
@@ -139,16 +626,146 @@ fn write_to_fd(fd: RawFd, frame: &CanFrame) -> io::Result<()> {
     }
 }
 
-fn read_from_fd(fd: RawFd) -> io::Result<Message> {
-    let mut frame = MaybeUninit::<CanFrame>::uninit();
-    let (frame, size) = unsafe {
-        let size = libc::read(fd, frame.as_mut_ptr() as *mut c_void, size_of::<CanFrame>());
-        (frame.assume_init(), size as usize)
+/// Decode a frame from the receive buffer, depending on whether a classic or a CAN-FD frame was read
+fn decode_frame(buf: CanFdFrame, size: isize) -> io::Result<Frame> {
+    if size < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Frame::from_buf(buf, size as usize).ok_or_else(|| {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            format!("Unexpected CAN frame size: {}", size),
+        )
+    })
+}
+
+fn read_from_fd(fd: RawFd) -> io::Result<(Frame, ())> {
+    let mut buf = CanFdFrame::zeroed();
+    let size = unsafe {
+        libc::read(
+            fd,
+            &mut buf as *mut CanFdFrame as *mut c_void,
+            size_of::<CanFdFrame>(),
+        )
+    };
+    Ok((decode_frame(buf, size)?, ()))
+}
+
+fn read_raw_from_fd(fd: RawFd, frame: &mut RawCanFrame) -> io::Result<()> {
+    let size = unsafe {
+        libc::read(
+            fd,
+            &mut frame.frame as *mut CanFdFrame as *mut c_void,
+            size_of::<CanFdFrame>(),
+        )
+    };
+    frame.fd = match size {
+        x if x < 0 => return Err(io::Error::last_os_error()),
+        x if x as usize == CAN_MTU => false,
+        x if x as usize == CANFD_MTU => true,
+        x => {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Unexpected CAN frame size: {}", x),
+            ))
+        }
+    };
+    Ok(())
+}
+
+fn recvmsg_from_fd(fd: RawFd) -> io::Result<(Frame, RecvMeta)> {
+    let mut buf = CanFdFrame::zeroed();
+    let mut addr = MaybeUninit::<CanSocketAddr>::zeroed();
+    // large enough for a `struct timeval` and the drop counter, u64 for alignment
+    let mut control = [0_u64; 8];
+    let mut iov = libc::iovec {
+        iov_base: &mut buf as *mut CanFdFrame as *mut c_void,
+        iov_len: size_of::<CanFdFrame>(),
+    };
+    let mut hdr: libc::msghdr = unsafe { std::mem::zeroed() };
+    hdr.msg_name = addr.as_mut_ptr() as *mut c_void;
+    hdr.msg_namelen = size_of::<CanSocketAddr>() as u32;
+    hdr.msg_iov = &mut iov;
+    hdr.msg_iovlen = 1;
+    hdr.msg_control = control.as_mut_ptr() as *mut c_void;
+    hdr.msg_controllen = size_of_val(&control);
+
+    let size = unsafe { libc::recvmsg(fd, &mut hdr, 0) };
+    let frame = decode_frame(buf, size)?;
+    let addr = unsafe { addr.assume_init() };
+
+    let mut meta = RecvMeta {
+        iface: None,
+        timestamp: None,
+        dropped: 0,
+        confirmed: hdr.msg_flags & libc::MSG_CONFIRM != 0,
     };
-    if size != size_of::<CanFrame>() {
+    if hdr.msg_namelen as usize >= size_of::<c_short>() + size_of::<c_int>() {
+        meta.iface = Some(addr.if_index as u32);
+    }
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&hdr);
+        while !cmsg.is_null() {
+            let data = libc::CMSG_DATA(cmsg);
+            match ((*cmsg).cmsg_level, (*cmsg).cmsg_type) {
+                (libc::SOL_SOCKET, libc::SO_TIMESTAMP) => {
+                    let tv = ptr::read_unaligned(data as *const libc::timeval);
+                    meta.timestamp = Some(Timestamp {
+                        micros: tv.tv_sec as u64 * 1_000_000 + tv.tv_usec as u64,
+                    });
+                }
+                (libc::SOL_SOCKET, libc::SO_RXQ_OVFL) => {
+                    meta.dropped = ptr::read_unaligned(data as *const u32) as u64;
+                }
+                _ => {}
+            }
+            cmsg = libc::CMSG_NXTHDR(&hdr, cmsg);
+        }
+    }
+    Ok((frame, meta))
+}
+
+fn name_to_index(ifname: &CString) -> io::Result<u32> {
+    let ifindex = unsafe { libc::if_nametoindex(ifname.as_ptr()) };
+    if ifindex == 0 {
         return Err(io::Error::last_os_error());
     }
-    Ok(frame.into())
+    Ok(ifindex)
+}
+
+fn interface_mtu(fd: RawFd, ifname: &CString) -> io::Result<usize> {
+    let mut req: libc::ifreq = unsafe { std::mem::zeroed() };
+    let name = ifname.as_bytes_with_nul();
+    if name.len() > req.ifr_name.len() {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            "Interface name is too long",
+        ));
+    }
+    for (dst, src) in req.ifr_name.iter_mut().zip(name) {
+        *dst = *src as libc::c_char;
+    }
+    let ok = unsafe { libc::ioctl(fd, libc::SIOCGIFMTU as _, &mut req) };
+    if ok != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { req.ifr_ifru.ifru_mtu } as usize)
+}
+
+fn set_socket_option(fd: RawFd, level: c_int, name: c_int, value: &[u8]) -> io::Result<()> {
+    let ok = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            value.as_ptr() as *const c_void,
+            value.len() as libc::socklen_t,
+        )
+    };
+    if ok != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
 }
 
 impl Source for CanSocket {
@@ -180,6 +797,10 @@ impl crate::Sender for CanSocket {
     async fn send(&mut self, msg: Message) -> Result<()> {
         Ok(self.send(msg).await?)
     }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        Ok(self.close()?)
+    }
 }
 
 #[async_trait]
@@ -187,6 +808,10 @@ impl crate::Receiver for CanSocket {
     async fn recv(&mut self) -> Result<Message> {
         Ok(self.recv().await?)
     }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        Ok(self.close()?)
+    }
 }
 
 /// Return the index of the given interface
@@ -197,10 +822,11 @@ pub async fn get_interface_index_by_name(interface: &str) -> crate::Result<u32>
             return Ok(device.index);
         }
     }
-    Err(crate::Error::Io(io::Error::new(
+    Err(io::Error::new(
         ErrorKind::NotFound,
         format!("Interface `{}` not found", interface),
-    )))
+    )
+    .into())
 }
 
 /// Enable the given CAN interface.
@@ -245,6 +871,31 @@ pub async fn set_interface_down(interface: &str) -> crate::Result<()> {
         .map_err(|x| crate::Error::Other(format!("{}", x)))
 }
 
+/// Set the length of the transmit queue of the given CAN interface.
+///
+/// This is like calling
+/// ```sh
+/// ip link set can0 txqueuelen 1000
+/// ```
+///
+/// The queue length defaults to 10 frames for most CAN drivers. Once the queue is full, the kernel
+/// does not block the socket but [`CanSocket::send`] fails with `ENOBUFS`. Raising the queue length
+/// allows sending larger bursts of frames without running into this error. Note that this is
+/// independent of the socket send buffer.
+///
+/// Note, that this requires the capability `CAP_NET_ADMIN`
+pub async fn set_txqueuelen(interface: &str, len: u32) -> crate::Result<()> {
+    let index = get_interface_index_by_name(interface).await?;
+    let (con, handle, _) = rtnetlink::new_connection()?;
+    tokio::spawn(con);
+    let mut request = handle.link().set(index);
+    request.message_mut().nlas.push(Nla::TxQueueLen(len));
+    request
+        .execute()
+        .await
+        .map_err(|x| crate::Error::Other(format!("{}", x)))
+}
+
 /// List all SocketCAN interfaces
 ///
 /// This is similar to using `ip link` but already filters for CAN interfaces
@@ -291,10 +942,85 @@ pub async fn list_devices() -> crate::Result<Vec<DeviceInfo>> {
     Ok(can_interfaces)
 }
 
+/// Bind a [`CanSocket`] to each CAN interface with a name matching the given pattern.
+///
+/// The pattern may contain `*` to match any sequence of characters and `?` to match a single
+/// character, e.g. `can*`. Interfaces which fail to bind are logged and skipped.
+pub async fn open_all_matching(pattern: &str) -> crate::Result<Vec<(String, CanSocket)>> {
+    let mut ret = Vec::new();
+    for device in list_devices().await? {
+        if !matches_pattern(pattern.as_bytes(), device.interface_name.as_bytes()) {
+            continue;
+        }
+        match CanSocket::bind(&device.interface_name) {
+            Ok(socket) => ret.push((device.interface_name, socket)),
+            Err(err) => log::warn!(
+                "Could not bind to interface `{}`: {}",
+                device.interface_name,
+                err
+            ),
+        }
+    }
+    Ok(ret)
+}
+
+fn matches_pattern(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            matches_pattern(&pattern[1..], name)
+                || (!name.is_empty() && matches_pattern(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => matches_pattern(&pattern[1..], &name[1..]),
+        (Some(x), Some(y)) if x == y => matches_pattern(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn interface_pattern() {
+        assert!(matches_pattern(b"can*", b"can0"));
+        assert!(matches_pattern(b"can*", b"can"));
+        assert!(matches_pattern(b"*can?", b"vcan1"));
+        assert!(matches_pattern(b"vcan0", b"vcan0"));
+        assert!(!matches_pattern(b"can*", b"vcan0"));
+        assert!(!matches_pattern(b"can?", b"can10"));
+    }
+
+    #[test]
+    fn fd_frame_round_trip() {
+        let msg = Message::new_fd(0x1234, true, true, &[0xA5; 64]).unwrap();
+        let frame = Frame::from(msg.clone());
+        assert_eq!(frame.mtu(), CANFD_MTU);
+        assert_eq!(Message::try_from(&frame).unwrap(), msg);
+
+        let msg = Message::new_data(0x123, false, &[1, 2, 3]).unwrap();
+        let frame = Frame::from(msg.clone());
+        assert_eq!(frame.mtu(), sys::CAN_MTU);
+        assert_eq!(Message::try_from(&frame).unwrap(), msg);
+    }
+
+    #[test]
+    fn decode_classic_frame_from_fd_buffer() {
+        let msg = Message::new_data(0x123, false, &[1, 2, 3]).unwrap();
+        let frame = Frame::from(msg.clone());
+        let mut buf = CanFdFrame::zeroed();
+        unsafe {
+            ptr::copy_nonoverlapping(
+                frame.as_ptr(),
+                &mut buf as *mut CanFdFrame as *mut u8,
+                frame.mtu(),
+            );
+        }
+        let frame = decode_frame(buf, sys::CAN_MTU as isize).unwrap();
+        assert_eq!(Message::try_from(&frame).unwrap(), msg);
+        assert!(decode_frame(CanFdFrame::zeroed(), 20).is_err());
+    }
+
     #[ignore]
     #[tokio::test]
     async fn socketcan_devices_up_down() {
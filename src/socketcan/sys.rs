@@ -1,26 +1,58 @@
 //! This module implements the low-level SocketCAN bindings, in this case
 //! just the C-ABI structures that are "serialized" onto the socket
 
+use std::convert::TryFrom;
 use std::os::raw::{c_int, c_short};
 
 use crate::Message::Remote;
-use crate::{CanFrameError, Message, CAN_EXT_ID_MASK, CAN_STD_ID_MASK};
+use crate::{BusError, CanFrameError, Message, CANFD_MAX_DLC, CAN_EXT_ID_MASK, CAN_STD_ID_MASK};
 
 const CAN_EFF_FLAG: u32 = 0x80000000;
 const CAN_RTR_FLAG: u32 = 0x40000000;
 const CAN_ERR_FLAG: u32 = 0x20000000;
 
+pub const CAN_INV_FILTER: u32 = 0x20000000;
+
+// error classes, encoded in the ID of error frames
+pub const CAN_ERR_CRTL: u32 = 0x00000004;
+pub const CAN_ERR_BUSOFF: u32 = 0x00000040;
+
+// controller problems, encoded in `data[1]` of error frames
+const CAN_ERR_CRTL_RX_WARNING: u8 = 0x04;
+const CAN_ERR_CRTL_TX_WARNING: u8 = 0x08;
+const CAN_ERR_CRTL_RX_PASSIVE: u8 = 0x10;
+const CAN_ERR_CRTL_TX_PASSIVE: u8 = 0x20;
+
 const CAN_SFF_ID_BITS: u32 = 11;
 const CAN_EFF_ID_BITS: u32 = 29;
 
 const CAN_MAX_DLC: usize = 8;
 const CAN_MAX_DLEN: usize = 8;
+const CANFD_MAX_DLEN: usize = CANFD_MAX_DLC;
+
+/// Size of a `struct can_frame`
+pub const CAN_MTU: usize = std::mem::size_of::<CanFrame>();
+/// Size of a `struct canfd_frame`
+pub const CANFD_MTU: usize = std::mem::size_of::<CanFdFrame>();
+
+const CANFD_BRS: u8 = 0x01;
+const CANFD_ESI: u8 = 0x02;
 
 pub const CAN_RAW: usize = 1;
 
+pub const SOL_CAN_RAW: c_int = 101;
+
+pub const CAN_RAW_FILTER: c_int = 1;
+pub const CAN_RAW_ERR_FILTER: c_int = 2;
+pub const CAN_RAW_LOOPBACK: c_int = 3;
+pub const CAN_RAW_RECV_OWN_MSGS: c_int = 4;
+pub const CAN_RAW_FD_FRAMES: c_int = 5;
+pub const CAN_RAW_JOIN_FILTERS: c_int = 6;
+
 pub const AF_CAN: c_int = 29;
 
 #[repr(C)]
+#[derive(Debug, Clone)]
 pub(crate) struct CanFrame {
     id: u32,
     dlc: u8,
@@ -30,6 +62,266 @@ pub(crate) struct CanFrame {
     data: [u8; CAN_MAX_DLEN],
 }
 
+/// Corresponds to `struct canfd_frame`. Note that a `struct can_frame` shares its layout with
+/// the first [`CAN_MTU`] bytes of this struct.
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub(crate) struct CanFdFrame {
+    id: u32,
+    len: u8,
+    flags: u8,
+    res0: u8,
+    res1: u8,
+    data: [u8; CANFD_MAX_DLEN],
+}
+
+/// A frame as it is read from or written to the socket, either a classic or a CAN-FD frame
+#[derive(Debug)]
+pub(crate) enum Frame {
+    Classic(CanFrame),
+    Fd(CanFdFrame),
+}
+
+impl Frame {
+    pub(crate) fn is_error_frame(&self) -> bool {
+        match self {
+            Frame::Classic(frame) => frame.id & CAN_ERR_FLAG != 0,
+            Frame::Fd(frame) => frame.id & CAN_ERR_FLAG != 0,
+        }
+    }
+
+    /// Translate an error frame into a [`BusError`]. Returns `None` if this is not an error frame or
+    /// if the error class has no corresponding [`BusError`].
+    pub(crate) fn bus_error(&self) -> Option<BusError> {
+        let frame = match self {
+            Frame::Classic(frame) if frame.id & CAN_ERR_FLAG != 0 => frame,
+            _ => return None,
+        };
+        if frame.id & CAN_ERR_BUSOFF != 0 {
+            return Some(BusError::Off);
+        }
+        if frame.id & CAN_ERR_CRTL != 0 {
+            let ctrl = frame.data[1];
+            if ctrl & (CAN_ERR_CRTL_RX_PASSIVE | CAN_ERR_CRTL_TX_PASSIVE) != 0 {
+                return Some(BusError::Passive);
+            }
+            if ctrl & (CAN_ERR_CRTL_RX_WARNING | CAN_ERR_CRTL_TX_WARNING) != 0 {
+                return Some(BusError::HeavyWarning);
+            }
+        }
+        None
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const u8 {
+        match self {
+            Frame::Classic(frame) => frame as *const CanFrame as *const u8,
+            Frame::Fd(frame) => frame as *const CanFdFrame as *const u8,
+        }
+    }
+
+    pub(crate) fn mtu(&self) -> usize {
+        match self {
+            Frame::Classic(_) => CAN_MTU,
+            Frame::Fd(_) => CANFD_MTU,
+        }
+    }
+
+    /// Interpret the first `size` bytes of `buf` as frame, i.e. `size` must either be [`CAN_MTU`]
+    /// or [`CANFD_MTU`].
+    pub(crate) fn from_buf(buf: CanFdFrame, size: usize) -> Option<Frame> {
+        match size {
+            CAN_MTU => Some(Frame::Classic(buf.to_classic())),
+            CANFD_MTU => Some(Frame::Fd(buf)),
+            _ => None,
+        }
+    }
+}
+
+/// A frame as it is read from or written to the socket, either a `struct can_frame` or a
+/// `struct canfd_frame`, see [`crate::socketcan::CanSocket::recv_into()`] and
+/// [`crate::socketcan::CanSocket::send_raw()`].
+///
+/// Other than [`Message`], this does not allocate and gives bit-level control over the frame, e.g. to inspect
+/// error frames or reserved bits.
+#[derive(Debug, Clone)]
+pub struct RawCanFrame {
+    pub(crate) frame: CanFdFrame,
+    pub(crate) fd: bool,
+}
+
+impl Default for RawCanFrame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RawCanFrame {
+    pub fn new() -> Self {
+        Self {
+            frame: CanFdFrame::zeroed(),
+            fd: false,
+        }
+    }
+
+    /// Create a classic frame. `raw_id` includes the `CAN_EFF_FLAG`, `CAN_RTR_FLAG` and `CAN_ERR_FLAG` bits
+    /// and is not validated. `dlc` is not checked against the length of `data`, which must not exceed 8 bytes.
+    pub fn new_classic(raw_id: u32, dlc: u8, data: &[u8]) -> Result<Self, CanFrameError> {
+        if data.len() > CAN_MAX_DLEN {
+            return Err(CanFrameError::DataTooLong);
+        }
+        let mut ret = Self::new();
+        ret.frame.id = raw_id;
+        ret.frame.len = dlc;
+        ret.frame.data[..data.len()].copy_from_slice(data);
+        Ok(ret)
+    }
+
+    /// Create a CAN-FD frame. `raw_id` includes the `CAN_EFF_FLAG` and `CAN_ERR_FLAG` bits and is not validated.
+    /// `flags` are the `CANFD_*` flags, e.g. `0x01` for BRS and `0x02` for ESI.
+    pub fn new_fd(raw_id: u32, flags: u8, data: &[u8]) -> Result<Self, CanFrameError> {
+        CanFrameError::validate_fd_len(data.len())?;
+        let mut ret = Self::new();
+        ret.frame.id = raw_id;
+        ret.frame.len = data.len() as u8;
+        ret.frame.flags = flags;
+        ret.frame.data[..data.len()].copy_from_slice(data);
+        ret.fd = true;
+        Ok(ret)
+    }
+
+    /// Returns the CAN ID including the `CAN_EFF_FLAG`, `CAN_RTR_FLAG` and `CAN_ERR_FLAG` bits.
+    pub fn raw_id(&self) -> u32 {
+        self.frame.id
+    }
+
+    /// Returns the CAN ID without flags.
+    pub fn id(&self) -> u32 {
+        if self.ext_id() {
+            self.frame.id & CAN_EXT_ID_MASK
+        } else {
+            self.frame.id & CAN_STD_ID_MASK
+        }
+    }
+
+    pub fn ext_id(&self) -> bool {
+        self.frame.id & CAN_EFF_FLAG != 0
+    }
+
+    pub fn is_remote(&self) -> bool {
+        self.frame.id & CAN_RTR_FLAG != 0
+    }
+
+    pub fn is_error_frame(&self) -> bool {
+        self.frame.id & CAN_ERR_FLAG != 0
+    }
+
+    /// Returns true if this is a CAN-FD frame.
+    pub fn is_fd(&self) -> bool {
+        self.fd
+    }
+
+    /// Returns the DLC of a classic frame or the length of the data field of a CAN-FD frame.
+    pub fn dlc(&self) -> u8 {
+        self.frame.len
+    }
+
+    /// Returns the `CANFD_*` flags of a CAN-FD frame. For classic frames, this is the padding byte.
+    pub fn flags(&self) -> u8 {
+        self.frame.flags
+    }
+
+    /// Returns the two reserved bytes following the flags. For classic frames, the second byte is the
+    /// `len8_dlc` field.
+    pub fn reserved(&self) -> [u8; 2] {
+        [self.frame.res0, self.frame.res1]
+    }
+
+    /// Returns the data field, which is empty for remote frames.
+    pub fn data(&self) -> &[u8] {
+        if self.is_remote() {
+            return &[];
+        }
+        let max_len = if self.fd {
+            CANFD_MAX_DLEN
+        } else {
+            CAN_MAX_DLEN
+        };
+        &self.frame.data[..(self.frame.len as usize).min(max_len)]
+    }
+}
+
+impl TryFrom<&RawCanFrame> for Message {
+    type Error = CanFrameError;
+
+    fn try_from(val: &RawCanFrame) -> Result<Self, Self::Error> {
+        if val.fd {
+            Message::try_from(&val.frame)
+        } else {
+            Message::try_from(&val.frame.to_classic())
+        }
+    }
+}
+
+impl TryFrom<RawCanFrame> for Message {
+    type Error = CanFrameError;
+
+    fn try_from(val: RawCanFrame) -> Result<Self, Self::Error> {
+        Message::try_from(&val)
+    }
+}
+
+impl From<Message> for RawCanFrame {
+    fn from(msg: Message) -> Self {
+        match Frame::from(msg) {
+            Frame::Classic(frame) => {
+                let mut ret = RawCanFrame::new();
+                ret.frame.id = frame.id;
+                ret.frame.len = frame.dlc;
+                ret.frame.data[..CAN_MAX_DLEN].copy_from_slice(&frame.data);
+                ret
+            }
+            Frame::Fd(frame) => RawCanFrame { frame, fd: true },
+        }
+    }
+}
+
+impl From<&RawCanFrame> for Frame {
+    fn from(val: &RawCanFrame) -> Self {
+        if val.fd {
+            Frame::Fd(val.frame.clone())
+        } else {
+            Frame::Classic(val.frame.to_classic())
+        }
+    }
+}
+
+impl CanFdFrame {
+    pub(crate) fn zeroed() -> Self {
+        Self {
+            id: 0,
+            len: 0,
+            flags: 0,
+            res0: 0,
+            res1: 0,
+            data: [0_u8; CANFD_MAX_DLEN],
+        }
+    }
+
+    /// Interpret the first [`CAN_MTU`] bytes as `struct can_frame`.
+    fn to_classic(&self) -> CanFrame {
+        let mut data = [0_u8; CAN_MAX_DLEN];
+        data.copy_from_slice(&self.data[0..CAN_MAX_DLEN]);
+        CanFrame {
+            id: self.id,
+            dlc: self.len,
+            pad: self.flags,
+            res0: self.res0,
+            res1: self.res1,
+            data,
+        }
+    }
+}
+
 impl CanFrame {
     pub(crate) fn new_data(id: u32, ext_id: bool, data: &[u8]) -> Result<Self, CanFrameError> {
         CanFrameError::validate_id(id, ext_id)?;
@@ -73,7 +365,7 @@ impl CanFrame {
     }
 }
 
-impl From<Message> for CanFrame {
+impl From<Message> for Frame {
     fn from(msg: Message) -> Self {
         let mut id = msg.id();
         if msg.ext_id() {
@@ -83,25 +375,44 @@ impl From<Message> for CanFrame {
             Message::Data(msg) => {
                 let mut can_data = [0_u8; CAN_MAX_DLEN];
                 can_data[0..msg.data().len()].copy_from_slice(msg.data());
-                CanFrame {
+                Frame::Classic(CanFrame {
                     id,
                     dlc: msg.data().len() as u8,
                     pad: 0,
                     res0: 0,
                     res1: 0,
                     data: can_data,
-                }
+                })
             }
             Remote(msg) => {
                 id |= CAN_RTR_FLAG;
-                CanFrame {
+                Frame::Classic(CanFrame {
                     id,
                     dlc: msg.dlc(),
                     pad: 0,
                     res0: 0,
                     res1: 0,
                     data: [0_u8; CAN_MAX_DLEN],
+                })
+            }
+            Message::Fd(msg) => {
+                let mut can_data = [0_u8; CANFD_MAX_DLEN];
+                can_data[0..msg.data().len()].copy_from_slice(msg.data());
+                let mut flags = 0;
+                if msg.brs() {
+                    flags |= CANFD_BRS;
+                }
+                if msg.esi() {
+                    flags |= CANFD_ESI;
                 }
+                Frame::Fd(CanFdFrame {
+                    id,
+                    len: msg.data().len() as u8,
+                    flags,
+                    res0: 0,
+                    res1: 0,
+                    data: can_data,
+                })
             }
         }
     }
@@ -116,8 +427,10 @@ pub(crate) struct CanSocketAddr {
     pub(crate) tx_id: u32,
 }
 
-impl From<CanFrame> for Message {
-    fn from(val: CanFrame) -> Self {
+impl TryFrom<&CanFrame> for Message {
+    type Error = CanFrameError;
+
+    fn try_from(val: &CanFrame) -> Result<Self, Self::Error> {
         let (id, ext_id) = if val.id & CAN_EFF_FLAG > 0 {
             (val.id & CAN_EXT_ID_MASK, true)
         } else {
@@ -125,9 +438,133 @@ impl From<CanFrame> for Message {
         };
         let rtr = val.id & CAN_RTR_FLAG > 0;
         if rtr {
-            Message::new_remote(id, ext_id, val.dlc).unwrap()
+            Message::new_remote(id, ext_id, val.dlc)
         } else {
-            Message::new_data(id, ext_id, &val.data[0..(val.dlc as usize)]).unwrap()
+            let data = val
+                .data
+                .get(0..(val.dlc as usize))
+                .ok_or(CanFrameError::DataTooLong)?;
+            Message::new_data(id, ext_id, data)
         }
     }
 }
+
+impl TryFrom<&CanFdFrame> for Message {
+    type Error = CanFrameError;
+
+    fn try_from(val: &CanFdFrame) -> Result<Self, Self::Error> {
+        let (id, ext_id) = if val.id & CAN_EFF_FLAG > 0 {
+            (val.id & CAN_EXT_ID_MASK, true)
+        } else {
+            (val.id & CAN_STD_ID_MASK, false)
+        };
+        let data = val
+            .data
+            .get(0..(val.len as usize))
+            .ok_or(CanFrameError::DataTooLong)?;
+        let frame = crate::CanFdFrame::new(id, ext_id, val.flags & CANFD_BRS != 0, data.to_vec())?
+            .with_esi(val.flags & CANFD_ESI != 0);
+        Ok(Message::Fd(frame))
+    }
+}
+
+impl TryFrom<&Frame> for Message {
+    type Error = CanFrameError;
+
+    fn try_from(val: &Frame) -> Result<Self, Self::Error> {
+        match val {
+            Frame::Classic(frame) => Message::try_from(frame),
+            Frame::Fd(frame) => Message::try_from(frame),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn error_frame(class: u32, ctrl: u8) -> Frame {
+        let mut data = [0_u8; CAN_MAX_DLEN];
+        data[1] = ctrl;
+        Frame::Classic(CanFrame {
+            id: CAN_ERR_FLAG | class,
+            dlc: CAN_MAX_DLEN as u8,
+            pad: 0,
+            res0: 0,
+            res1: 0,
+            data,
+        })
+    }
+
+    #[test]
+    fn parse_error_frames() {
+        let frame = error_frame(CAN_ERR_BUSOFF, 0);
+        assert!(frame.is_error_frame());
+        assert!(matches!(frame.bus_error(), Some(BusError::Off)));
+        let frame = error_frame(CAN_ERR_CRTL, CAN_ERR_CRTL_TX_PASSIVE);
+        assert!(matches!(frame.bus_error(), Some(BusError::Passive)));
+        let frame = error_frame(CAN_ERR_CRTL, CAN_ERR_CRTL_RX_WARNING);
+        assert!(matches!(frame.bus_error(), Some(BusError::HeavyWarning)));
+        // protocol violation, not mapped
+        let frame = error_frame(0x08, 0);
+        assert!(frame.is_error_frame());
+        assert!(frame.bus_error().is_none());
+
+        let frame = Frame::from(Message::new_data(0x123, false, &[]).unwrap());
+        assert!(!frame.is_error_frame());
+    }
+
+    #[test]
+    fn raw_frame_to_message() {
+        let mut raw = RawCanFrame::new();
+        raw.frame.id = 0x12345 | CAN_EFF_FLAG;
+        raw.frame.len = 2;
+        raw.frame.data[..3].copy_from_slice(&[0xAB, 0xCD, 0xEF]);
+        assert_eq!(raw.id(), 0x12345);
+        assert!(raw.ext_id());
+        assert_eq!(raw.data(), [0xAB, 0xCD]);
+        let msg = Message::new_data(0x12345, true, &[0xAB, 0xCD]).unwrap();
+        assert_eq!(Message::try_from(&raw).unwrap(), msg);
+
+        raw.fd = true;
+        raw.frame.flags = CANFD_BRS;
+        raw.frame.len = 12;
+        let msg = Message::try_from(&raw).unwrap();
+        assert!(matches!(msg, Message::Fd(ref x) if x.brs() && x.data().len() == 12));
+
+        raw.frame.id |= CAN_ERR_FLAG;
+        assert!(raw.is_error_frame());
+    }
+
+    #[test]
+    fn raw_frame_from_message() {
+        let msg = Message::new_remote(0x123, false, 4).unwrap();
+        let raw = RawCanFrame::from(msg.clone());
+        assert_eq!(raw.raw_id(), 0x123 | CAN_RTR_FLAG);
+        assert_eq!(raw.dlc(), 4);
+        assert!(raw.data().is_empty());
+        assert_eq!(Message::try_from(raw).unwrap(), msg);
+
+        let msg = Message::new_fd(0x1234, true, true, &[1; 16]).unwrap();
+        let raw = RawCanFrame::from(msg.clone());
+        assert!(raw.is_fd());
+        assert_eq!(raw.flags(), CANFD_BRS);
+        assert_eq!(Message::try_from(&raw).unwrap(), msg);
+
+        let frame = crate::CanFdFrame::new(0x1234, true, true, vec![2; 64])
+            .unwrap()
+            .with_esi(true);
+        let msg = Message::Fd(frame);
+        let raw = RawCanFrame::from(msg.clone());
+        assert_eq!(raw.flags(), CANFD_BRS | CANFD_ESI);
+        let received = Message::try_from(&raw).unwrap();
+        assert!(matches!(received, Message::Fd(ref x) if x.brs() && x.esi()));
+        assert_eq!(received, msg);
+        assert_eq!(Message::try_from(&Frame::from(msg.clone())).unwrap(), msg);
+
+        let raw = RawCanFrame::new_classic(CAN_ERR_FLAG | CAN_ERR_BUSOFF, 8, &[0; 8]).unwrap();
+        assert!(matches!(Frame::from(&raw).bus_error(), Some(BusError::Off)));
+        assert!(RawCanFrame::new_classic(0x123, 9, &[0; 9]).is_err());
+        assert!(RawCanFrame::new_fd(0x123, 0, &[0; 9]).is_err());
+    }
+}
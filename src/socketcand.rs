@@ -0,0 +1,331 @@
+//! This module implements a client for [socketcand](https://github.com/linux-can/socketcand), which exposes
+//! the SocketCAN interfaces of a Linux machine over TCP.
+//!
+//! The protocol is line-less and ASCII based: each command or frame is enclosed in angle brackets,
+//! for example `< send 123 2 AB CD >` to transmit a frame. After connecting, the client opens a bus with
+//! `< open can0 >` and switches to raw mode, in which all frames on the bus are forwarded as
+//! `< frame 123 1623449370.453281 ABCD >`. Remote frames and CAN-FD are not supported.
+
+use crate::{decode_hex, trace, CanFrameError, Message, CAN_MAX_DLC};
+use async_trait::async_trait;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::ToSocketAddrs;
+use tokio::net::{
+    tcp::{OwnedReadHalf, OwnedWriteHalf},
+    TcpStream,
+};
+
+/// A sender for a socketcand server. Implements [`crate::Sender`].
+///
+/// Contains the write half of the TCP stream. Sending a [`Message::Remote`] or [`Message::Fd`] fails
+/// with [`crate::Error::NotSupported`].
+pub struct Sender {
+    stream: OwnedWriteHalf,
+}
+
+/// A receiver for a socketcand server. Implements [`crate::Receiver`].
+///
+/// Contains the read half of the TCP stream.
+pub struct Receiver {
+    stream: BufReader<OwnedReadHalf>,
+    /// The record being read, kept across calls such that receiving is cancel-safe
+    buf: Vec<u8>,
+    dropped_malformed: u64,
+}
+
+/// Connect to the socketcand server at `addr` and open the given `bus`, e.g. `can0`.
+pub async fn connect<A: ToSocketAddrs>(addr: A, bus: &str) -> crate::Result<(Sender, Receiver)> {
+    let stream = TcpStream::connect(addr).await?;
+    stream.set_nodelay(true)?;
+    let (read, write) = stream.into_split();
+    let mut sender = Sender { stream: write };
+    let mut receiver = Receiver {
+        stream: BufReader::new(read),
+        buf: Vec::new(),
+        dropped_malformed: 0,
+    };
+    receiver.expect("hi").await?;
+    sender.command(&format!("open {}", bus)).await?;
+    receiver.expect("ok").await?;
+    sender.command("rawmode").await?;
+    receiver.expect("ok").await?;
+    Ok((sender, receiver))
+}
+
+fn encode(msg: &Message) -> crate::Result<String> {
+    let frame = match msg {
+        Message::Data(frame) => frame,
+        Message::Remote(_) => {
+            return Err(crate::Error::NotSupported(
+                "socketcand does not support remote frames".to_string(),
+            ))
+        }
+        Message::Fd(_) => {
+            return Err(crate::Error::NotSupported(
+                "socketcand does not support CAN-FD".to_string(),
+            ))
+        }
+    };
+    let mut ret = if msg.ext_id() {
+        format!("send {:08X} {}", msg.id(), msg.dlc())
+    } else {
+        format!("send {:03X} {}", msg.id(), msg.dlc())
+    };
+    for x in frame.data() {
+        ret.push_str(&format!(" {:02X}", x));
+    }
+    Ok(ret)
+}
+
+/// Decode the content of a `< frame ... >` record, i.e. the words following `frame`.
+///
+/// Extended IDs are recognized by being formatted with 8 digits. The data may either be a single
+/// hex string or separated into bytes.
+fn decode(words: &[&str]) -> Result<Message, CanFrameError> {
+    let (id, data) = match words {
+        [id, _timestamp, data @ ..] => (*id, data.concat()),
+        _ => return Err(CanFrameError::InvalidFormat),
+    };
+    let ext_id = id.len() > 3;
+    let id = u32::from_str_radix(id, 16).map_err(|_| CanFrameError::InvalidFormat)?;
+    let data = decode_hex(data.as_bytes())?;
+    if data.len() > CAN_MAX_DLC {
+        return Err(CanFrameError::DataTooLong);
+    }
+    Message::new_data(id, ext_id, &data)
+}
+
+impl Sender {
+    async fn command(&mut self, cmd: &str) -> crate::Result<()> {
+        self.stream
+            .write_all(format!("< {} >", cmd).as_bytes())
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl crate::Sender for Sender {
+    async fn send(&mut self, msg: Message) -> crate::Result<()> {
+        let cmd = encode(&msg)?;
+        trace::send("socketcand", &msg);
+        self.command(&cmd).await
+    }
+}
+
+impl Receiver {
+    /// Returns the number of received frames which were dropped because they could not be decoded
+    /// into a [`crate::Message`].
+    pub fn dropped_malformed_count(&self) -> u64 {
+        self.dropped_malformed
+    }
+
+    /// Read the next `< ... >` record and return its content with surrounding whitespace removed.
+    ///
+    /// This is cancel-safe, a partially read record is kept in `self.buf` and completed by the next call.
+    async fn read_record(&mut self) -> crate::Result<String> {
+        loop {
+            if self.stream.read_until(b'>', &mut self.buf).await? == 0 {
+                return Err(crate::Error::Disconnected);
+            }
+            let record = String::from_utf8_lossy(&self.buf).into_owned();
+            self.buf.clear();
+            if let Some((_, content)) = record.split_once('<') {
+                return Ok(content.trim_end_matches('>').trim().to_string());
+            }
+        }
+    }
+
+    async fn expect(&mut self, expected: &str) -> crate::Result<()> {
+        let record = self.read_record().await?;
+        if record != expected {
+            return Err(crate::Error::Other(format!(
+                "Unexpected response from socketcand: `< {} >`",
+                record
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl crate::Receiver for Receiver {
+    async fn recv(&mut self) -> crate::Result<Message> {
+        loop {
+            let record = self.read_record().await?;
+            let words: Vec<_> = record.split_whitespace().collect();
+            match words.split_first() {
+                Some((&"frame", frame)) => match decode(frame) {
+                    Ok(msg) => {
+                        trace::recv("socketcand", &msg);
+                        return Ok(msg);
+                    }
+                    Err(err) => {
+                        self.dropped_malformed += 1;
+                        log::warn!(
+                            "Dropping malformed socketcand frame ({:?}): `< {} >`",
+                            err,
+                            record
+                        );
+                    }
+                },
+                Some((&"error", _)) => {
+                    return Err(crate::Error::Other(format!(
+                        "socketcand reported an error: `< {} >`",
+                        record
+                    )))
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+        task,
+    };
+
+    use std::time::Duration;
+
+    use crate::ext::ReceiverExt;
+    use crate::{Message, Receiver, Sender};
+
+    async fn read_record<R: tokio::io::AsyncBufRead + Unpin>(read: &mut R) -> String {
+        let mut buf = Vec::new();
+        read.read_until(b'>', &mut buf).await.unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[tokio::test]
+    async fn handshake_and_frames() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = task::spawn(async move {
+            let (connection, _) = listener.accept().await.unwrap();
+            let (read, mut write) = connection.into_split();
+            let mut read = BufReader::new(read);
+            write.write_all(b"< hi >").await.unwrap();
+            assert_eq!(read_record(&mut read).await, "< open vcan0 >");
+            write.write_all(b"< ok >").await.unwrap();
+            assert_eq!(read_record(&mut read).await, "< rawmode >");
+            write.write_all(b"< ok >").await.unwrap();
+            write
+                .write_all(b"< frame 123 1623449370.453281 ABCD >< frame 1ABCDEF0 0.000001 >")
+                .await
+                .unwrap();
+            // malformed, followed by data separated into bytes
+            write
+                .write_all(b"< frame 12 0.1 ABC >\n< frame 7FF 0.2 01 02 >")
+                .await
+                .unwrap();
+            read_record(&mut read).await
+        });
+        let (mut tx, mut rx) = super::connect(addr, "vcan0").await.unwrap();
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            Message::new_data(0x123, false, &[0xAB, 0xCD]).unwrap()
+        );
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            Message::new_data(0x1ABCDEF0, true, &[]).unwrap()
+        );
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            Message::new_data(0x7FF, false, &[1, 2]).unwrap()
+        );
+        assert_eq!(rx.dropped_malformed_count(), 1);
+
+        let msg = Message::new_data(0x1234, true, &[0xDE, 0xAD]).unwrap();
+        tx.send(msg).await.unwrap();
+        assert_eq!(server.await.unwrap(), "< send 00001234 2 DE AD >");
+
+        let msg = Message::new_remote(0x123, false, 2).unwrap();
+        assert!(tx.send(msg).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn cancel_partial_record() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (resume_tx, resume_rx) = tokio::sync::oneshot::channel();
+        task::spawn(async move {
+            let (connection, _) = listener.accept().await.unwrap();
+            let (read, mut write) = connection.into_split();
+            let mut read = BufReader::new(read);
+            write.write_all(b"< hi >").await.unwrap();
+            read_record(&mut read).await;
+            write.write_all(b"< ok >").await.unwrap();
+            read_record(&mut read).await;
+            write.write_all(b"< ok >< frame 123 0.1 AB").await.unwrap();
+            resume_rx.await.unwrap();
+            write.write_all(b"CD >").await.unwrap();
+            read_record(&mut read).await;
+        });
+        let (_tx, mut rx) = super::connect(addr, "vcan0").await.unwrap();
+        let timeout = Duration::from_millis(50);
+        assert_eq!(rx.recv_timeout(timeout).await.unwrap(), None);
+        resume_tx.send(()).unwrap();
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            Message::new_data(0x123, false, &[0xAB, 0xCD]).unwrap()
+        );
+        assert_eq!(rx.dropped_malformed_count(), 0);
+    }
+
+    #[test]
+    fn decode_non_ascii() {
+        assert!(super::decode(&["123", "0", "a\u{e9}b"]).is_err());
+        assert!(super::decode(&["123", "0", "\u{e9}", "AB"]).is_err());
+        assert!(super::decode(&["123", "0", "\u{fffd}"]).is_err());
+    }
+
+    #[test]
+    fn decode_errors() {
+        use super::decode;
+        use crate::CanFrameError;
+
+        assert!(matches!(
+            decode(&["123"]),
+            Err(CanFrameError::InvalidFormat)
+        ));
+        assert!(matches!(
+            decode(&["12X", "0"]),
+            Err(CanFrameError::InvalidFormat)
+        ));
+        assert!(matches!(
+            decode(&["123", "0", "AX"]),
+            Err(CanFrameError::InvalidFormat)
+        ));
+        assert!(matches!(
+            decode(&["123", "0", "ABC"]),
+            Err(CanFrameError::InvalidFormat)
+        ));
+        assert!(matches!(
+            decode(&["123", "0", "001122334455667788"]),
+            Err(CanFrameError::DataTooLong)
+        ));
+    }
+
+    #[tokio::test]
+    async fn open_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        task::spawn(async move {
+            let (mut connection, _) = listener.accept().await.unwrap();
+            connection
+                .write_all(b"< hi >< error could not open bus >")
+                .await
+                .unwrap();
+            let mut buf = Vec::new();
+            let _ = BufReader::new(connection).read_until(b'>', &mut buf).await;
+        });
+        assert!(matches!(
+            super::connect(addr, "can9").await,
+            Err(crate::Error::Other(_))
+        ));
+    }
+}
@@ -0,0 +1,207 @@
+//! This module implements counting the frames and errors passing through a sender or receiver, e.g. to display
+//! them on a dashboard.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::{Message, Receiver, Result, Sender};
+
+#[derive(Debug, Default)]
+struct Counters {
+    frames: AtomicU64,
+    bytes: AtomicU64,
+    standard: AtomicU64,
+    extended: AtomicU64,
+    remote: AtomicU64,
+    fd: AtomicU64,
+    errors: AtomicU64,
+}
+
+/// The counters of a [`Stats`] at a point in time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatsSnapshot {
+    /// Number of frames
+    pub frames: u64,
+    /// Number of data bytes, remote frames do not carry any
+    pub bytes: u64,
+    /// Number of frames with a standard ID
+    pub standard: u64,
+    /// Number of frames with an extended ID
+    pub extended: u64,
+    /// Number of remote frames
+    pub remote: u64,
+    /// Number of CAN-FD frames
+    pub fd: u64,
+    /// Number of errors returned by the wrapped sender or receiver
+    pub errors: u64,
+}
+
+/// Running counters of frames and errors, updated by [`StatsReceiver`] and [`StatsSender`].
+///
+/// Clones share the same counters, so a clone can be polled from another task while the wrapper is in use.
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    counters: Arc<Counters>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the current values of all counters.
+    pub fn snapshot(&self) -> StatsSnapshot {
+        let c = &self.counters;
+        StatsSnapshot {
+            frames: c.frames.load(Ordering::Relaxed),
+            bytes: c.bytes.load(Ordering::Relaxed),
+            standard: c.standard.load(Ordering::Relaxed),
+            extended: c.extended.load(Ordering::Relaxed),
+            remote: c.remote.load(Ordering::Relaxed),
+            fd: c.fd.load(Ordering::Relaxed),
+            errors: c.errors.load(Ordering::Relaxed),
+        }
+    }
+
+    fn count_error(&self) {
+        self.counters.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn count_msg(&self, msg: &Message) {
+        let c = &self.counters;
+        c.frames.fetch_add(1, Ordering::Relaxed);
+        if msg.ext_id() {
+            c.extended.fetch_add(1, Ordering::Relaxed);
+        } else {
+            c.standard.fetch_add(1, Ordering::Relaxed);
+        }
+        match msg {
+            Message::Data(frame) => {
+                c.bytes
+                    .fetch_add(frame.data().len() as u64, Ordering::Relaxed);
+            }
+            Message::Remote(_) => {
+                c.remote.fetch_add(1, Ordering::Relaxed);
+            }
+            Message::Fd(frame) => {
+                c.bytes
+                    .fetch_add(frame.data().len() as u64, Ordering::Relaxed);
+                c.fd.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Wraps a [`crate::Receiver`] and counts all received messages and errors in a [`Stats`].
+pub struct StatsReceiver<R> {
+    inner: R,
+    stats: Stats,
+}
+
+impl<R> StatsReceiver<R> {
+    pub fn new(inner: R, stats: Stats) -> Self {
+        Self { inner, stats }
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Return the wrapped receiver.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<R: Receiver + Send> Receiver for StatsReceiver<R> {
+    async fn recv(&mut self) -> Result<Message> {
+        let result = self.inner.recv().await;
+        match &result {
+            Ok(msg) => self.stats.count_msg(msg),
+            Err(_) => self.stats.count_error(),
+        }
+        result
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        self.inner.shutdown().await
+    }
+}
+
+/// Wraps a [`crate::Sender`] and counts all successfully sent messages and errors in a [`Stats`].
+pub struct StatsSender<S> {
+    inner: S,
+    stats: Stats,
+}
+
+impl<S> StatsSender<S> {
+    pub fn new(inner: S, stats: Stats) -> Self {
+        Self { inner, stats }
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Return the wrapped sender.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<S: Sender + Send> Sender for StatsSender<S> {
+    async fn send(&mut self, msg: Message) -> Result<()> {
+        let result = self.inner.send(msg.clone()).await;
+        match &result {
+            Ok(()) => self.stats.count_msg(&msg),
+            Err(_) => self.stats.count_error(),
+        }
+        result
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        self.inner.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::loopback;
+
+    #[tokio::test]
+    async fn count_frames() {
+        let (tx, rx) = loopback::connect();
+        let stats = Stats::new();
+        let mut tx = StatsSender::new(tx, stats.clone());
+        let mut rx = StatsReceiver::new(rx, Stats::new());
+        let msgs = [
+            Message::new_data(0x123, false, &[1, 2, 3]).unwrap(),
+            Message::new_remote(0x1234, true, 8).unwrap(),
+            Message::new_fd(0x456, false, true, &[0; 12]).unwrap(),
+        ];
+        for msg in msgs {
+            tx.send(msg.clone()).await.unwrap();
+            assert_eq!(rx.recv().await.unwrap(), msg);
+        }
+        let expected = StatsSnapshot {
+            frames: 3,
+            bytes: 15,
+            standard: 2,
+            extended: 1,
+            remote: 1,
+            fd: 1,
+            errors: 0,
+        };
+        assert_eq!(stats.snapshot(), expected);
+        assert_eq!(rx.stats().snapshot(), expected);
+
+        drop(tx);
+        assert!(rx.recv().await.is_err());
+        assert_eq!(rx.stats().snapshot().errors, 1);
+    }
+}
@@ -0,0 +1,51 @@
+//! This module implements the optional instrumentation of the backends with [`tracing`](https://docs.rs/tracing),
+//! enabled with the `tracing` feature.
+//!
+//! Each message sent or received by a backend emits a `TRACE` event with its ID, DLC and extended flag, each
+//! bus error emits a `WARN` event. Without a `tracing` subscriber, the events are forwarded to `log`.
+//!
+//! Without the `tracing` feature, all functions of this module compile to nothing.
+
+use crate::{BusError, Message};
+
+/// Emit an event for `msg`, which is about to be sent by `backend`.
+#[cfg(feature = "tracing")]
+pub(crate) fn send(backend: &'static str, msg: &Message) {
+    tracing::trace!(
+        backend,
+        id = msg.id(),
+        dlc = msg.dlc(),
+        ext_id = msg.ext_id(),
+        "send"
+    );
+}
+
+/// Emit an event for `msg`, which was received by `backend`.
+#[cfg(feature = "tracing")]
+pub(crate) fn recv(backend: &'static str, msg: &Message) {
+    tracing::trace!(
+        backend,
+        id = msg.id(),
+        dlc = msg.dlc(),
+        ext_id = msg.ext_id(),
+        "recv"
+    );
+}
+
+/// Emit a warning for a bus error reported by `backend`.
+#[cfg(feature = "tracing")]
+pub(crate) fn bus_error(backend: &'static str, err: &BusError) {
+    tracing::warn!(backend, "Bus error: {}", err);
+}
+
+#[cfg(not(feature = "tracing"))]
+#[inline(always)]
+pub(crate) fn send(_backend: &'static str, _msg: &Message) {}
+
+#[cfg(not(feature = "tracing"))]
+#[inline(always)]
+pub(crate) fn recv(_backend: &'static str, _msg: &Message) {}
+
+#[cfg(not(feature = "tracing"))]
+#[inline(always)]
+pub(crate) fn bus_error(_backend: &'static str, _err: &BusError) {}
@@ -0,0 +1,223 @@
+//! This module implements connecting to a backend given as URI, such that the backend can be selected
+//! with a configuration value.
+//!
+//! The following schemes are supported, if the corresponding backend is enabled:
+//!
+//!  * `socketcan://can0`
+//!  * `pcan://usb1?bitrate=500000`
+//!  * `usr-canet://192.168.1.10:20001`
+//!  * `slcan:///dev/ttyACM0?bitrate=500000` or `slcan://COM3?bitrate=500000`
+//!  * `socketcand://192.168.1.10:29536/can0`
+//!  * `loopback://`, which receives all messages sent with the returned sender
+
+use std::time::Duration;
+
+use crate::ext::ReceiverExt;
+use crate::{Error, Message, Receiver, Result, Sender};
+
+/// A sender returned by [`connect()`].
+pub type BoxSender = Box<dyn Sender + Send>;
+
+/// A receiver returned by [`connect()`].
+pub type BoxReceiver = Box<dyn Receiver + Send>;
+
+struct Uri<'a> {
+    scheme: &'a str,
+    target: &'a str,
+    params: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Uri<'a> {
+    fn parse(uri: &'a str) -> Result<Self> {
+        let (scheme, rest) = uri
+            .split_once("://")
+            .ok_or_else(|| Error::Other(format!("Invalid URI `{}`", uri)))?;
+        let (target, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let params = query
+            .split('&')
+            .filter(|x| !x.is_empty())
+            .map(|x| x.split_once('=').unwrap_or((x, "")))
+            .collect();
+        Ok(Self {
+            scheme,
+            target,
+            params,
+        })
+    }
+
+    /// Returns an error if parameters other than `allowed` are given.
+    fn check_params(&self, allowed: &[&str]) -> Result<()> {
+        match self.params.iter().find(|(k, _)| !allowed.contains(k)) {
+            Some((k, _)) => Err(Error::Other(format!(
+                "Unknown parameter `{}` for `{}`",
+                k, self.scheme
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg_attr(not(any(feature = "pcan", feature = "slcan")), allow(dead_code))]
+    fn bitrate(&self) -> Result<u32> {
+        self.params
+            .iter()
+            .find(|(k, _)| *k == "bitrate")
+            .and_then(|(_, v)| v.parse().ok())
+            .ok_or(Error::InvalidBitRate)
+    }
+}
+
+#[allow(dead_code)]
+fn not_enabled(scheme: &str) -> Error {
+    Error::Other(format!("Backend for `{}` is not enabled", scheme))
+}
+
+/// Connect to the backend given by `uri`, refer to the [module documentation](crate::uri) for the supported
+/// schemes.
+///
+/// Returns an error if the scheme is unknown or the corresponding backend is not enabled.
+pub async fn connect(uri: &str) -> Result<(BoxSender, BoxReceiver)> {
+    let uri = Uri::parse(uri)?;
+    match uri.scheme {
+        "socketcan" => {
+            uri.check_params(&[])?;
+            #[cfg(all(target_os = "linux", feature = "socket_can"))]
+            {
+                let socket = crate::socketcan::CanSocket::bind_auto(uri.target)?;
+                let sender = socket.try_clone()?;
+                Ok((Box::new(sender), Box::new(socket)))
+            }
+            #[cfg(not(all(target_os = "linux", feature = "socket_can")))]
+            {
+                Err(not_enabled(uri.scheme))
+            }
+        }
+        "pcan" => {
+            uri.check_params(&["bitrate"])?;
+            #[cfg(feature = "pcan")]
+            {
+                let bitrate = uri.bitrate()?;
+                let sender = crate::pcan::Sender::connect_async(uri.target, bitrate).await?;
+                let receiver = crate::pcan::Receiver::connect_async(uri.target, bitrate).await?;
+                Ok((Box::new(sender), Box::new(receiver)))
+            }
+            #[cfg(not(feature = "pcan"))]
+            {
+                Err(not_enabled(uri.scheme))
+            }
+        }
+        "usr-canet" => {
+            uri.check_params(&[])?;
+            #[cfg(feature = "usr_canet")]
+            {
+                let (sender, receiver) = crate::usr_canet::connect(uri.target).await?;
+                Ok((Box::new(sender), Box::new(receiver)))
+            }
+            #[cfg(not(feature = "usr_canet"))]
+            {
+                Err(not_enabled(uri.scheme))
+            }
+        }
+        "slcan" => {
+            uri.check_params(&["bitrate"])?;
+            #[cfg(feature = "slcan")]
+            {
+                let (sender, receiver) = crate::slcan::connect(uri.target, uri.bitrate()?).await?;
+                Ok((Box::new(sender), Box::new(receiver)))
+            }
+            #[cfg(not(feature = "slcan"))]
+            {
+                Err(not_enabled(uri.scheme))
+            }
+        }
+        "socketcand" => {
+            uri.check_params(&[])?;
+            #[cfg(feature = "socketcand")]
+            {
+                let (addr, bus) = uri
+                    .target
+                    .split_once('/')
+                    .ok_or(Error::InvalidInterfaceAddress)?;
+                let (sender, receiver) = crate::socketcand::connect(addr, bus).await?;
+                Ok((Box::new(sender), Box::new(receiver)))
+            }
+            #[cfg(not(feature = "socketcand"))]
+            {
+                Err(not_enabled(uri.scheme))
+            }
+        }
+        "loopback" => {
+            uri.check_params(&[])?;
+            let (sender, receiver) = crate::loopback::connect();
+            Ok((Box::new(sender), Box::new(receiver)))
+        }
+        scheme => Err(Error::Other(format!("Unknown URI scheme `{}`", scheme))),
+    }
+}
+
+/// Connect to `uri`, send `msg` and shut down the connection again, e.g. for command line tools.
+///
+/// This is inefficient for repeated use, since the backend is connected for every message. Use [`connect()`]
+/// instead to send more than a single message.
+pub async fn send_once(uri: &str, msg: Message) -> Result<()> {
+    let (mut sender, mut receiver) = connect(uri).await?;
+    let result = sender.send(msg).await;
+    let shutdown = sender.shutdown().await.and(receiver.shutdown().await);
+    result?;
+    shutdown
+}
+
+/// Connect to `uri`, receive a single message and shut down the connection again, e.g. for command line tools.
+///
+/// Returns `Ok(None)` if no message was received within `timeout`. This is inefficient for repeated use, since
+/// the backend is connected for every message. Use [`connect()`] instead to receive more than a single message.
+pub async fn recv_once(uri: &str, timeout: Duration) -> Result<Option<Message>> {
+    let (mut sender, mut receiver) = connect(uri).await?;
+    let result = receiver.recv_timeout(timeout).await;
+    let shutdown = receiver.shutdown().await.and(sender.shutdown().await);
+    let msg = result?;
+    shutdown?;
+    Ok(msg)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Message;
+
+    #[test]
+    fn parse() {
+        let uri = Uri::parse("pcan://usb1?bitrate=500000").unwrap();
+        assert_eq!(uri.scheme, "pcan");
+        assert_eq!(uri.target, "usb1");
+        assert_eq!(uri.bitrate().unwrap(), 500000);
+        assert!(uri.check_params(&["bitrate"]).is_ok());
+        assert!(uri.check_params(&[]).is_err());
+
+        let uri = Uri::parse("slcan:///dev/ttyACM0").unwrap();
+        assert_eq!(uri.target, "/dev/ttyACM0");
+        assert!(matches!(uri.bitrate(), Err(Error::InvalidBitRate)));
+        assert!(Uri::parse("can0").is_err());
+    }
+
+    #[tokio::test]
+    async fn connect_loopback() {
+        let (mut tx, mut rx) = connect("loopback://").await.unwrap();
+        let msg = Message::new_data(0x123, false, &[1, 2]).unwrap();
+        tx.send(msg.clone()).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap(), msg);
+
+        assert!(connect("foo://bar").await.is_err());
+        assert!(connect("loopback://?bitrate=500000").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn once() {
+        let msg = Message::new_data(0x123, false, &[1, 2]).unwrap();
+        send_once("loopback://", msg.clone()).await.unwrap();
+        assert!(send_once("foo://bar", msg).await.is_err());
+
+        let timeout = Duration::from_millis(10);
+        assert_eq!(recv_once("loopback://", timeout).await.unwrap(), None);
+        assert!(recv_once("foo://bar", timeout).await.is_err());
+    }
+}
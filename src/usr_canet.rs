@@ -2,77 +2,347 @@
 //!
 //! The manual describing the protocol is [here](https://www.pusr.com/products/can-to-ethernet-converters-usr-canet200.html).
 //! It's a very simple protocol for framing CAN messages on TCP without support for CAN-FD.
+//!
+//! The framing is implemented by [`CanCodec`], which can be used with [`tokio_util::codec::Framed`] to run the
+//! protocol over any other byte stream, such as a serial port or a pipe.
 
-use crate::Message;
+use crate::reconnect::Backoff;
+use crate::{trace, CanFrameError, Message, CAN_MAX_DLC};
 use async_trait::async_trait;
 use byteorder::{BigEndian, ByteOrder};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use bytes::{Buf, BytesMut};
+use futures::{SinkExt, StreamExt};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::ToSocketAddrs;
 use tokio::net::{
     tcp::{OwnedReadHalf, OwnedWriteHalf},
     TcpStream,
 };
+use tokio::sync::Mutex;
+use tokio_util::codec::{Decoder, Encoder, FramedRead, FramedWrite};
+
+/// Length of a frame on the wire.
+const FRAME_LEN: usize = 13;
+
+/// Encodes and decodes [`Message`]s in the 13-byte USR-CANET framing.
+///
+/// Frames which cannot be decoded into a [`Message`] are skipped and counted. Encoding a [`Message::Fd`]
+/// fails with [`crate::Error::NotSupported`].
+#[derive(Debug, Clone, Default)]
+pub struct CanCodec {
+    dropped_malformed: u64,
+}
+
+impl CanCodec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of frames which were skipped because they could not be decoded
+    /// into a [`crate::Message`].
+    pub fn dropped_malformed_count(&self) -> u64 {
+        self.dropped_malformed
+    }
+}
+
+impl Encoder<Message> for CanCodec {
+    type Error = crate::Error;
+
+    fn encode(&mut self, msg: Message, dst: &mut BytesMut) -> crate::Result<()> {
+        let mut buf = [0_u8; FRAME_LEN];
+        buf[0] = if msg.ext_id() { 0x80_u8 } else { 0x00 };
+        buf[0] |= msg.dlc() & 0xF;
+        BigEndian::write_u32(&mut buf[1..], msg.id());
+        match msg {
+            Message::Data(msg) => {
+                buf[5..5 + msg.dlc() as usize].copy_from_slice(msg.data());
+            }
+            Message::Remote(_) => {
+                buf[0] |= 0x40;
+            }
+            Message::Fd(_) => {
+                return Err(crate::Error::NotSupported(
+                    "USR-CANET does not support CAN-FD".to_string(),
+                ));
+            }
+        }
+        dst.extend_from_slice(&buf);
+        Ok(())
+    }
+}
+
+impl Decoder for CanCodec {
+    type Item = Message;
+    type Error = crate::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> crate::Result<Option<Message>> {
+        while src.len() >= FRAME_LEN {
+            let mut buf = [0_u8; FRAME_LEN];
+            src.copy_to_slice(&mut buf);
+            match decode(&buf) {
+                Ok(msg) => return Ok(Some(msg)),
+                Err(err) => {
+                    self.dropped_malformed += 1;
+                    log::warn!(
+                        "Dropping malformed USR-CANET frame ({:?}): {:02x?}",
+                        err,
+                        buf
+                    );
+                }
+            }
+        }
+        src.reserve(FRAME_LEN - src.len());
+        Ok(None)
+    }
+}
+
+/// Options for [`connect_with_options()`].
+#[derive(Debug, Clone, Default)]
+pub struct ConnectOptions {
+    /// Idle time after which TCP keepalive probes are sent, or `None` to disable TCP keepalive
+    pub keepalive: Option<Duration>,
+    /// Backoff policy to re-dial the device once the connection was lost, or `None` to fail instead
+    pub reconnect: Option<Backoff>,
+}
+
+/// The connection shared by a [`Sender`] and [`Receiver`].
+///
+/// Once the connection is re-established, the halves of the new TCP stream are placed in `pending` and
+/// `generation` is incremented. The sender and receiver pick up their half the next time they are used.
+struct Link {
+    addrs: Vec<SocketAddr>,
+    options: ConnectOptions,
+    generation: AtomicU64,
+    connected: AtomicBool,
+    pending: Mutex<(Option<OwnedReadHalf>, Option<OwnedWriteHalf>)>,
+}
+
+impl Link {
+    async fn dial(&self) -> io::Result<TcpStream> {
+        let stream = TcpStream::connect(&self.addrs[..]).await?;
+        stream.set_nodelay(true)?;
+        if let Some(time) = self.options.keepalive {
+            let keepalive = socket2::TcpKeepalive::new().with_time(time);
+            socket2::SockRef::from(&stream).set_tcp_keepalive(&keepalive)?;
+        }
+        Ok(stream)
+    }
+
+    /// Re-dial the device unless the connection was already re-established since `generation`.
+    async fn reconnect(&self, generation: u64, err: crate::Error) -> crate::Result<()> {
+        self.connected.store(false, Ordering::Relaxed);
+        let backoff = match &self.options.reconnect {
+            Some(backoff) => backoff,
+            None => return Err(err),
+        };
+        let mut pending = self.pending.lock().await;
+        if self.generation.load(Ordering::Acquire) != generation {
+            return Ok(());
+        }
+        log::info!("Connection to USR-CANET lost, reconnecting: {}", err);
+        let stream = backoff.retry(|| async { Ok(self.dial().await?) }).await?;
+        let (read, write) = stream.into_split();
+        *pending = (Some(read), Some(write));
+        self.generation.fetch_add(1, Ordering::Release);
+        self.connected.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+}
 
 /// A sender for the USR-CANET200 device. Implements [`crate::Sender`].
 ///
-/// Contains the write half of the TCP stream.
+/// Contains the write half of the TCP stream. Only classic CAN frames with a DLC of at most 8 can be sent,
+/// sending a [`Message::Fd`] fails with [`crate::Error::NotSupported`].
 pub struct Sender {
-    stream: OwnedWriteHalf,
+    stream: Option<FramedWrite<OwnedWriteHalf, CanCodec>>,
+    generation: u64,
+    link: Arc<Link>,
+    shut_down: bool,
 }
 
 /// A receiver for the USR-CANET200 device. Implements [`crate::Receiver`].
 ///
 /// Contains the read half of the TCP stream.
 pub struct Receiver {
-    stream: OwnedReadHalf,
+    stream: Option<FramedRead<OwnedReadHalf, CanCodec>>,
+    generation: u64,
+    link: Arc<Link>,
+    /// Malformed frames dropped on previous connections
+    dropped_malformed: u64,
+    shut_down: bool,
 }
 
 /// Construct a sender and receiver by connecting a TCP stream to the given device.
 pub async fn connect<A: ToSocketAddrs>(addr: A) -> crate::Result<(Sender, Receiver)> {
-    let stream = TcpStream::connect(addr).await?;
-    stream.set_nodelay(true)?;
-    let (read, write) = stream.into_split();
-    let sender = Sender { stream: write };
-    let receiver = Receiver { stream: read };
+    connect_with_options(addr, ConnectOptions::default()).await
+}
+
+/// Construct a sender and receiver by connecting a TCP stream to the given device, optionally enabling
+/// TCP keepalive and reconnecting.
+///
+/// If reconnecting is enabled, the sender and receiver re-dial the device with the given backoff policy
+/// once reading or writing fails, instead of returning the error. A message which failed to send is sent
+/// again on the new connection. Messages received by the device in the meantime are lost.
+pub async fn connect_with_options<A: ToSocketAddrs>(
+    addr: A,
+    options: ConnectOptions,
+) -> crate::Result<(Sender, Receiver)> {
+    let addrs: Vec<_> = tokio::net::lookup_host(addr).await?.collect();
+    let link = Link {
+        addrs,
+        options,
+        generation: AtomicU64::new(0),
+        connected: AtomicBool::new(true),
+        pending: Mutex::new((None, None)),
+    };
+    let (read, write) = link.dial().await?.into_split();
+    let link = Arc::new(link);
+    let sender = Sender {
+        stream: Some(FramedWrite::new(write, CanCodec::new())),
+        generation: 0,
+        link: link.clone(),
+        shut_down: false,
+    };
+    let receiver = Receiver {
+        stream: Some(FramedRead::new(read, CanCodec::new())),
+        generation: 0,
+        link,
+        dropped_malformed: 0,
+        shut_down: false,
+    };
     Ok((sender, receiver))
 }
 
+impl Sender {
+    /// Returns false if the connection to the device was lost and not re-established yet.
+    pub fn is_connected(&self) -> bool {
+        self.link.connected.load(Ordering::Relaxed)
+    }
+
+    /// Switch to the write half of the most recent connection, if the connection was re-established.
+    async fn update_stream(&mut self) {
+        let generation = self.link.generation.load(Ordering::Acquire);
+        if generation != self.generation {
+            let write = self.link.pending.lock().await.1.take();
+            self.stream = write.map(|x| FramedWrite::new(x, CanCodec::new()));
+            self.generation = generation;
+        }
+    }
+}
+
 #[async_trait]
 impl crate::Sender for Sender {
     async fn send(&mut self, msg: Message) -> crate::Result<()> {
-        let mut buf = [0_u8; 13];
-        buf[0] = if msg.ext_id() { 0x80_u8 } else { 0x00 };
-        buf[0] |= msg.dlc() & 0xF;
-        BigEndian::write_u32(&mut buf[1..], msg.id());
-        match msg {
-            Message::Data(msg) => {
-                buf[5..5 + msg.dlc() as usize].copy_from_slice(msg.data());
-            }
-            Message::Remote(msg) => {
-                buf[0] |= 0x40;
-                BigEndian::write_u32(&mut buf[1..], msg.id());
+        if let Message::Fd(_) = msg {
+            return Err(crate::Error::NotSupported(
+                "USR-CANET does not support CAN-FD".to_string(),
+            ));
+        }
+        if self.shut_down {
+            return Err(crate::Error::Disconnected);
+        }
+        trace::send("usr_canet", &msg);
+        loop {
+            self.update_stream().await;
+            let result = match &mut self.stream {
+                Some(stream) => stream.send(msg.clone()).await,
+                None => Err(io::Error::from(io::ErrorKind::NotConnected).into()),
+            };
+            match result {
+                Ok(()) => return Ok(()),
+                Err(err) => self.link.reconnect(self.generation, err).await?,
             }
         }
-        self.stream.write_all(&buf).await?;
-        Ok(())
+    }
+
+    /// Flush the TCP stream and shut down its write half, which sends a FIN to the device.
+    async fn shutdown(&mut self) -> crate::Result<()> {
+        self.shut_down = true;
+        match self.stream.take() {
+            Some(mut stream) => stream.close().await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Receiver {
+    /// Returns false if the connection to the device was lost and not re-established yet.
+    pub fn is_connected(&self) -> bool {
+        self.link.connected.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of received frames which were dropped because they could not be decoded
+    /// into a [`crate::Message`].
+    pub fn dropped_malformed_count(&self) -> u64 {
+        let current = self
+            .stream
+            .as_ref()
+            .map(|x| x.decoder().dropped_malformed_count());
+        self.dropped_malformed + current.unwrap_or(0)
+    }
+
+    /// Switch to the read half of the most recent connection, if the connection was re-established.
+    async fn update_stream(&mut self) {
+        let generation = self.link.generation.load(Ordering::Acquire);
+        if generation != self.generation {
+            self.dropped_malformed = self.dropped_malformed_count();
+            let read = self.link.pending.lock().await.0.take();
+            self.stream = read.map(|x| FramedRead::new(x, CanCodec::new()));
+            self.generation = generation;
+        }
+    }
+}
+
+fn decode(buf: &[u8; FRAME_LEN]) -> Result<Message, CanFrameError> {
+    let ext_id = (buf[0] & 0x80) != 0;
+    let id = BigEndian::read_u32(&buf[1..]);
+    let dlc = buf[0] & 0xF;
+    if dlc as usize > CAN_MAX_DLC {
+        return Err(CanFrameError::DataTooLong);
+    }
+    if (buf[0] & 0x40) != 0 {
+        Message::new_remote(id, ext_id, dlc)
+    } else {
+        Message::new_data(id, ext_id, &buf[5..5 + (dlc as usize)])
     }
 }
 
 #[async_trait]
 impl crate::Receiver for Receiver {
     async fn recv(&mut self) -> crate::Result<Message> {
-        let mut buf = [0_u8; 13];
-        self.stream.read_exact(&mut buf).await?;
-
-        let ext_id = (buf[0] & 0x80) != 0;
-        let id = BigEndian::read_u32(&buf[1..]);
-        let dlc = buf[0] & 0xF;
-        let ret = if (buf[0] & 0x40) != 0 {
-            Message::new_remote(id, ext_id, dlc)?
-        } else {
-            Message::new_data(id, ext_id, &buf[5..5 + (dlc as usize)])?
-        };
-        Ok(ret)
+        if self.shut_down {
+            return Err(crate::Error::Disconnected);
+        }
+        loop {
+            self.update_stream().await;
+            let result = match &mut self.stream {
+                Some(stream) => stream
+                    .next()
+                    .await
+                    .unwrap_or_else(|| Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())),
+                None => Err(io::Error::from(io::ErrorKind::NotConnected).into()),
+            };
+            match result {
+                Ok(msg) => {
+                    trace::recv("usr_canet", &msg);
+                    return Ok(msg);
+                }
+                Err(err) => self.link.reconnect(self.generation, err).await?,
+            }
+        }
+    }
+
+    /// Drop the read half of the TCP stream. The connection is closed once the [`Sender`] is shut down
+    /// or dropped as well.
+    async fn shutdown(&mut self) -> crate::Result<()> {
+        self.dropped_malformed = self.dropped_malformed_count();
+        self.shut_down = true;
+        self.stream = None;
+        Ok(())
     }
 }
 
@@ -109,4 +379,118 @@ mod test {
         let rx_msg = rx.recv().await.unwrap();
         assert_eq!(tx_msg, rx_msg);
     }
+
+    #[tokio::test]
+    async fn codec_over_duplex() {
+        use futures::{SinkExt, StreamExt};
+        use tokio_util::codec::Framed;
+
+        let (a, b) = tokio::io::duplex(64);
+        let mut a = Framed::new(a, super::CanCodec::new());
+        let mut b = Framed::new(b, super::CanCodec::new());
+        let msgs = [
+            Message::new_data(0x123, false, &[1, 2, 3]).unwrap(),
+            Message::new_remote(0x1ABCDEF, true, 8).unwrap(),
+        ];
+        for msg in msgs {
+            a.send(msg.clone()).await.unwrap();
+            assert_eq!(b.next().await.unwrap().unwrap(), msg);
+        }
+        let msg = Message::new_fd(0x123, false, false, &[]).unwrap();
+        assert!(a.send(msg).await.is_err());
+        drop(a);
+        assert!(b.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn shutdown() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = task::spawn(async move {
+            let (mut connection, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            connection.read_to_end(&mut received).await.unwrap();
+            received
+        });
+        let (mut tx, mut rx) = super::connect(addr).await.unwrap();
+        tx.send(Message::new_data(0x123, false, &[1]).unwrap())
+            .await
+            .unwrap();
+        tx.shutdown().await.unwrap();
+        rx.shutdown().await.unwrap();
+        assert_eq!(server.await.unwrap().len(), super::FRAME_LEN);
+        let msg = Message::new_data(0x123, false, &[]).unwrap();
+        assert!(tx.send(msg).await.unwrap_err().is_disconnected());
+        assert!(rx.recv().await.unwrap_err().is_disconnected());
+    }
+
+    #[tokio::test]
+    async fn reject_fd() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        task::spawn(async move {
+            let _ = listener.accept().await.unwrap();
+        });
+        let (mut tx, _rx) = super::connect(addr).await.unwrap();
+        let msg = Message::new_fd(0x123, false, true, &[0; 12]).unwrap();
+        assert!(matches!(
+            tx.send(msg).await,
+            Err(crate::Error::NotSupported(x)) if x == "USR-CANET does not support CAN-FD"
+        ));
+    }
+
+    #[tokio::test]
+    async fn drop_malformed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        task::spawn(async move {
+            let (mut connection, _) = listener.accept().await.unwrap();
+            // dlc of 9 is invalid, followed by a valid frame with ID 0x123
+            let mut frames = [0_u8; 26];
+            frames[0] = 0x09;
+            frames[13] = 0x01;
+            frames[16..18].copy_from_slice(&[0x01, 0x23]);
+            frames[18] = 0xAB;
+            connection.write_all(&frames).await.unwrap();
+        });
+        let (_tx, mut rx) = super::connect(addr).await.unwrap();
+        let msg = rx.recv().await.unwrap();
+        assert_eq!(msg, Message::new_data(0x123, false, &[0xAB]).unwrap());
+        assert_eq!(rx.dropped_malformed_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = task::spawn(async move {
+            // the first connection is closed immediately
+            drop(listener.accept().await.unwrap());
+            let (mut connection, _) = listener.accept().await.unwrap();
+            let mut frame = [0_u8; 13];
+            frame[0] = 0x01;
+            frame[3..5].copy_from_slice(&[0x01, 0x23]);
+            frame[5] = 0xAB;
+            connection.write_all(&frame).await.unwrap();
+            connection.read_exact(&mut frame).await.unwrap();
+            frame
+        });
+        let options = super::ConnectOptions {
+            keepalive: Some(std::time::Duration::from_secs(10)),
+            reconnect: Some(crate::reconnect::Backoff {
+                initial: std::time::Duration::from_millis(1),
+                ..Default::default()
+            }),
+        };
+        let (mut tx, mut rx) = super::connect_with_options(addr, options).await.unwrap();
+        let msg = Message::new_data(0x123, false, &[0xAB]).unwrap();
+        assert_eq!(rx.recv().await.unwrap(), msg);
+        assert!(rx.is_connected());
+        // the sender switches to the new connection as well
+        tx.send(Message::new_data(0x456, false, &[]).unwrap())
+            .await
+            .unwrap();
+        assert!(tx.is_connected());
+        assert_eq!(server.await.unwrap()[3..5], [0x04, 0x56]);
+    }
 }